@@ -9,6 +9,13 @@ execute() {
     fi
 }
 
+# Passphrase used to encrypt the generated key files
+if [ -z "$SCW_PASSPHRASE" ]; then
+    echo "Error: set SCW_PASSPHRASE to the passphrase to encrypt the generated key files with"
+    exit 1
+fi
+export SCW_PASSPHRASE
+
 # Build the project
 execute cargo build --release
 
@@ -30,14 +37,14 @@ echo "My Wallet Public Key: $MY_WALLET_PUBKEY"
 echo "Recipient Wallet Public Key: $RECIPIENT_WALLET_PUBKEY"
 
 # Fund Wallet
-execute ./target/release/scw fund -w $MY_WALLET_PUBKEY -a 2
+execute ./target/release/scw fund -w $MY_WALLET_PUBKEY --amount 2
 
 # Check Balance
 execute ./target/release/scw balance -w $MY_WALLET_PUBKEY
 execute ./target/release/scw balance -w $RECIPIENT_WALLET_PUBKEY
 
 # Transfer SOL
-execute ./target/release/scw send -f keypair.json -t $RECIPIENT_WALLET_PUBKEY -a 0.1
+execute ./target/release/scw send -f keypair.json -t $RECIPIENT_WALLET_PUBKEY --amount 0.1
 
 # Check Balance Again
 execute ./target/release/scw balance -w $MY_WALLET_PUBKEY
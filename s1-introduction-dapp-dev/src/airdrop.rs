@@ -0,0 +1,556 @@
+use serde_json::{ json, Value };
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{
+    instruction::Instruction,
+    pubkey::Pubkey,
+    signature::{ Keypair, Signature },
+    signer::Signer,
+    transaction::TransactionError,
+};
+use std::{
+    collections::{ BTreeMap, HashMap, HashSet },
+    error::Error,
+    fs,
+    path::Path,
+    str::FromStr,
+    sync::{ atomic::{ AtomicUsize, Ordering }, Mutex },
+    thread,
+};
+
+use crate::{
+    amount::SOL_DECIMALS,
+    batch::{ may_still_land, save_ledger, LAMPORTS_PER_SIGNATURE, MAX_STATUS_QUERY },
+    cnft::*,
+    output::{ emit, status },
+    state::*,
+    token::format_token_amount,
+    transaction::{ build_transaction, compute_unit_price, max_priority_fee, submit_transaction },
+};
+
+/// Recipients of an airdrop file, each listed once, in file order.
+#[derive(Debug, PartialEq)]
+pub struct Recipients {
+    /// Line and address of each first occurrence.
+    pub addresses: Vec<(usize, Pubkey)>,
+    /// Line and address of each repeat, which is skipped.
+    pub duplicates: Vec<(usize, Pubkey)>,
+}
+
+/// Parses one address per line; anything after a comma is ignored, so `batch-send` files work
+/// too. Blank lines, `#` comments and a `recipient` header are skipped, and every invalid
+/// address is reported, not just the first.
+pub fn parse_recipients(contents: &str) -> Result<Recipients, Vec<String>> {
+    let mut recipients = Recipients { addresses: Vec::new(), duplicates: Vec::new() };
+    let mut seen = HashSet::new();
+    let mut errors = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let field = line.split(',').next().unwrap_or_default().trim();
+        if index == 0 && field.eq_ignore_ascii_case("recipient") {
+            continue;
+        }
+        match Pubkey::from_str(field) {
+            Ok(address) if seen.insert(address) => recipients.addresses.push((index + 1, address)),
+            Ok(address) => recipients.duplicates.push((index + 1, address)),
+            Err(_) => errors.push(format!("line {}: invalid address '{}'", index + 1, field)),
+        }
+    }
+    if errors.is_empty() { Ok(recipients) } else { Err(errors) }
+}
+
+/// Shallowest tree the compression program accepts that holds `leaves` leaves.
+pub fn depth_for(leaves: u64) -> Option<u32> {
+    DEPTH_SIZE_PAIRS
+        .iter()
+        .map(|(depth, _)| *depth)
+        .find(|depth| 1u64 << depth >= leaves)
+}
+
+fn report_errors(errors: Vec<String>) -> Box<dyn Error> {
+    format!("{} invalid addresses; nothing was minted\n{}", errors.len(), errors.join("\n")).into()
+}
+
+pub fn cnft_airdrop(
+    rpc_client: &RpcClient,
+    options: &TransactionArgs,
+    payer: &Keypair,
+    tree_file: &str,
+    cmd: &CnftAirdropCommand
+) -> Result<(), Box<dyn Error>> {
+    let recipients = parse_recipients(&fs::read_to_string(&cmd.file)?).map_err(report_errors)?;
+    if recipients.addresses.is_empty() {
+        return Err(format!("No recipients found in {}", cmd.file).into());
+    }
+    for (line, address) in &recipients.duplicates {
+        status(&format!("line {}: {} is listed again; it gets one NFT", line, address));
+    }
+    for (line, address) in &recipients.addresses {
+        if !address.is_on_curve() {
+            status(&format!("line {}: {} is a program address, not a wallet", line, address));
+        }
+    }
+    let metadata = metadata_args(&cmd.metadata, &payer.pubkey())?;
+    let merkle_tree = Pubkey::from_str(&read_tree_file(tree_file)?.public_key)?;
+    let (header, config) = fetch_tree(rpc_client, &merkle_tree)?;
+
+    let state_path = cmd.state.clone().unwrap_or_else(|| format!("{}.airdrop.json", cmd.file));
+    let mut state = load_state(&state_path, &merkle_tree)?;
+    reconcile_state(rpc_client, &mut state)?;
+    save_ledger(&state_path, &state)?;
+
+    let is_minted = |address: &Pubkey| {
+        state.recipients
+            .get(&address.to_string())
+            .is_some_and(|entry| entry.status == MintStatus::Minted)
+    };
+    let already_minted = recipients.addresses
+        .iter()
+        .filter(|(_, address)| is_minted(address))
+        .count();
+    let remaining: Vec<Pubkey> = recipients.addresses
+        .iter()
+        .map(|(_, address)| *address)
+        .filter(|address| !is_minted(address))
+        .collect();
+    let room = config.total_mint_capacity.saturating_sub(config.num_minted);
+    let needed = remaining.len() as u64;
+    // Every mint is its own transaction, with the nonce advance when one is used.
+    let priority_fee = match remaining.first() {
+        Some(recipient) => {
+            let payer_key = payer.pubkey();
+            let instruction = mint_v1_instruction(
+                &merkle_tree,
+                recipient,
+                recipient,
+                &payer_key,
+                &payer_key,
+                &metadata
+            )?;
+            let price = compute_unit_price(rpc_client, options, &[instruction])?;
+            max_priority_fee(options, price, 1 + usize::from(options.nonce.is_some()))
+        }
+        None => 0,
+    };
+    let fees = needed * (LAMPORTS_PER_SIGNATURE + priority_fee);
+    let duplicates: Vec<String> = recipients.duplicates
+        .iter()
+        .map(|(_, address)| address.to_string())
+        .collect();
+
+    let plan = format!(
+        "{} of {} recipients to mint ({} already minted, {} duplicates skipped); \
+         the tree has room for {} more leaves",
+        remaining.len(),
+        recipients.addresses.len(),
+        already_minted,
+        duplicates.len(),
+        room
+    );
+    // A dry run reports capacity as `fits`, but the signer must be allowed to mint at all.
+    check_can_mint(&config, &payer.pubkey(), 0)?;
+    if cmd.dry_run {
+        emit(
+            json!({
+                "file": cmd.file,
+                "dry_run": true,
+                "tree": merkle_tree.to_string(),
+                "recipients": recipients.addresses.len(),
+                "duplicates": duplicates,
+                "already_minted": already_minted,
+                "to_mint": remaining.len(),
+                "capacity_remaining": room,
+                "fits": needed <= room,
+                "fees": fees,
+            }),
+            || {
+                println!("{}", plan);
+                println!("Fees: {} SOL", format_token_amount(fees, SOL_DECIMALS));
+            }
+        );
+        return Ok(());
+    }
+    if remaining.is_empty() {
+        emit(
+            json!({
+                "file": cmd.file,
+                "state": state_path,
+                "tree": merkle_tree.to_string(),
+                "recipients": recipients.addresses.len(),
+                "minted": recipients.addresses.len(),
+                "already_minted": already_minted,
+                "failed": 0,
+                "mints": [],
+            }),
+            || {
+                println!(
+                    "All {} recipients in {} already have their NFT",
+                    recipients.addresses.len(),
+                    cmd.file
+                )
+            }
+        );
+        return Ok(());
+    }
+    if needed > room {
+        let hint = match depth_for(needed) {
+            Some(depth) => format!("; a new tree of depth {} holds them all", depth),
+            None => String::new(),
+        };
+        return Err(
+            format!(
+                "The tree has room for {} more leaves, but {} recipients are left{}",
+                room,
+                needed,
+                hint
+            ).into()
+        );
+    }
+    check_can_mint(&config, &payer.pubkey(), needed)?;
+    let balance = rpc_client.get_balance(&payer.pubkey())?;
+    if balance < fees {
+        return Err(
+            format!(
+                "Insufficient SOL for fees: {} needed, {} available",
+                format_token_amount(fees, SOL_DECIMALS),
+                format_token_amount(balance, SOL_DECIMALS)
+            ).into()
+        );
+    }
+    status(&plan);
+
+    // Each mint advances a durable nonce, so they cannot share one concurrently. Without one,
+    // mints beyond the tree's buffer size in the same slot would be rejected.
+    let workers = if options.nonce.is_some() {
+        1
+    } else {
+        cmd.concurrency.clamp(1, header.max_buffer_size as usize)
+    };
+    let state = Mutex::new(state);
+    let results: Mutex<Vec<(usize, Value)>> = Mutex::new(Vec::with_capacity(remaining.len()));
+    let next = AtomicUsize::new(0);
+    thread::scope(|scope| {
+        for _ in 0..workers.min(remaining.len()) {
+            scope.spawn(|| {
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(recipient) = remaining.get(index) else {
+                        break;
+                    };
+                    let payer_key = payer.pubkey();
+                    let result = mint_v1_instruction(
+                        &merkle_tree,
+                        recipient,
+                        recipient,
+                        &payer_key,
+                        &payer_key,
+                        &metadata
+                    )
+                        .map_err(|e| e.to_string())
+                        .and_then(|instruction| {
+                            mint_to(
+                                rpc_client,
+                                options,
+                                payer,
+                                instruction,
+                                recipient,
+                                &state,
+                                &state_path
+                            )
+                        });
+                    let mut summary = json!({ "recipient": recipient.to_string() });
+                    match result {
+                        Ok(entry) => {
+                            let leaf = entry.leaf_index.map_or("?".to_string(), |i| i.to_string());
+                            status(
+                                &format!(
+                                    "{}/{}: {} leaf {}, Signature {}",
+                                    index + 1,
+                                    remaining.len(),
+                                    recipient,
+                                    leaf,
+                                    entry.signature.as_deref().unwrap_or_default()
+                                )
+                            );
+                            summary["leaf_index"] = json!(entry.leaf_index);
+                            summary["asset_id"] = json!(entry.asset_id);
+                            summary["signature"] = json!(entry.signature);
+                        }
+                        Err(e) => {
+                            status(
+                                &format!(
+                                    "{}/{}: {} failed: {}",
+                                    index + 1,
+                                    remaining.len(),
+                                    recipient,
+                                    e
+                                )
+                            );
+                            summary["error"] = json!(e);
+                        }
+                    }
+                    results.lock().unwrap().push((index, summary));
+                }
+            });
+        }
+    });
+
+    let state = state.into_inner().unwrap();
+    let failed = remaining
+        .iter()
+        .filter(|address| {
+            !state.recipients
+                .get(&address.to_string())
+                .is_some_and(|entry| entry.status == MintStatus::Minted)
+        })
+        .count();
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(index, _)| *index);
+    let minted = recipients.addresses.len() - failed;
+    emit(
+        json!({
+            "file": cmd.file,
+            "state": state_path,
+            "tree": merkle_tree.to_string(),
+            "recipients": recipients.addresses.len(),
+            "minted": minted,
+            "already_minted": already_minted,
+            "failed": failed,
+            "mints": results.into_iter().map(|(_, result)| result).collect::<Vec<_>>(),
+        }),
+        || {
+            println!(
+                "Minted to {} of {} recipients; progress saved to {}",
+                minted,
+                recipients.addresses.len(),
+                state_path
+            );
+        }
+    );
+    if failed > 0 {
+        return Err(format!("{} mints failed; rerun the same command to retry them", failed).into());
+    }
+    Ok(())
+}
+
+/// Records the mint as pending under its signature before submitting it, so an interrupted
+/// run can find out whether it landed.
+fn mint_to(
+    rpc_client: &RpcClient,
+    options: &TransactionArgs,
+    payer: &Keypair,
+    instruction: Instruction,
+    recipient: &Pubkey,
+    state: &Mutex<AirdropState>,
+    state_path: &str
+) -> Result<AirdropEntry, String> {
+    let record = |entry: &AirdropEntry| -> Result<(), String> {
+        let mut state = state.lock().unwrap();
+        state.recipients.insert(recipient.to_string(), entry.clone());
+        save_ledger(state_path, &*state).map_err(|e| e.to_string())
+    };
+
+    let transaction = build_transaction(
+        rpc_client,
+        options,
+        &[instruction],
+        &payer.pubkey(),
+        &[payer],
+        None
+    ).map_err(|e| e.to_string())?;
+    let signature = transaction.signatures[0];
+    let mut entry = AirdropEntry {
+        status: MintStatus::Pending,
+        signature: Some(signature.to_string()),
+        blockhash: Some(transaction.message.recent_blockhash.to_string()),
+        nonce: options.nonce.clone(),
+        leaf_index: None,
+        asset_id: None,
+        error: None,
+    };
+    record(&entry)?;
+
+    match submit_transaction(rpc_client, options, &transaction) {
+        Ok(_) => {
+            entry.status = MintStatus::Minted;
+            read_leaf(rpc_client, &signature, &mut entry);
+            record(&entry)?;
+            Ok(entry)
+        }
+        Err(e) => {
+            entry.status = MintStatus::Failed;
+            entry.error = Some(e.to_string());
+            record(&entry)?;
+            Err(e.to_string())
+        }
+    }
+}
+
+/// Fills in the leaf index and asset id from the mint transaction's leaf event.
+fn read_leaf(rpc_client: &RpcClient, signature: &Signature, entry: &mut AirdropEntry) {
+    match minted_leaves(rpc_client, signature) {
+        Ok(leaves) => {
+            if let Some(leaf) = leaves.first() {
+                entry.leaf_index = Some(leaf.nonce);
+                entry.asset_id = Some(leaf.id.to_string());
+            }
+        }
+        Err(e) => status(&format!("Cannot read the leaf minted by {}: {}", signature, e)),
+    }
+}
+
+fn load_state(path: &str, merkle_tree: &Pubkey) -> Result<AirdropState, Box<dyn Error>> {
+    if !Path::new(path).exists() {
+        return Ok(AirdropState { tree: merkle_tree.to_string(), recipients: BTreeMap::new() });
+    }
+    let state: AirdropState = serde_json::from_str(&fs::read_to_string(path)?)?;
+    if state.tree != merkle_tree.to_string() {
+        return Err(
+            format!("Progress file {} is for tree {}, not {}", path, state.tree, merkle_tree).into()
+        );
+    }
+    Ok(state)
+}
+
+/// Settles mints left pending or failed by an earlier run, as `batch-send` does with its
+/// ledger, and looks up leaf indexes that could not be read at the time.
+fn reconcile_state(
+    rpc_client: &RpcClient,
+    state: &mut AirdropState
+) -> Result<(), Box<dyn Error>> {
+    let mut unsettled: Vec<Signature> = Vec::new();
+    for entry in state.recipients.values() {
+        if entry.status == MintStatus::Minted {
+            continue;
+        }
+        if let Some(signature) = &entry.signature {
+            unsettled.push(Signature::from_str(signature)?);
+        }
+    }
+    let mut statuses = HashMap::new();
+    for chunk in unsettled.chunks(MAX_STATUS_QUERY) {
+        let found = rpc_client.get_signature_statuses_with_history(chunk)?.value;
+        statuses.extend(chunk.iter().map(|signature| signature.to_string()).zip(found));
+    }
+
+    for (recipient, entry) in state.recipients.iter_mut() {
+        let Some(signature) = entry.signature.clone() else {
+            continue;
+        };
+        if entry.status != MintStatus::Minted {
+            let status = statuses
+                .get(&signature)
+                .cloned()
+                .flatten()
+                .map(|status| status.status);
+            let settled = settle_mint(recipient, entry, status, || {
+                may_still_land(rpc_client, entry.blockhash.as_deref(), entry.nonce.as_deref())
+            })?;
+            *entry = settled;
+        }
+        if entry.status == MintStatus::Minted && entry.leaf_index.is_none() {
+            read_leaf(rpc_client, &Signature::from_str(&signature)?, entry);
+        }
+    }
+    Ok(())
+}
+
+/// What became of a mint that was not known to have landed, from the status of its signature
+/// when the cluster has one. Without one, it is retried only once it can no longer land, and
+/// until then this is an error so that nobody receives two NFTs.
+fn settle_mint(
+    recipient: &str,
+    entry: &AirdropEntry,
+    status: Option<Result<(), TransactionError>>,
+    may_still_land: impl FnOnce() -> Result<bool, Box<dyn Error>>
+) -> Result<AirdropEntry, Box<dyn Error>> {
+    let mut entry = entry.clone();
+    match status {
+        Some(Ok(())) => {
+            entry.status = MintStatus::Minted;
+            entry.error = None;
+        }
+        Some(Err(err)) => {
+            entry.status = MintStatus::Failed;
+            entry.error = Some(err.to_string());
+        }
+        None => {
+            if may_still_land()? {
+                return Err(
+                    format!(
+                        "The mint to {} was submitted as {} and may still land; rerun in a minute",
+                        recipient,
+                        entry.signature.as_deref().unwrap_or_default()
+                    ).into()
+                );
+            }
+            entry.status = MintStatus::Failed;
+        }
+    }
+    Ok(entry)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::hash::Hash;
+
+    #[test]
+    fn recipients_are_validated_and_deduplicated() {
+        let first = Pubkey::new_unique();
+        let second = Pubkey::new_unique();
+        let contents = format!(
+            "recipient\n# comment\n{a}\n\n{b},1.5\n{a}\n  {b}  \n",
+            a = first,
+            b = second
+        );
+        let recipients = parse_recipients(&contents).unwrap();
+        assert_eq!(recipients.addresses, vec![(3, first), (5, second)]);
+        assert_eq!(recipients.duplicates, vec![(6, first), (7, second)]);
+
+        let errors = parse_recipients(&format!("{}\nnobody\n{}0\n", first, second)).unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].starts_with("line 2:"));
+    }
+
+    #[test]
+    fn suggests_the_shallowest_tree_that_fits() {
+        assert_eq!(depth_for(1), Some(3));
+        assert_eq!(depth_for(34), Some(6));
+        assert_eq!(depth_for(16_384), Some(14));
+        assert_eq!(depth_for(16_385), Some(15));
+        assert_eq!(depth_for(1 << 31), None);
+    }
+
+    #[test]
+    fn unsettled_mints_are_retried_only_once_they_cannot_land() {
+        let pending = AirdropEntry {
+            status: MintStatus::Pending,
+            signature: Some(Signature::new_unique().to_string()),
+            blockhash: Some(Hash::new_unique().to_string()),
+            nonce: None,
+            leaf_index: None,
+            asset_id: None,
+            error: None,
+        };
+        let unreachable = || -> Result<bool, Box<dyn Error>> { panic!("status is known") };
+
+        let minted = settle_mint("alice", &pending, Some(Ok(())), unreachable).unwrap();
+        assert_eq!(minted.status, MintStatus::Minted);
+
+        let error = TransactionError::AccountNotFound;
+        let failed = settle_mint("alice", &pending, Some(Err(error)), unreachable).unwrap();
+        assert_eq!(failed.status, MintStatus::Failed);
+        assert!(failed.error.is_some());
+        let retried = settle_mint("alice", &failed, Some(Ok(())), unreachable).unwrap();
+        assert_eq!(retried.status, MintStatus::Minted);
+        assert_eq!(retried.error, None);
+
+        let expired = settle_mint("alice", &pending, None, || Ok(false)).unwrap();
+        assert_eq!(expired.status, MintStatus::Failed);
+        let error = settle_mint("alice", &pending, None, || Ok(true)).unwrap_err();
+        assert!(error.to_string().contains("may still land"));
+    }
+}
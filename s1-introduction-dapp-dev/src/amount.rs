@@ -0,0 +1,67 @@
+use std::error::Error;
+
+use crate::token::parse_token_amount;
+
+pub const SOL_DECIMALS: u8 = 9;
+/// Accepted in place of an amount to move everything available.
+const ALL_KEYWORD: &str = "ALL";
+
+/// An amount given on the command line, in base units (lamports for SOL).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Amount {
+    Exact(u64),
+    /// Everything available; what that is depends on the command.
+    All,
+}
+
+impl Amount {
+    /// Parses a decimal with at most `decimals` places, or a whole number of base units when
+    /// `base_units` is set. `ALL` is accepted either way; zero is not.
+    pub fn parse(value: &str, decimals: u8, base_units: bool) -> Result<Self, Box<dyn Error>> {
+        let value = value.trim();
+        if value.eq_ignore_ascii_case(ALL_KEYWORD) {
+            return Ok(Amount::All);
+        }
+        let amount = if base_units {
+            parse_token_amount(value, 0).map_err(|_| {
+                format!("Invalid amount '{}': base units must be a whole number", value)
+            })?
+        } else {
+            parse_token_amount(value, decimals)?
+        };
+        if amount == 0 {
+            return Err("Amount must be greater than zero".into());
+        }
+        Ok(Amount::Exact(amount))
+    }
+
+    /// The exact amount, for commands where `ALL` has no meaning.
+    pub fn exact(self) -> Result<u64, Box<dyn Error>> {
+        match self {
+            Amount::Exact(amount) => Ok(amount),
+            Amount::All => Err(format!("{} is not accepted here", ALL_KEYWORD).into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_exact_amounts() {
+        let sol = |value: &str| Amount::parse(value, SOL_DECIMALS, false);
+        assert_eq!(sol("0.3").unwrap(), Amount::Exact(300_000_000));
+        assert_eq!(sol("1.000000001").unwrap(), Amount::Exact(1_000_000_001));
+        assert_eq!(sol("ALL").unwrap(), Amount::All);
+        assert_eq!(Amount::parse("5000", SOL_DECIMALS, true).unwrap(), Amount::Exact(5000));
+        assert_eq!(Amount::parse("all", SOL_DECIMALS, true).unwrap(), Amount::All);
+
+        assert!(sol("0.0000000001").is_err());
+        assert!(sol("0").is_err());
+        assert!(sol("1e9").is_err());
+        assert!(sol("-1").is_err());
+        assert!(Amount::parse("1.5", SOL_DECIMALS, true).is_err());
+        assert!(Amount::All.exact().is_err());
+    }
+}
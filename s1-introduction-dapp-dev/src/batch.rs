@@ -0,0 +1,784 @@
+use serde::Serialize;
+use serde_json::{ json, Value };
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{
+    commitment_config::CommitmentConfig,
+    compute_budget::ComputeBudgetInstruction,
+    hash::Hash,
+    instruction::Instruction,
+    message::Message,
+    packet::PACKET_DATA_SIZE,
+    pubkey::Pubkey,
+    signature::{ Keypair, Signature },
+    signer::Signer,
+    system_instruction,
+    transaction::Transaction,
+};
+use std::{
+    collections::{ BTreeMap, HashMap },
+    error::Error,
+    fs,
+    path::Path,
+    str::FromStr,
+    sync::{ atomic::{ AtomicUsize, Ordering }, Mutex },
+    thread,
+};
+
+use crate::{
+    amount::SOL_DECIMALS,
+    keystore::read_keys,
+    output::{ emit, status },
+    state::*,
+    token::{ format_token_amount, parse_token_amount, unpack_token_account, MintInfo },
+    transaction::{
+        build_transaction,
+        compute_unit_price,
+        max_priority_fee,
+        nonce_blockhash,
+        submit_transaction,
+    },
+    wallet::Wallet,
+};
+
+const SOL_ASSET: &str = "SOL";
+pub const LAMPORTS_PER_SIGNATURE: u64 = 5000;
+/// `getSignatureStatuses` accepts at most this many signatures.
+pub const MAX_STATUS_QUERY: usize = 256;
+/// `getMultipleAccounts` accepts at most this many accounts.
+const MAX_MULTIPLE_ACCOUNTS: usize = 100;
+
+/// A row of a payout file as written, before the amount is converted with the mint's decimals.
+#[derive(Debug)]
+pub struct PaymentRow {
+    pub line: usize,
+    pub recipient: Pubkey,
+    pub amount: String,
+    pub mint: Option<Pubkey>,
+}
+
+/// A validated payment. `key` identifies it in the ledger and does not depend on its position
+/// in the file, so reordering rows never pays anyone twice.
+#[derive(Debug)]
+pub struct Payment {
+    pub key: String,
+    pub line: usize,
+    pub recipient: Pubkey,
+    pub amount: u64,
+    pub mint: Option<Pubkey>,
+}
+
+struct Batch {
+    payments: Vec<usize>,
+    instructions: Vec<Instruction>,
+}
+
+pub fn batch_send(
+    rpc_client: &RpcClient,
+    options: &TransactionArgs,
+    wallet: &Wallet,
+    cmd: &BatchSendCommand
+) -> Result<(), Box<dyn Error>> {
+    let rows = parse_payment_rows(&fs::read_to_string(&cmd.file)?).map_err(report_errors)?;
+    if rows.is_empty() {
+        return Err(format!("No payments found in {}", cmd.file).into());
+    }
+
+    let mut mints: HashMap<Pubkey, MintInfo> = HashMap::new();
+    let mut errors = Vec::new();
+    for row in &rows {
+        let Some(mint) = row.mint else {
+            continue;
+        };
+        if mints.contains_key(&mint) {
+            continue;
+        }
+        match MintInfo::fetch(rpc_client, &mint) {
+            Ok(info) => {
+                mints.insert(mint, info);
+            }
+            Err(e) => errors.push(format!("line {}: mint {}: {}", row.line, mint, e)),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(report_errors(errors));
+    }
+    let decimals = mints
+        .iter()
+        .map(|(address, info)| (*address, info.decimals))
+        .collect();
+    let payments = resolve_payments(&rows, &decimals).map_err(report_errors)?;
+
+    let sender = read_keys(&wallet.resolve(cmd.from.as_deref())?)?;
+    let ledger_path = cmd.ledger.clone().unwrap_or_else(|| format!("{}.ledger.json", cmd.file));
+    let mut ledger = load_ledger(&ledger_path, &sender.pubkey())?;
+    reconcile_ledger(rpc_client, &mut ledger)?;
+    save_ledger(&ledger_path, &ledger)?;
+
+    let is_paid = |payment: &Payment| {
+        ledger.payments
+            .get(&payment.key)
+            .is_some_and(|entry| entry.status == PaymentStatus::Paid)
+    };
+    let already_paid = payments.iter().filter(|payment| is_paid(payment)).count();
+    let remaining: Vec<&Payment> = payments
+        .iter()
+        .filter(|payment| !is_paid(payment))
+        .collect();
+    if remaining.is_empty() {
+        emit(
+            json!({
+                "file": cmd.file,
+                "ledger": ledger_path,
+                "payments": payments.len(),
+                "paid": payments.len(),
+                "already_paid": already_paid,
+                "failed": 0,
+                "transactions": [],
+            }),
+            || println!("All {} payments in {} are already paid", payments.len(), cmd.file)
+        );
+        return Ok(());
+    }
+
+    let mut instructions = Vec::with_capacity(remaining.len());
+    for payment in &remaining {
+        instructions.push(payment_instructions(rpc_client, &sender.pubkey(), payment, &mints)?);
+    }
+    let overhead = overhead_instructions(options, &sender.pubkey())?;
+    let batches = pack_batches(&instructions, &overhead, &sender.pubkey())?;
+    check_balances(rpc_client, options, &sender.pubkey(), &remaining, &mints, &batches)?;
+
+    let plan = format!(
+        "{} payments to send in {} transactions ({} already paid)",
+        remaining.len(),
+        batches.len(),
+        already_paid
+    );
+    if cmd.dry_run {
+        let batches_json: Vec<Value> = batches
+            .iter()
+            .map(|batch| {
+                let payments: Vec<Value> = batch.payments
+                    .iter()
+                    .map(|&i| payment_json(remaining[i], &mints))
+                    .collect();
+                json!({ "payments": payments })
+            })
+            .collect();
+        emit(
+            json!({
+                "file": cmd.file,
+                "dry_run": true,
+                "already_paid": already_paid,
+                "batches": batches_json,
+            }),
+            || {
+                println!("{}", plan);
+                for (index, batch) in batches.iter().enumerate() {
+                    println!("Batch {}:", index + 1);
+                    for payment in batch.payments.iter().map(|&i| remaining[i]) {
+                        println!("    line {:<5} {}", payment.line, describe(payment, &mints));
+                    }
+                }
+            }
+        );
+        return Ok(());
+    }
+    status(&plan);
+
+    // Each transaction advances a durable nonce, so they cannot share one concurrently.
+    let workers = if options.nonce.is_some() { 1 } else { cmd.concurrency.max(1) };
+    let ledger = Mutex::new(ledger);
+    let results: Mutex<Vec<Value>> = Mutex::new(Vec::with_capacity(batches.len()));
+    let next = AtomicUsize::new(0);
+    thread::scope(|scope| {
+        for _ in 0..workers.min(batches.len()) {
+            scope.spawn(|| {
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(batch) = batches.get(index) else {
+                        break;
+                    };
+                    let keys: Vec<&str> = batch.payments
+                        .iter()
+                        .map(|&i| remaining[i].key.as_str())
+                        .collect();
+                    let result = send_batch(
+                        rpc_client,
+                        options,
+                        &sender,
+                        batch,
+                        &keys,
+                        &ledger,
+                        &ledger_path
+                    );
+                    let mut summary = json!({ "batch": index + 1, "payments": keys });
+                    match result {
+                        Ok(signature) => {
+                            status(
+                                &format!(
+                                    "Batch {}/{}: {} payments, Signature {}",
+                                    index + 1,
+                                    batches.len(),
+                                    keys.len(),
+                                    signature
+                                )
+                            );
+                            summary["signature"] = json!(signature.to_string());
+                        }
+                        Err(e) => {
+                            status(
+                                &format!("Batch {}/{} failed: {}", index + 1, batches.len(), e)
+                            );
+                            summary["error"] = json!(e.to_string());
+                        }
+                    }
+                    results.lock().unwrap().push(summary);
+                }
+            });
+        }
+    });
+
+    let ledger = ledger.into_inner().unwrap();
+    let failed = remaining
+        .iter()
+        .filter(|payment| {
+            !ledger.payments
+                .get(&payment.key)
+                .is_some_and(|entry| entry.status == PaymentStatus::Paid)
+        })
+        .count();
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|result| result["batch"].as_u64());
+    emit(
+        json!({
+            "file": cmd.file,
+            "ledger": ledger_path,
+            "payments": payments.len(),
+            "paid": payments.len() - failed,
+            "already_paid": already_paid,
+            "failed": failed,
+            "transactions": results,
+        }),
+        || {
+            println!(
+                "Paid {} of {} payments; ledger saved to {}",
+                payments.len() - failed,
+                payments.len(),
+                ledger_path
+            );
+        }
+    );
+    if failed > 0 {
+        return Err(
+            format!("{} payments failed; rerun the same command to retry them", failed).into()
+        );
+    }
+    Ok(())
+}
+
+/// Parses `recipient,amount[,mint]` rows. Blank lines, `#` comments and a `recipient,...`
+/// header are skipped; every malformed row is reported, not just the first.
+pub fn parse_payment_rows(contents: &str) -> Result<Vec<PaymentRow>, Vec<String>> {
+    let mut rows = Vec::new();
+    let mut errors = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        if index == 0 && fields[0].eq_ignore_ascii_case("recipient") {
+            continue;
+        }
+        match parse_row(&fields) {
+            Ok((recipient, amount, mint)) => {
+                rows.push(PaymentRow { line: index + 1, recipient, amount, mint });
+            }
+            Err(e) => errors.push(format!("line {}: {}", index + 1, e)),
+        }
+    }
+    if errors.is_empty() { Ok(rows) } else { Err(errors) }
+}
+
+fn parse_row(fields: &[&str]) -> Result<(Pubkey, String, Option<Pubkey>), String> {
+    if !(2..=3).contains(&fields.len()) {
+        return Err(format!("expected recipient,amount[,mint], found {} fields", fields.len()));
+    }
+    let recipient = Pubkey::from_str(fields[0]).map_err(|_|
+        format!("invalid recipient '{}'", fields[0])
+    )?;
+    let mint = match fields.get(2) {
+        None => None,
+        Some(mint) if mint.is_empty() || mint.eq_ignore_ascii_case(SOL_ASSET) => None,
+        Some(mint) => Some(Pubkey::from_str(mint).map_err(|_| format!("invalid mint '{}'", mint))?),
+    };
+    Ok((recipient, fields[1].to_string(), mint))
+}
+
+/// Converts amounts to base units and assigns ledger keys. Identical rows are numbered so
+/// that each of them is paid exactly once.
+pub fn resolve_payments(
+    rows: &[PaymentRow],
+    decimals: &HashMap<Pubkey, u8>
+) -> Result<Vec<Payment>, Vec<String>> {
+    let mut payments = Vec::with_capacity(rows.len());
+    let mut errors = Vec::new();
+    let mut occurrences: HashMap<String, usize> = HashMap::new();
+    for row in rows {
+        let row_decimals = row.mint.map_or(SOL_DECIMALS, |mint| decimals[&mint]);
+        match parse_token_amount(&row.amount, row_decimals) {
+            Ok(0) => errors.push(format!("line {}: amount must be positive", row.line)),
+            Ok(amount) => {
+                let asset = row.mint.map_or_else(|| SOL_ASSET.to_string(), |mint| mint.to_string());
+                let base = format!("{},{},{}", row.recipient, amount, asset);
+                let occurrence = occurrences.entry(base.clone()).or_insert(0);
+                *occurrence += 1;
+                payments.push(Payment {
+                    key: format!("{}#{}", base, occurrence),
+                    line: row.line,
+                    recipient: row.recipient,
+                    amount,
+                    mint: row.mint,
+                });
+            }
+            Err(e) => errors.push(format!("line {}: {}", row.line, e)),
+        }
+    }
+    if errors.is_empty() { Ok(payments) } else { Err(errors) }
+}
+
+fn report_errors(errors: Vec<String>) -> Box<dyn Error> {
+    format!("{} invalid rows; nothing was sent\n{}", errors.len(), errors.join("\n")).into()
+}
+
+fn payment_json(payment: &Payment, mints: &HashMap<Pubkey, MintInfo>) -> Value {
+    let (amount, asset) = match payment.mint.and_then(|mint| mints.get(&mint)) {
+        Some(mint) => (mint.format_amount(payment.amount), mint.address.to_string()),
+        None => (format_token_amount(payment.amount, SOL_DECIMALS), SOL_ASSET.to_string()),
+    };
+    json!({
+        "line": payment.line,
+        "recipient": payment.recipient.to_string(),
+        "amount": amount,
+        "base_units": payment.amount,
+        "asset": asset,
+    })
+}
+
+fn describe(payment: &Payment, mints: &HashMap<Pubkey, MintInfo>) -> String {
+    match payment.mint.and_then(|mint| mints.get(&mint)) {
+        Some(mint) => {
+            format!(
+                "{} {} to {}",
+                mint.format_amount(payment.amount),
+                mint.address,
+                payment.recipient
+            )
+        }
+        None => {
+            format!(
+                "{} SOL to {}",
+                format_token_amount(payment.amount, SOL_DECIMALS),
+                payment.recipient
+            )
+        }
+    }
+}
+
+fn payment_instructions(
+    rpc_client: &RpcClient,
+    sender: &Pubkey,
+    payment: &Payment,
+    mints: &HashMap<Pubkey, MintInfo>
+) -> Result<Vec<Instruction>, Box<dyn Error>> {
+    let Some(mint) = payment.mint.and_then(|mint| mints.get(&mint)) else {
+        return Ok(vec![system_instruction::transfer(sender, &payment.recipient, payment.amount)]);
+    };
+    let (transfer, _) = mint.transfer(
+        rpc_client,
+        &mint.associated_account(sender),
+        &mint.associated_account(&payment.recipient),
+        sender,
+        payment.amount
+    )?;
+    Ok(vec![mint.create_associated_account(sender, &payment.recipient), transfer])
+}
+
+/// Stand-ins for the nonce and compute budget instructions `build_transaction` may add, so
+/// packed batches still fit once they are added.
+fn overhead_instructions(
+    options: &TransactionArgs,
+    payer: &Pubkey
+) -> Result<Vec<Instruction>, Box<dyn Error>> {
+    let mut overhead = vec![
+        ComputeBudgetInstruction::set_compute_unit_limit(0),
+        ComputeBudgetInstruction::set_compute_unit_price(0),
+    ];
+    if options.nonce.is_some() {
+        let authority = match &options.nonce_authority_signer {
+            Some(authority) => authority.pubkey()?,
+            None => *payer,
+        };
+        overhead.push(system_instruction::advance_nonce_account(&Pubkey::default(), &authority));
+    }
+    Ok(overhead)
+}
+
+/// Greedily fills each transaction with as many payments as fit in one packet.
+fn pack_batches(
+    instructions: &[Vec<Instruction>],
+    overhead: &[Instruction],
+    payer: &Pubkey
+) -> Result<Vec<Batch>, Box<dyn Error>> {
+    let fits = |batch: &[Instruction]| {
+        let mut all = overhead.to_vec();
+        all.extend_from_slice(batch);
+        let transaction = Transaction::new_unsigned(Message::new(&all, Some(payer)));
+        bincode
+            ::serialized_size(&transaction)
+            .is_ok_and(|size| (size as usize) <= PACKET_DATA_SIZE)
+    };
+
+    let mut batches = Vec::new();
+    let mut current = Batch { payments: Vec::new(), instructions: Vec::new() };
+    for (index, payment) in instructions.iter().enumerate() {
+        let mut candidate = current.instructions.clone();
+        candidate.extend_from_slice(payment);
+        if fits(&candidate) {
+            current.payments.push(index);
+            current.instructions = candidate;
+            continue;
+        }
+        if current.payments.is_empty() || !fits(payment) {
+            return Err("A single payment does not fit in a transaction".into());
+        }
+        batches.push(current);
+        current = Batch { payments: vec![index], instructions: payment.clone() };
+    }
+    if !current.payments.is_empty() {
+        batches.push(current);
+    }
+    Ok(batches)
+}
+
+/// Checks the sender holds every token total, and enough SOL for the SOL payments plus the
+/// fees of every transaction, their priority fees, the rent of each recipient token account
+/// that does not exist yet, and its own rent-exempt minimum.
+fn check_balances(
+    rpc_client: &RpcClient,
+    options: &TransactionArgs,
+    sender: &Pubkey,
+    payments: &[&Payment],
+    mints: &HashMap<Pubkey, MintInfo>,
+    batches: &[Batch]
+) -> Result<(), Box<dyn Error>> {
+    let mut totals: BTreeMap<Pubkey, u128> = BTreeMap::new();
+    let mut lamports: u128 = 0;
+    let mut token_accounts: Vec<(Pubkey, usize)> = Vec::new();
+    for payment in payments {
+        match payment.mint.and_then(|mint| mints.get(&mint)) {
+            Some(mint) => {
+                *totals.entry(mint.address).or_insert(0) += payment.amount as u128;
+                let account = mint.associated_account(&payment.recipient);
+                if !token_accounts.iter().any(|(address, _)| *address == account) {
+                    token_accounts.push((account, mint.account_len));
+                }
+            }
+            None => {
+                lamports += payment.amount as u128;
+            }
+        }
+    }
+
+    for (address, total) in totals {
+        let mint = &mints[&address];
+        let account = mint.associated_account(sender);
+        // Only a missing account holds nothing; RPC failures are reported.
+        let response = rpc_client.get_account_with_commitment(&account, rpc_client.commitment())?;
+        let available = match response.value {
+            Some(account) => unpack_token_account(&account.data)?.amount,
+            None => 0,
+        };
+        ensure_available(&address.to_string(), mint.decimals, available as u128, total)?;
+    }
+
+    let mut rents: HashMap<usize, u64> = HashMap::new();
+    for chunk in token_accounts.chunks(MAX_MULTIPLE_ACCOUNTS) {
+        let addresses: Vec<Pubkey> = chunk
+            .iter()
+            .map(|(address, _)| *address)
+            .collect();
+        let existing = rpc_client.get_multiple_accounts(&addresses)?;
+        for ((_, len), account) in chunk.iter().zip(existing) {
+            if account.is_none() {
+                let rent = match rents.get(len) {
+                    Some(rent) => *rent,
+                    None => {
+                        let rent = rpc_client.get_minimum_balance_for_rent_exemption(*len)?;
+                        rents.insert(*len, rent);
+                        rent
+                    }
+                };
+                lamports += rent as u128;
+            }
+        }
+    }
+
+    let all_instructions: Vec<Instruction> = batches
+        .iter()
+        .flat_map(|batch| batch.instructions.iter().cloned())
+        .collect();
+    let price = compute_unit_price(rpc_client, options, &all_instructions)?;
+    // A nonce authority other than the sender signs every transaction too.
+    let signatures = match (&options.nonce, &options.nonce_authority_signer) {
+        (Some(_), Some(authority)) if authority.pubkey()? != *sender => 2,
+        _ => 1,
+    };
+    for batch in batches {
+        let instructions = batch.instructions.len() + usize::from(options.nonce.is_some());
+        let priority_fee = max_priority_fee(options, price, instructions);
+        lamports += (signatures * LAMPORTS_PER_SIGNATURE + priority_fee) as u128;
+    }
+    lamports += rpc_client.get_minimum_balance_for_rent_exemption(0)? as u128;
+    let available = rpc_client.get_balance(sender)? as u128;
+    ensure_available(SOL_ASSET, SOL_DECIMALS, available, lamports)
+}
+
+fn ensure_available(
+    asset: &str,
+    decimals: u8,
+    available: u128,
+    required: u128
+) -> Result<(), Box<dyn Error>> {
+    if available < required {
+        return Err(
+            format!(
+                "Insufficient {}: {} needed, {} available",
+                asset,
+                format_u128(required, decimals),
+                format_u128(available, decimals)
+            ).into()
+        );
+    }
+    Ok(())
+}
+
+fn format_u128(amount: u128, decimals: u8) -> String {
+    format_token_amount(amount.min(u64::MAX as u128) as u64, decimals)
+}
+
+/// Records the batch as pending under its signature before submitting it, so an interrupted
+/// run can find out whether it landed.
+fn send_batch(
+    rpc_client: &RpcClient,
+    options: &TransactionArgs,
+    sender: &Keypair,
+    batch: &Batch,
+    keys: &[&str],
+    ledger: &Mutex<BatchLedger>,
+    ledger_path: &str
+) -> Result<Signature, String> {
+    let record = |entry: LedgerEntry| -> Result<(), String> {
+        let mut ledger = ledger.lock().unwrap();
+        for key in keys {
+            ledger.payments.insert(key.to_string(), entry.clone());
+        }
+        save_ledger(ledger_path, &*ledger).map_err(|e| e.to_string())
+    };
+
+    let transaction = build_transaction(
+        rpc_client,
+        options,
+        &batch.instructions,
+        &sender.pubkey(),
+        &[sender],
+        None
+    ).map_err(|e| e.to_string())?;
+    let signature = transaction.signatures[0];
+    let mut entry = LedgerEntry {
+        status: PaymentStatus::Pending,
+        signature: Some(signature.to_string()),
+        blockhash: Some(transaction.message.recent_blockhash.to_string()),
+        nonce: options.nonce.clone(),
+        error: None,
+    };
+    record(entry.clone())?;
+
+    match submit_transaction(rpc_client, options, &transaction) {
+        Ok(_) => {
+            entry.status = PaymentStatus::Paid;
+            record(entry)?;
+            Ok(signature)
+        }
+        Err(e) => {
+            entry.status = PaymentStatus::Failed;
+            entry.error = Some(e.to_string());
+            record(entry)?;
+            Err(e.to_string())
+        }
+    }
+}
+
+fn load_ledger(path: &str, sender: &Pubkey) -> Result<BatchLedger, Box<dyn Error>> {
+    if !Path::new(path).exists() {
+        return Ok(BatchLedger { sender: sender.to_string(), payments: BTreeMap::new() });
+    }
+    let ledger: BatchLedger = serde_json::from_str(&fs::read_to_string(path)?)?;
+    if ledger.sender != sender.to_string() {
+        return Err(
+            format!("Ledger {} belongs to sender {}, not {}", path, ledger.sender, sender).into()
+        );
+    }
+    Ok(ledger)
+}
+
+/// Writes through a temporary file, so an interrupted write never leaves a truncated ledger.
+pub fn save_ledger(path: &str, ledger: &impl Serialize) -> Result<(), Box<dyn Error>> {
+    let tmp_path = format!("{}.tmp", path);
+    fs::write(&tmp_path, serde_json::to_string_pretty(ledger)?)?;
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+/// Settles payments left pending or failed by an earlier run: landed transactions are marked
+/// paid, and the rest are retried only once their blockhash or nonce can no longer be used.
+fn reconcile_ledger(
+    rpc_client: &RpcClient,
+    ledger: &mut BatchLedger
+) -> Result<(), Box<dyn Error>> {
+    let mut unsettled: Vec<Signature> = Vec::new();
+    for entry in ledger.payments.values() {
+        if entry.status == PaymentStatus::Paid {
+            continue;
+        }
+        if let Some(signature) = &entry.signature {
+            let signature = Signature::from_str(signature)?;
+            if !unsettled.contains(&signature) {
+                unsettled.push(signature);
+            }
+        }
+    }
+
+    let mut statuses = HashMap::new();
+    for chunk in unsettled.chunks(MAX_STATUS_QUERY) {
+        let found = rpc_client.get_signature_statuses_with_history(chunk)?.value;
+        statuses.extend(chunk.iter().map(|signature| signature.to_string()).zip(found));
+    }
+
+    let mut still_valid: HashMap<String, bool> = HashMap::new();
+    for (key, entry) in ledger.payments.iter_mut() {
+        let Some(signature) = entry.signature.clone() else {
+            continue;
+        };
+        if entry.status == PaymentStatus::Paid {
+            continue;
+        }
+        match statuses.get(&signature).cloned().flatten() {
+            Some(status) if status.err.is_none() => {
+                entry.status = PaymentStatus::Paid;
+                entry.error = None;
+            }
+            Some(status) => {
+                entry.status = PaymentStatus::Failed;
+                entry.error = status.err.map(|err| err.to_string());
+            }
+            None => {
+                if !still_valid.contains_key(&signature) {
+                    let valid = may_still_land(
+                        rpc_client,
+                        entry.blockhash.as_deref(),
+                        entry.nonce.as_deref()
+                    )?;
+                    still_valid.insert(signature.clone(), valid);
+                }
+                if still_valid[&signature] {
+                    return Err(
+                        format!(
+                            "Payment {} was submitted as {} and may still land; rerun in a minute",
+                            key,
+                            signature
+                        ).into()
+                    );
+                }
+                entry.status = PaymentStatus::Failed;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Whether a transaction signed with `blockhash`, or with the value of durable `nonce`, can
+/// still be processed.
+pub fn may_still_land(
+    rpc_client: &RpcClient,
+    blockhash: Option<&str>,
+    nonce: Option<&str>
+) -> Result<bool, Box<dyn Error>> {
+    let Some(blockhash) = blockhash else {
+        return Ok(false);
+    };
+    let blockhash = Hash::from_str(blockhash)?;
+    match nonce {
+        Some(nonce) => Ok(nonce_blockhash(rpc_client, &Pubkey::from_str(nonce)?)? == blockhash),
+        None => Ok(rpc_client.is_blockhash_valid(&blockhash, CommitmentConfig::processed())?),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rows_are_validated_before_anything_is_sent() {
+        let recipient = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let contents = format!(
+            "recipient,amount,mint\n# comment\n{r},1.5\n\n{r},2,{m}\n{r},1.5,SOL\n",
+            r = recipient,
+            m = mint
+        );
+        let rows = parse_payment_rows(&contents).unwrap();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[1].mint, Some(mint));
+        assert_eq!(rows[2].line, 6);
+
+        let decimals = HashMap::from([(mint, 6)]);
+        let payments = resolve_payments(&rows, &decimals).unwrap();
+        assert_eq!(payments[0].amount, 1_500_000_000);
+        assert_eq!(payments[1].amount, 2_000_000);
+        assert!(payments[0].key.ends_with(",SOL#1"));
+        assert!(payments[2].key.ends_with(",SOL#2"));
+
+        let errors = parse_payment_rows("nobody,1\nrecipient,1\n").unwrap_err();
+        assert_eq!(errors.len(), 2);
+        let bad_amounts = format!("{r},0\n{r},0.0000000001\n{r},abc\n", r = recipient);
+        let rows = parse_payment_rows(&bad_amounts).unwrap();
+        assert_eq!(resolve_payments(&rows, &HashMap::new()).unwrap_err().len(), 3);
+    }
+
+    #[test]
+    fn batches_fill_but_never_exceed_a_packet() {
+        let payer = Pubkey::new_unique();
+        let instructions: Vec<Vec<Instruction>> = (0..60)
+            .map(|_| vec![system_instruction::transfer(&payer, &Pubkey::new_unique(), 1)])
+            .collect();
+        let overhead = overhead_instructions(
+            &TransactionArgs::for_tests(),
+            &payer
+        ).unwrap();
+        let batches = pack_batches(&instructions, &overhead, &payer).unwrap();
+        assert!(batches.len() > 1 && batches.len() < 10);
+        let packed: Vec<usize> = batches
+            .iter()
+            .flat_map(|batch| batch.payments.clone())
+            .collect();
+        assert_eq!(packed, (0..60).collect::<Vec<_>>());
+        for batch in &batches {
+            let mut all = overhead.clone();
+            all.extend_from_slice(&batch.instructions);
+            let transaction = Transaction::new_unsigned(Message::new(&all, Some(&payer)));
+            assert!((bincode::serialized_size(&transaction).unwrap() as usize) <= PACKET_DATA_SIZE);
+        }
+    }
+}
@@ -0,0 +1,735 @@
+use borsh::{ BorshDeserialize, BorshSerialize };
+use solana_client::{ rpc_client::RpcClient, rpc_config::RpcTransactionConfig };
+use solana_sdk::{
+    commitment_config::CommitmentConfig,
+    instruction::{ AccountMeta, Instruction },
+    pubkey,
+    pubkey::Pubkey,
+    signature::{ Keypair, Signature },
+    signer::Signer,
+    system_instruction,
+    system_program,
+};
+use solana_transaction_status::{
+    option_serializer::OptionSerializer,
+    EncodedConfirmedTransactionWithStatusMeta,
+    UiInstruction,
+    UiTransactionEncoding,
+};
+use serde_json::{ json, Value };
+use std::{ error::Error, fs, path::Path, str::FromStr };
+
+use crate::{
+    airdrop::cnft_airdrop,
+    history::account_keys,
+    keystore::read_keys,
+    merkle::{ hash_creators, hash_metadata, leaf_proof, replay_tree, verify_proof },
+    output::{ emit, status },
+    state::*,
+    transaction::send_instructions,
+    vault::{ anchor_instruction, decode_anchor_account },
+    wallet::Wallet,
+};
+
+pub const BUBBLEGUM_PROGRAM_ID: Pubkey = pubkey!("BGUMAp9Gq7iTEuizy4pqaxsTyUCBK68MDfK752saRPUY");
+pub const COMPRESSION_PROGRAM_ID: Pubkey = pubkey!("cmtDvXumGCrqC1Age74AVPhSRVXJMd8PJS91L8KbNCK");
+/// Bubblegum and the compression program log their events as calls to this program.
+pub const NOOP_PROGRAM_ID: Pubkey = pubkey!("noopb9bkMVfRPU8AsbpTUg8AQkHtKwMYZiFUjNRtMmV");
+
+/// Depth and buffer size pairs the compression program accepts.
+pub const DEPTH_SIZE_PAIRS: [(u32, u32); 33] = [
+    (3, 8),
+    (5, 8),
+    (6, 16),
+    (7, 16),
+    (8, 16),
+    (9, 16),
+    (10, 32),
+    (11, 32),
+    (12, 32),
+    (13, 32),
+    (14, 64),
+    (14, 256),
+    (14, 1024),
+    (15, 64),
+    (16, 64),
+    (17, 64),
+    (18, 64),
+    (19, 64),
+    (20, 64),
+    (20, 256),
+    (20, 1024),
+    (20, 2048),
+    (24, 64),
+    (24, 256),
+    (24, 512),
+    (24, 1024),
+    (24, 2048),
+    (26, 512),
+    (26, 1024),
+    (26, 2048),
+    (30, 512),
+    (30, 1024),
+    (30, 2048),
+];
+/// Account type, header version, max buffer size, max depth, authority, creation slot and
+/// padding, before the tree itself.
+pub const TREE_HEADER_SIZE: usize = 56;
+const NODE_SIZE: usize = 32;
+const MAX_NAME_LENGTH: usize = 32;
+const MAX_SYMBOL_LENGTH: usize = 10;
+const MAX_URI_LENGTH: usize = 200;
+const MAX_CREATORS: usize = 5;
+
+/// Bytes of a tree account: header, changelog buffer, rightmost path and canopy.
+pub fn tree_account_size(max_depth: u32, max_buffer_size: u32, canopy_depth: u32) -> usize {
+    TREE_HEADER_SIZE + tree_size(max_depth, max_buffer_size) + canopy_size(canopy_depth)
+}
+
+/// Sequence number, active index and buffer size, then `max_buffer_size` changelogs (a root,
+/// a path and an index each) and the rightmost proof (a path, a leaf and an index).
+fn tree_size(max_depth: u32, max_buffer_size: u32) -> usize {
+    let path = NODE_SIZE * max_depth as usize;
+    let change_log = NODE_SIZE + path + 8;
+    let rightmost_proof = path + NODE_SIZE + 8;
+    24 + (max_buffer_size as usize) * change_log + rightmost_proof
+}
+
+/// Every node of the top `canopy_depth` levels below the root.
+fn canopy_size(canopy_depth: u32) -> usize {
+    ((1 << (canopy_depth + 1)) - 2) * NODE_SIZE
+}
+
+/// Header of a tree account, and the canopy depth its size implies.
+#[derive(Debug, PartialEq)]
+pub struct TreeHeader {
+    pub max_buffer_size: u32,
+    pub max_depth: u32,
+    /// The Bubblegum tree config, which signs every change.
+    pub authority: Pubkey,
+    pub creation_slot: u64,
+    pub canopy_depth: u32,
+}
+
+pub fn decode_tree_header(data: &[u8]) -> Result<TreeHeader, Box<dyn Error>> {
+    if data.len() < TREE_HEADER_SIZE || data[0] != 1 {
+        return Err("Account data is not a concurrent Merkle tree".into());
+    }
+    if data[1] != 0 {
+        return Err(format!("Unsupported tree header version {}", data[1]).into());
+    }
+    let u32_at = |offset: usize| u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+    let max_buffer_size = u32_at(2);
+    let max_depth = u32_at(6);
+    let canopy = data.len().checked_sub(TREE_HEADER_SIZE + tree_size(max_depth, max_buffer_size));
+    let canopy_depth = canopy
+        .and_then(|bytes| (0..max_depth).find(|depth| canopy_size(*depth) == bytes))
+        .ok_or("The tree account size does not match its depth and buffer size")?;
+    Ok(TreeHeader {
+        max_buffer_size,
+        max_depth,
+        authority: Pubkey::try_from(&data[10..42])?,
+        creation_slot: u64::from_le_bytes(data[42..50].try_into()?),
+        canopy_depth,
+    })
+}
+
+/// Bubblegum's `TreeConfig`, the tree's authority. Later fields are not needed.
+#[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq)]
+pub struct TreeConfig {
+    pub tree_creator: Pubkey,
+    pub tree_delegate: Pubkey,
+    pub total_mint_capacity: u64,
+    pub num_minted: u64,
+    pub is_public: bool,
+}
+
+impl TreeConfig {
+    pub fn address(merkle_tree: &Pubkey) -> Pubkey {
+        Pubkey::find_program_address(&[merkle_tree.as_ref()], &BUBBLEGUM_PROGRAM_ID).0
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct Creator {
+    pub address: Pubkey,
+    /// Verified creators must sign the mint.
+    pub verified: bool,
+    pub share: u8,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct Collection {
+    pub verified: bool,
+    pub key: Pubkey,
+}
+
+/// `MetadataArgs` of Bubblegum V1. Enums are kept as their Borsh tags: `token_standard` 0 is
+/// non-fungible and `token_program_version` 0 is the original token program.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct MetadataArgs {
+    pub name: String,
+    pub symbol: String,
+    pub uri: String,
+    pub seller_fee_basis_points: u16,
+    pub primary_sale_happened: bool,
+    pub is_mutable: bool,
+    pub edition_nonce: Option<u8>,
+    pub token_standard: Option<u8>,
+    pub collection: Option<Collection>,
+    /// `Uses` are deprecated and never set; `None` encodes the same either way.
+    pub uses: Option<()>,
+    pub token_program_version: u8,
+    pub creators: Vec<Creator>,
+}
+
+impl MetadataArgs {
+    /// Checks what Bubblegum checks before minting.
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        for (field, value, max) in [
+            ("name", &self.name, MAX_NAME_LENGTH),
+            ("symbol", &self.symbol, MAX_SYMBOL_LENGTH),
+            ("uri", &self.uri, MAX_URI_LENGTH),
+        ] {
+            if value.len() > max {
+                return Err(
+                    format!("The {} is {} bytes; at most {} fit", field, value.len(), max).into()
+                );
+            }
+        }
+        if self.seller_fee_basis_points > 10_000 {
+            return Err("Royalties are at most 10000 basis points".into());
+        }
+        if self.creators.len() > MAX_CREATORS {
+            return Err(format!("At most {} creators are allowed", MAX_CREATORS).into());
+        }
+        let shares: u32 = self.creators
+            .iter()
+            .map(|creator| u32::from(creator.share))
+            .sum();
+        if !self.creators.is_empty() && shares != 100 {
+            return Err(format!("Creator shares add up to {}, not 100", shares).into());
+        }
+        Ok(())
+    }
+}
+
+/// Parses `ADDRESS:SHARE`. The creator is verified when it is the signer.
+pub fn parse_creator(value: &str, signer: &Pubkey) -> Result<Creator, Box<dyn Error>> {
+    let (address, share) = value
+        .rsplit_once(':')
+        .ok_or_else(|| format!("Invalid creator '{}': expected ADDRESS:SHARE", value))?;
+    let address = Pubkey::from_str(address).map_err(|_| {
+        format!("Invalid creator '{}': not a base58 public key", address)
+    })?;
+    let share = share
+        .parse()
+        .map_err(|_| format!("Invalid share '{}': expected a whole percentage", share))?;
+    Ok(Creator { address, verified: address == *signer, share })
+}
+
+/// A leaf as Bubblegum V1 emits it: `nonce` is the leaf index.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub struct LeafSchema {
+    pub id: Pubkey,
+    pub owner: Pubkey,
+    pub delegate: Pubkey,
+    pub nonce: u64,
+    pub data_hash: [u8; 32],
+    pub creator_hash: [u8; 32],
+}
+
+/// `createTree`, for a tree account the compression program already owns.
+pub fn create_tree_instruction(
+    merkle_tree: &Pubkey,
+    payer: &Pubkey,
+    tree_creator: &Pubkey,
+    max_depth: u32,
+    max_buffer_size: u32,
+    public: bool
+) -> Result<Instruction, Box<dyn Error>> {
+    let accounts = vec![
+        AccountMeta::new(TreeConfig::address(merkle_tree), false),
+        AccountMeta::new(*merkle_tree, false),
+        AccountMeta::new(*payer, true),
+        AccountMeta::new_readonly(*tree_creator, true),
+        AccountMeta::new_readonly(NOOP_PROGRAM_ID, false),
+        AccountMeta::new_readonly(COMPRESSION_PROGRAM_ID, false),
+        AccountMeta::new_readonly(system_program::id(), false)
+    ];
+    let args = (max_depth, max_buffer_size, Some(public));
+    anchor_instruction(&BUBBLEGUM_PROGRAM_ID, "create_tree", &args, accounts)
+}
+
+/// `mintV1`, signed by the payer and the tree delegate (the creator, unless delegated).
+pub fn mint_v1_instruction(
+    merkle_tree: &Pubkey,
+    leaf_owner: &Pubkey,
+    leaf_delegate: &Pubkey,
+    payer: &Pubkey,
+    tree_delegate: &Pubkey,
+    metadata: &MetadataArgs
+) -> Result<Instruction, Box<dyn Error>> {
+    let accounts = vec![
+        AccountMeta::new(TreeConfig::address(merkle_tree), false),
+        AccountMeta::new_readonly(*leaf_owner, false),
+        AccountMeta::new_readonly(*leaf_delegate, false),
+        AccountMeta::new(*merkle_tree, false),
+        AccountMeta::new(*payer, true),
+        AccountMeta::new_readonly(*tree_delegate, true),
+        AccountMeta::new_readonly(NOOP_PROGRAM_ID, false),
+        AccountMeta::new_readonly(COMPRESSION_PROGRAM_ID, false),
+        AccountMeta::new_readonly(system_program::id(), false)
+    ];
+    anchor_instruction(&BUBBLEGUM_PROGRAM_ID, "mint_v1", metadata, accounts)
+}
+
+/// Data of every call to the noop program in a transaction, in order: the events Bubblegum
+/// and the compression program logged.
+pub fn noop_events(
+    signature: &Signature,
+    transaction: &EncodedConfirmedTransactionWithStatusMeta
+) -> Result<Vec<Vec<u8>>, Box<dyn Error>> {
+    let keys = account_keys(signature, transaction)?;
+    let Some(meta) = &transaction.transaction.meta else {
+        return Err(format!("Transaction {} has no status metadata", signature).into());
+    };
+    let OptionSerializer::Some(inner_instructions) = &meta.inner_instructions else {
+        return Ok(Vec::new());
+    };
+    let mut events = Vec::new();
+    for instruction in inner_instructions.iter().flat_map(|inner| &inner.instructions) {
+        let UiInstruction::Compiled(instruction) = instruction else {
+            continue;
+        };
+        let noop = NOOP_PROGRAM_ID.to_string();
+        if keys.get(instruction.program_id_index as usize) == Some(&noop) {
+            events.push(bs58::decode(&instruction.data).into_vec()?);
+        }
+    }
+    Ok(events)
+}
+
+/// Decodes Bubblegum's leaf event, which the compression program's `ApplicationData` V1
+/// event wraps: tags 1 and 0, the length, then `LeafSchemaEvent` V1 and the leaf hash.
+pub fn decode_leaf_event(data: &[u8]) -> Option<LeafSchema> {
+    let application_data = match data {
+        [1, 0, rest @ ..] => Vec::<u8>::deserialize(&mut &rest[..]).ok()?,
+        _ => {
+            return None;
+        }
+    };
+    match application_data.as_slice() {
+        [1, 0, 0, schema @ ..] => LeafSchema::deserialize(&mut &schema[..]).ok(),
+        _ => None,
+    }
+}
+
+/// A confirmed transaction with the inner instructions `noop_events` reads.
+pub fn fetch_transaction(
+    rpc_client: &RpcClient,
+    signature: &Signature
+) -> Result<EncodedConfirmedTransactionWithStatusMeta, Box<dyn Error>> {
+    Ok(
+        rpc_client.get_transaction_with_config(
+            signature,
+            RpcTransactionConfig {
+                encoding: Some(UiTransactionEncoding::Json),
+                commitment: Some(CommitmentConfig::confirmed()),
+                max_supported_transaction_version: Some(0),
+            }
+        )?
+    )
+}
+
+/// The leaves a confirmed transaction minted.
+pub fn minted_leaves(
+    rpc_client: &RpcClient,
+    signature: &Signature
+) -> Result<Vec<LeafSchema>, Box<dyn Error>> {
+    let transaction = fetch_transaction(rpc_client, signature)?;
+    let events = noop_events(signature, &transaction)?;
+    Ok(events.iter().filter_map(|event| decode_leaf_event(event)).collect())
+}
+
+pub fn read_tree_file(path: &str) -> Result<TreeFile, Box<dyn Error>> {
+    let data = fs::read_to_string(path).map_err(|e| {
+        format!("Cannot read the tree file {}: {}; create a tree with `cnft create-tree`", path, e)
+    })?;
+    Ok(serde_json::from_str(&data).map_err(|e| format!("Invalid tree file {}: {}", path, e))?)
+}
+
+/// Fetches a tree account and its Bubblegum config, checking both belong to the programs.
+pub fn fetch_tree(
+    rpc_client: &RpcClient,
+    merkle_tree: &Pubkey
+) -> Result<(TreeHeader, TreeConfig), Box<dyn Error>> {
+    let account = rpc_client.get_account(merkle_tree)?;
+    if account.owner != COMPRESSION_PROGRAM_ID {
+        return Err(format!("{} is not owned by the compression program", merkle_tree).into());
+    }
+    let header = decode_tree_header(&account.data)?;
+    let config_address = TreeConfig::address(merkle_tree);
+    if header.authority != config_address {
+        return Err(format!("{} is not a Bubblegum tree", merkle_tree).into());
+    }
+    let config = rpc_client.get_account(&config_address)?;
+    if config.owner != BUBBLEGUM_PROGRAM_ID {
+        return Err(format!("{} is not owned by Bubblegum", config_address).into());
+    }
+    Ok((header, decode_anchor_account("TreeConfig", &config.data)?))
+}
+
+pub fn cnft_command(
+    rpc_client: &RpcClient,
+    options: &TransactionArgs,
+    wallet: &Wallet,
+    cmd: &CnftCommand
+) -> Result<(), Box<dyn Error>> {
+    let signer_path = wallet.resolve(cmd.from.as_deref())?;
+    let signer = || read_keys(&signer_path);
+    match &cmd.operation {
+        CnftOperations::CreateTree(tree_cmd) => {
+            create_tree(rpc_client, options, &signer()?, &cmd.tree_file, tree_cmd)
+        }
+        CnftOperations::Mint(mint_cmd) => {
+            mint(rpc_client, options, &signer()?, &cmd.tree_file, mint_cmd)
+        }
+        CnftOperations::Airdrop(airdrop_cmd) => {
+            cnft_airdrop(rpc_client, options, &signer()?, &cmd.tree_file, airdrop_cmd)
+        }
+        CnftOperations::Show => show_tree(rpc_client, &cmd.tree_file),
+        CnftOperations::Replay => replay_tree(rpc_client, &cmd.tree_file),
+        CnftOperations::Proof(proof_cmd) => leaf_proof(rpc_client, &cmd.tree_file, proof_cmd),
+        CnftOperations::Verify(verify_cmd) => {
+            verify_proof(rpc_client, &cmd.tree_file, verify_cmd)
+        }
+    }
+}
+
+fn create_tree(
+    rpc_client: &RpcClient,
+    options: &TransactionArgs,
+    payer: &Keypair,
+    tree_file: &str,
+    cmd: &CreateTreeCommand
+) -> Result<(), Box<dyn Error>> {
+    if Path::new(tree_file).exists() && !cmd.force {
+        let existing = read_tree_file(tree_file)?.public_key;
+        return Err(
+            format!(
+                "{} already names the tree {}; pass --force to create another",
+                tree_file,
+                existing
+            ).into()
+        );
+    }
+    if !DEPTH_SIZE_PAIRS.contains(&(cmd.max_depth, cmd.max_buffer_size)) {
+        let pairs: Vec<String> = DEPTH_SIZE_PAIRS
+            .iter()
+            .map(|(depth, size)| format!("{}/{}", depth, size))
+            .collect();
+        return Err(
+            format!(
+                "Depth {} with buffer size {} is not supported; valid depth/buffer pairs: {}",
+                cmd.max_depth,
+                cmd.max_buffer_size,
+                pairs.join(", ")
+            ).into()
+        );
+    }
+    if cmd.canopy_depth >= cmd.max_depth {
+        return Err("The canopy depth must be less than the tree depth".into());
+    }
+
+    let merkle_tree = Keypair::new();
+    let space = tree_account_size(cmd.max_depth, cmd.max_buffer_size, cmd.canopy_depth);
+    let lamports = rpc_client.get_minimum_balance_for_rent_exemption(space)?;
+    let instructions = [
+        system_instruction::create_account(
+            &payer.pubkey(),
+            &merkle_tree.pubkey(),
+            lamports,
+            space as u64,
+            &COMPRESSION_PROGRAM_ID
+        ),
+        create_tree_instruction(
+            &merkle_tree.pubkey(),
+            &payer.pubkey(),
+            &payer.pubkey(),
+            cmd.max_depth,
+            cmd.max_buffer_size,
+            cmd.public
+        )?,
+    ];
+    let confirmation = send_instructions(
+        rpc_client,
+        options,
+        &instructions,
+        payer,
+        &[&merkle_tree]
+    )?;
+    let signature = confirmation.signature;
+
+    let tree = TreeFile {
+        public_key: merkle_tree.pubkey().to_string(),
+        max_depth: Some(cmd.max_depth),
+        max_buffer_size: Some(cmd.max_buffer_size),
+        canopy_depth: Some(cmd.canopy_depth),
+        creator: Some(payer.pubkey().to_string()),
+        signature: Some(signature.to_string()),
+    };
+    fs::write(tree_file, serde_json::to_string_pretty(&tree)?)?;
+    emit(
+        json!({
+            "tree": tree.public_key,
+            "tree_file": tree_file,
+            "max_depth": cmd.max_depth,
+            "max_buffer_size": cmd.max_buffer_size,
+            "canopy_depth": cmd.canopy_depth,
+            "capacity": 1u64 << cmd.max_depth,
+            "space": space,
+            "lamports": lamports,
+            "signature": signature.to_string(),
+        }),
+        || {
+            println!("Tree created: {}", tree.public_key);
+            println!(
+                "Depth {}, buffer {}, canopy {}: {} leaves",
+                cmd.max_depth,
+                cmd.max_buffer_size,
+                cmd.canopy_depth,
+                1u64 << cmd.max_depth
+            );
+            println!("Saved to {}", tree_file);
+            println!("Signature: {}", signature);
+        }
+    );
+    Ok(())
+}
+
+fn mint(
+    rpc_client: &RpcClient,
+    options: &TransactionArgs,
+    payer: &Keypair,
+    tree_file: &str,
+    cmd: &CnftMintCommand
+) -> Result<(), Box<dyn Error>> {
+    let merkle_tree = Pubkey::from_str(&read_tree_file(tree_file)?.public_key)?;
+    let owner = Pubkey::from_str(&cmd.owner)?;
+    let delegate = match &cmd.delegate {
+        Some(delegate) => Pubkey::from_str(delegate)?,
+        None => owner,
+    };
+    let metadata = metadata_args(&cmd.metadata, &payer.pubkey())?;
+    let (_, config) = fetch_tree(rpc_client, &merkle_tree)?;
+    check_can_mint(&config, &payer.pubkey(), 1)?;
+
+    let instruction = mint_v1_instruction(
+        &merkle_tree,
+        &owner,
+        &delegate,
+        &payer.pubkey(),
+        &payer.pubkey(),
+        &metadata
+    )?;
+    let signature = send_instructions(rpc_client, options, &[instruction], payer, &[])?.signature;
+    let leaf = match minted_leaves(rpc_client, &signature) {
+        Ok(leaves) => leaves.into_iter().next(),
+        Err(e) => {
+            status(&format!("Cannot read the minted leaf: {}", e));
+            None
+        }
+    };
+    if let Some(leaf) = &leaf {
+        let data_hash = hash_metadata(&metadata)?;
+        if leaf.data_hash != data_hash || leaf.creator_hash != hash_creators(&metadata.creators) {
+            status("Warning: the minted leaf's hashes do not match the metadata sent");
+        }
+    }
+    emit(
+        json!({
+            "tree": merkle_tree.to_string(),
+            "owner": owner.to_string(),
+            "leaf_index": leaf.as_ref().map(|leaf| leaf.nonce),
+            "asset_id": leaf.as_ref().map(|leaf| leaf.id.to_string()),
+            "signature": signature.to_string(),
+        }),
+        || {
+            println!("Minted '{}' to {}", metadata.name, owner);
+            if let Some(leaf) = &leaf {
+                println!("Leaf index: {}", leaf.nonce);
+                println!("Asset id: {}", leaf.id);
+            }
+            println!("Signature: {}", signature);
+        }
+    );
+    Ok(())
+}
+
+/// Metadata for `cnft mint` and `cnft airdrop`. Without `--creator`, the signer is the sole,
+/// verified creator.
+pub fn metadata_args(
+    cmd: &NftMetadataArgs,
+    signer: &Pubkey
+) -> Result<MetadataArgs, Box<dyn Error>> {
+    let mut creators = cmd.creators
+        .iter()
+        .map(|creator| parse_creator(creator, signer))
+        .collect::<Result<Vec<_>, _>>()?;
+    if creators.is_empty() {
+        creators.push(Creator { address: *signer, verified: true, share: 100 });
+    }
+    let collection = match &cmd.collection {
+        Some(key) => Some(Collection { verified: false, key: Pubkey::from_str(key)? }),
+        None => None,
+    };
+    let metadata = MetadataArgs {
+        name: cmd.name.clone(),
+        symbol: cmd.symbol.clone(),
+        uri: cmd.uri.clone(),
+        seller_fee_basis_points: cmd.seller_fee_basis_points,
+        primary_sale_happened: false,
+        is_mutable: !cmd.immutable,
+        edition_nonce: None,
+        token_standard: Some(0),
+        collection,
+        uses: None,
+        token_program_version: 0,
+        creators,
+    };
+    metadata.validate()?;
+    Ok(metadata)
+}
+
+/// Fails unless `signer` may mint `count` more leaves into the tree.
+pub fn check_can_mint(
+    config: &TreeConfig,
+    signer: &Pubkey,
+    count: u64
+) -> Result<(), Box<dyn Error>> {
+    if !config.is_public && config.tree_delegate != *signer {
+        return Err(
+            format!(
+                "Only the tree delegate {} can mint into this private tree",
+                config.tree_delegate
+            ).into()
+        );
+    }
+    let remaining = config.total_mint_capacity.saturating_sub(config.num_minted);
+    if remaining < count {
+        return Err(
+            format!("The tree has room for {} more leaves, not {}", remaining, count).into()
+        );
+    }
+    Ok(())
+}
+
+fn tree_json(merkle_tree: &Pubkey, header: &TreeHeader, config: &TreeConfig) -> Value {
+    json!({
+        "tree": merkle_tree.to_string(),
+        "max_depth": header.max_depth,
+        "max_buffer_size": header.max_buffer_size,
+        "canopy_depth": header.canopy_depth,
+        "creation_slot": header.creation_slot,
+        "tree_config": header.authority.to_string(),
+        "creator": config.tree_creator.to_string(),
+        "delegate": config.tree_delegate.to_string(),
+        "public": config.is_public,
+        "capacity": config.total_mint_capacity,
+        "minted": config.num_minted,
+    })
+}
+
+fn show_tree(rpc_client: &RpcClient, tree_file: &str) -> Result<(), Box<dyn Error>> {
+    let merkle_tree = Pubkey::from_str(&read_tree_file(tree_file)?.public_key)?;
+    let (header, config) = fetch_tree(rpc_client, &merkle_tree)?;
+    emit(tree_json(&merkle_tree, &header, &config), || {
+        println!("Tree: {}", merkle_tree);
+        println!(
+            "Depth {}, buffer {}, canopy {}",
+            header.max_depth,
+            header.max_buffer_size,
+            header.canopy_depth
+        );
+        println!("Creator: {}", config.tree_creator);
+        println!("Delegate: {}", config.tree_delegate);
+        println!("Public: {}", if config.is_public { "yes" } else { "no" });
+        println!("Minted: {} of {}", config.num_minted, config.total_mint_capacity);
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes_tree_accounts_like_the_compression_program() {
+        assert_eq!(tree_account_size(14, 64, 0), 31_800);
+        assert_eq!(tree_account_size(3, 8, 0), 1_304);
+
+        let mut data = vec![0; tree_account_size(14, 64, 5)];
+        data[0] = 1;
+        data[2..6].copy_from_slice(&64u32.to_le_bytes());
+        data[6..10].copy_from_slice(&14u32.to_le_bytes());
+        data[42..50].copy_from_slice(&7u64.to_le_bytes());
+        let header = decode_tree_header(&data).unwrap();
+        assert_eq!((header.max_depth, header.max_buffer_size, header.canopy_depth), (14, 64, 5));
+        assert_eq!(header.creation_slot, 7);
+        assert!(decode_tree_header(&data[..data.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn encodes_bubblegum_instructions() {
+        let tree = Pubkey::new_unique();
+        let payer = Pubkey::new_unique();
+        let create = create_tree_instruction(&tree, &payer, &payer, 14, 64, false).unwrap();
+        assert_eq!(create.data[..8], [165, 83, 136, 142, 89, 202, 47, 220]);
+        assert_eq!(create.data[8..], [14, 0, 0, 0, 64, 0, 0, 0, 1, 0]);
+        assert_eq!(create.accounts[0].pubkey, TreeConfig::address(&tree));
+
+        let cmd = NftMetadataArgs {
+            name: "Triad Beta Pass".to_string(),
+            uri: "https://example.com/beta.json".to_string(),
+            symbol: String::new(),
+            seller_fee_basis_points: 500,
+            creators: Vec::new(),
+            collection: None,
+            immutable: false,
+        };
+        let metadata = metadata_args(&cmd, &payer).unwrap();
+        assert_eq!(metadata.creators, vec![Creator { address: payer, verified: true, share: 100 }]);
+        let mint = mint_v1_instruction(&tree, &payer, &payer, &payer, &payer, &metadata).unwrap();
+        assert_eq!(mint.data[..8], [145, 98, 192, 118, 184, 147, 118, 104]);
+        assert_eq!(MetadataArgs::try_from_slice(&mint.data[8..]).unwrap(), metadata);
+        // Name, symbol and URI, then royalties, flags, options and the creator.
+        let strings = 4 + 15 + 4 + (4 + 29);
+        assert_eq!(mint.data.len(), 8 + strings + 2 + 2 + 1 + 2 + 1 + 1 + 1 + 4 + 34);
+
+        let split = parse_creator(&format!("{}:60", Pubkey::new_unique()), &payer).unwrap();
+        assert!(!split.verified && split.share == 60);
+        let cmd = NftMetadataArgs { creators: vec![format!("{}:60", payer)], ..cmd };
+        assert!(metadata_args(&cmd, &payer).is_err());
+    }
+
+    #[test]
+    fn decodes_leaf_events() {
+        let leaf = LeafSchema {
+            id: Pubkey::new_unique(),
+            owner: Pubkey::new_unique(),
+            delegate: Pubkey::new_unique(),
+            nonce: 41,
+            data_hash: [2; 32],
+            creator_hash: [3; 32],
+        };
+        let mut application_data = vec![1, 0, 0];
+        application_data.extend(borsh::to_vec(&leaf).unwrap());
+        application_data.extend([4; 32]);
+        let mut event = vec![1, 0];
+        event.extend(borsh::to_vec(&application_data).unwrap());
+
+        assert_eq!(decode_leaf_event(&event), Some(leaf));
+        assert_eq!(decode_leaf_event(&[0, 0, 0, 0, 0, 0]), None);
+    }
+}
@@ -0,0 +1,349 @@
+use clap::ValueEnum;
+use serde_json::{ json, Value };
+use solana_sdk::commitment_config::CommitmentConfig;
+use std::{ collections::BTreeMap, env, error::Error, fs, io::ErrorKind, path::{ Path, PathBuf } };
+use url::Url;
+
+use crate::{ output::{ emit, value_name }, state::*, wallet::Wallet };
+
+/// Overrides the location of the configuration file.
+const CONFIG_ENV: &str = "SCW_CONFIG";
+const DEFAULT_PROFILE: &str = "default";
+/// Written to the working directory by earlier versions; its endpoint seeds the first profile.
+const LEGACY_SETTINGS: &str = "./settings.json";
+
+/// `$SCW_CONFIG`, otherwise `scw/config.json` in the user config directory.
+pub fn config_path() -> Result<PathBuf, Box<dyn Error>> {
+    if let Some(path) = env::var_os(CONFIG_ENV) {
+        return Ok(PathBuf::from(path));
+    }
+    let dir = dirs
+        ::config_dir()
+        .ok_or_else(|| format!("No user config directory found; set {}", CONFIG_ENV))?;
+    Ok(dir.join("scw").join("config.json"))
+}
+
+impl ProgramSettings {
+    /// Loads the configuration, creating it with a `default` profile on first use.
+    pub fn load_settings(path: &Path) -> Result<Self, Box<dyn Error>> {
+        match fs::read_to_string(path) {
+            Ok(contents) => {
+                serde_json::from_str(&contents).map_err(|e| {
+                    format!("Invalid configuration in {}: {}", path.display(), e).into()
+                })
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                let settings = Self::initial(legacy_endpoint());
+                settings.save_settings(path)?;
+                Ok(settings)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save_settings(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    fn initial(rpc_url: Option<String>) -> Self {
+        let rpc_url = rpc_url.unwrap_or_else(|| Endpoint::Local.into());
+        Self {
+            active_profile: DEFAULT_PROFILE.to_string(),
+            profiles: BTreeMap::from([(DEFAULT_PROFILE.to_string(), Profile::new(rpc_url))]),
+        }
+    }
+
+    /// Name of the profile picked by `--profile` or `SCW_PROFILE`, else the active one.
+    pub fn selected_name(&self, name: Option<&str>) -> String {
+        name.unwrap_or(&self.active_profile).to_string()
+    }
+
+    pub fn profile(&self, name: Option<&str>) -> Result<&Profile, Box<dyn Error>> {
+        let name = self.selected_name(name);
+        self.profiles
+            .get(&name)
+            .ok_or_else(|| {
+                format!(
+                    "No profile named '{}'; create it with `config set rpc-url <URL> --profile {}`",
+                    name,
+                    name
+                ).into()
+            })
+    }
+}
+
+fn legacy_endpoint() -> Option<String> {
+    let contents = fs::read_to_string(LEGACY_SETTINGS).ok()?;
+    let legacy: Value = serde_json::from_str(&contents).ok()?;
+    legacy["endpoint"].as_str().map(str::to_string)
+}
+
+impl Profile {
+    pub fn new(rpc_url: String) -> Self {
+        Self {
+            rpc_url,
+            websocket_url: None,
+            commitment: Commitment::default(),
+            keypair: None,
+            priority_fee: None,
+        }
+    }
+
+    /// The configured websocket URL, or the one the RPC node serves next to `rpc_url`.
+    pub fn websocket_url(&self) -> Result<String, Box<dyn Error>> {
+        match &self.websocket_url {
+            Some(url) => Ok(url.clone()),
+            None => websocket_url_for(&self.rpc_url),
+        }
+    }
+
+    fn get(&self, key: ConfigKey) -> Result<Value, Box<dyn Error>> {
+        Ok(match key {
+            ConfigKey::RpcUrl => json!(self.rpc_url),
+            ConfigKey::WebsocketUrl => json!(self.websocket_url()?),
+            ConfigKey::Commitment => json!(self.commitment),
+            ConfigKey::Keypair => json!(self.keypair),
+            ConfigKey::PriorityFee => json!(self.priority_fee),
+        })
+    }
+
+    fn set(&mut self, key: ConfigKey, value: Option<&str>) -> Result<(), Box<dyn Error>> {
+        let required = || value.ok_or_else(|| format!("{} cannot be cleared", value_name(&key)));
+        match key {
+            ConfigKey::RpcUrl => {
+                self.rpc_url = parse_rpc_url(required()?)?;
+            }
+            ConfigKey::WebsocketUrl => {
+                self.websocket_url = value.map(parse_websocket_url).transpose()?;
+            }
+            ConfigKey::Commitment => {
+                self.commitment = Commitment::from_str(required()?, true)?;
+            }
+            ConfigKey::Keypair => {
+                self.keypair = value.map(str::to_string);
+            }
+            ConfigKey::PriorityFee => {
+                self.priority_fee = value.map(str::parse).transpose()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn to_json(&self) -> Result<Value, Box<dyn Error>> {
+        Ok(
+            json!({
+                "rpc_url": self.rpc_url,
+                "websocket_url": self.websocket_url()?,
+                "commitment": self.commitment,
+                "keypair": self.keypair,
+                "priority_fee": self.priority_fee,
+            })
+        )
+    }
+}
+
+impl Commitment {
+    pub fn config(self) -> CommitmentConfig {
+        match self {
+            Commitment::Processed => CommitmentConfig::processed(),
+            Commitment::Confirmed => CommitmentConfig::confirmed(),
+            Commitment::Finalized => CommitmentConfig::finalized(),
+        }
+    }
+}
+
+impl From<Endpoint> for String {
+    fn from(endpoint: Endpoint) -> Self {
+        match endpoint {
+            Endpoint::Local => "http://127.0.0.1:8899".to_string(),
+            Endpoint::Dev => "https://api.devnet.solana.com".to_string(),
+            Endpoint::Test => "https://api.testnet.solana.com".to_string(),
+            Endpoint::MainnetBeta => "https://api.mainnet-beta.solana.com".to_string(),
+        }
+    }
+}
+
+/// Accepts an http(s) URL or a cluster moniker such as `devnet` or `mainnet-beta`.
+pub fn parse_rpc_url(value: &str) -> Result<String, Box<dyn Error>> {
+    if let Ok(endpoint) = Endpoint::from_str(value, true) {
+        return Ok(endpoint.into());
+    }
+    let url = Url::parse(value).map_err(|e| format!("Invalid RPC URL '{}': {}", value, e))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(format!("RPC URL '{}' must use http or https", value).into());
+    }
+    Ok(value.to_string())
+}
+
+fn parse_websocket_url(value: &str) -> Result<String, Box<dyn Error>> {
+    let url = Url::parse(value).map_err(|e| format!("Invalid websocket URL '{}': {}", value, e))?;
+    if !matches!(url.scheme(), "ws" | "wss") {
+        return Err(format!("Websocket URL '{}' must use ws or wss", value).into());
+    }
+    Ok(value.to_string())
+}
+
+/// Validators serve websockets on the RPC port plus one, and public endpoints on the same host.
+pub fn websocket_url_for(rpc_url: &str) -> Result<String, Box<dyn Error>> {
+    let mut url = Url::parse(rpc_url)?;
+    let scheme = if url.scheme() == "https" { "wss" } else { "ws" };
+    url.set_scheme(scheme).map_err(|_| format!("Cannot derive a websocket URL from {}", rpc_url))?;
+    if let Some(port) = url.port() {
+        url.set_port(Some(port + 1)).map_err(|_| format!("Invalid port in {}", rpc_url))?;
+    }
+    Ok(url.to_string().trim_end_matches('/').to_string())
+}
+
+pub fn config_command(
+    path: &Path,
+    settings: &mut ProgramSettings,
+    wallet: &Wallet,
+    profile: Option<&str>,
+    cmd: &ConfigCommand
+) -> Result<(), Box<dyn Error>> {
+    let name = settings.selected_name(profile);
+    match &cmd.operation {
+        ConfigOperations::Get(cmd) => {
+            let selected = settings.profile(Some(&name))?;
+            match cmd.key {
+                Some(key) => {
+                    let value = selected.get(key)?;
+                    emit(json!({ "profile": name, value_name(&key): value }), || {
+                        println!("{}", format_value(&value))
+                    });
+                }
+                None => {
+                    let value = selected.to_json()?;
+                    emit(json!({ "profile": name, "settings": value }), || {
+                        println!("Profile: {}", name);
+                        print_profile(&value);
+                    });
+                }
+            }
+        }
+        ConfigOperations::Set(cmd) => {
+            let mut updated = match settings.profiles.get(&name) {
+                Some(existing) => existing.clone(),
+                None => Profile::new(Endpoint::Local.into()),
+            };
+            if let (ConfigKey::Keypair, Some(keypair)) = (cmd.key, &cmd.value) {
+                // Stored as given so labels follow the wallet directory.
+                wallet.resolve(Some(keypair))?;
+            }
+            updated.set(cmd.key, cmd.value.as_deref())?;
+            let value = updated.get(cmd.key)?;
+            settings.profiles.insert(name.clone(), updated);
+            settings.save_settings(path)?;
+            let key = value_name(&cmd.key);
+            emit(json!({ "profile": name, key.as_str(): value }), || {
+                match value {
+                    Value::Null => println!("{} cleared in profile {}", key, name),
+                    _ => println!("{} set to {} in profile {}", key, format_value(&value), name),
+                }
+            });
+        }
+        ConfigOperations::List => {
+            let mut profiles = serde_json::Map::new();
+            for (name, profile) in &settings.profiles {
+                profiles.insert(name.clone(), profile.to_json()?);
+            }
+            emit(
+                json!({
+                    "config_file": path.to_string_lossy(),
+                    "active_profile": settings.active_profile,
+                    "profiles": profiles,
+                }),
+                || {
+                    println!("Config file: {}", path.display());
+                    for (name, value) in &profiles {
+                        let marker = if *name == settings.active_profile { "*" } else { " " };
+                        println!("{} {}", marker, name);
+                        print_profile(value);
+                    }
+                }
+            );
+        }
+        ConfigOperations::Use(cmd) => {
+            settings.profile(Some(&cmd.name))?;
+            settings.active_profile = cmd.name.clone();
+            settings.save_settings(path)?;
+            emit(json!({ "active_profile": cmd.name }), || {
+                println!("Active profile set to: {}", cmd.name)
+            });
+        }
+    }
+    Ok(())
+}
+
+fn print_profile(value: &Value) {
+    for key in ConfigKey::value_variants() {
+        let name = value_name(key);
+        println!("    {:<14} {}", name, format_value(&value[name.replace('-', "_")]));
+    }
+}
+
+fn format_value(value: &Value) -> String {
+    match value {
+        Value::Null => "(not set)".to_string(),
+        Value::String(value) => value.clone(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expands_monikers_and_derives_websocket_urls() {
+        assert_eq!(parse_rpc_url("devnet").unwrap(), "https://api.devnet.solana.com");
+        assert_eq!(parse_rpc_url("mainnet-beta").unwrap(), "https://api.mainnet-beta.solana.com");
+        let custom = "https://rpc.example.com/key";
+        assert_eq!(parse_rpc_url(custom).unwrap(), custom);
+        assert!(parse_rpc_url("ftp://example.com").is_err());
+
+        assert_eq!(websocket_url_for("http://127.0.0.1:8899").unwrap(), "ws://127.0.0.1:8900");
+        assert_eq!(
+            websocket_url_for("https://api.devnet.solana.com").unwrap(),
+            "wss://api.devnet.solana.com"
+        );
+    }
+
+    #[test]
+    fn profiles_are_created_and_selected() {
+        let mut settings = ProgramSettings::initial(Some("https://rpc.example.com".to_string()));
+        assert_eq!(settings.profile(None).unwrap().rpc_url, "https://rpc.example.com");
+        assert!(settings.profile(Some("main")).is_err());
+
+        let mut main = Profile::new(Endpoint::Local.into());
+        main.set(ConfigKey::RpcUrl, Some("mainnet-beta")).unwrap();
+        main.set(ConfigKey::Commitment, Some("finalized")).unwrap();
+        main.set(ConfigKey::PriorityFee, Some("5000")).unwrap();
+        assert!(main.set(ConfigKey::RpcUrl, None).is_err());
+        settings.profiles.insert("main".to_string(), main);
+
+        let main = settings.profile(Some("main")).unwrap();
+        assert_eq!(main.commitment, Commitment::Finalized);
+        assert_eq!(main.priority_fee, Some(5000));
+        assert_eq!(main.websocket_url().unwrap(), "wss://api.mainnet-beta.solana.com");
+    }
+
+    #[test]
+    fn keypair_must_name_a_key_file_or_label() {
+        let mut settings = ProgramSettings::initial(None);
+        let wallet = Wallet::new("no-such-wallet", None);
+        let cmd = ConfigCommand {
+            operation: ConfigOperations::Set(ConfigSetCommand {
+                key: ConfigKey::Keypair,
+                value: Some("nobody".to_string()),
+            }),
+        };
+        let path = Path::new("no-such-dir/config.json");
+        assert!(config_command(path, &mut settings, &wallet, None, &cmd).is_err());
+        assert_eq!(settings.profile(None).unwrap().keypair, None);
+    }
+}
@@ -0,0 +1,235 @@
+use futures_util::StreamExt;
+use serde_json::{ json, Value };
+use solana_client::{
+    nonblocking::pubsub_client::PubsubClient,
+    rpc_client::RpcClient,
+    rpc_config::RpcSignatureSubscribeConfig,
+};
+use solana_sdk::{ commitment_config::CommitmentConfig, hash::Hash, signature::Signature };
+use solana_transaction_status::{ TransactionConfirmationStatus, TransactionStatus };
+use std::{
+    error::Error,
+    fmt,
+    sync::mpsc::{ self, Receiver, RecvTimeoutError },
+    thread,
+    time::{ Duration, Instant },
+};
+use tokio::{ runtime, sync::oneshot, time };
+
+use crate::{ output::value_name, state::{ Commitment, TransactionArgs } };
+
+/// First wait between status polls; it doubles after every poll up to `MAX_POLL_INTERVAL`.
+const INITIAL_POLL_INTERVAL: Duration = Duration::from_millis(500);
+const MAX_POLL_INTERVAL: Duration = Duration::from_secs(8);
+
+/// A transaction that reached the requested commitment.
+#[derive(Debug)]
+pub struct Confirmation {
+    pub signature: Signature,
+    pub slot: u64,
+    /// Highest commitment reported by the cluster, at least the one requested.
+    pub commitment: Commitment,
+}
+
+impl Confirmation {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "signature": self.signature.to_string(),
+            "slot": self.slot,
+            "commitment": self.commitment,
+        })
+    }
+}
+
+impl fmt::Display for Confirmation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({} at slot {})", self.signature, value_name(&self.commitment), self.slot)
+    }
+}
+
+/// Waits until `signature` reaches the commitment of the selected profile.
+///
+/// Statuses are polled with exponential backoff. When the profile's websocket endpoint accepts
+/// a `signatureSubscribe`, its notification ends the current wait early. `blockhash` is the
+/// one the transaction was signed with: once the cluster no longer considers it valid the
+/// transaction can never land, and waiting stops. Durable nonce transactions pass `None` and
+/// wait for the full `--confirm-timeout`.
+pub fn confirm_transaction(
+    rpc_client: &RpcClient,
+    options: &TransactionArgs,
+    signature: &Signature,
+    blockhash: Option<&Hash>
+) -> Result<Confirmation, Box<dyn Error>> {
+    let target = options.commitment.config();
+    let deadline = Instant::now() + Duration::from_secs(options.confirm_timeout);
+    let mut notifications = options.websocket_url
+        .as_deref()
+        .map(|url| subscribe(url, signature, target, deadline));
+    let mut interval = INITIAL_POLL_INTERVAL;
+    let mut reached = None;
+
+    loop {
+        if let Some(status) = signature_status(rpc_client, signature)? {
+            if let Some(err) = status.err {
+                return Err(err.into());
+            }
+            reached = status.confirmation_status.as_ref().map(commitment_reached);
+            if status.satisfies_commitment(target) {
+                return Ok(Confirmation {
+                    signature: *signature,
+                    slot: status.slot,
+                    commitment: reached.unwrap_or(Commitment::Finalized),
+                });
+            }
+        } else if let Some(blockhash) = blockhash {
+            if !rpc_client.is_blockhash_valid(blockhash, CommitmentConfig::processed())? {
+                // It may have landed between the two requests.
+                if signature_status(rpc_client, signature)?.is_none() {
+                    return Err(
+                        format!(
+                            "Blockhash expired before {} landed; it is safe to send again",
+                            signature
+                        ).into()
+                    );
+                }
+                continue;
+            }
+        }
+
+        let now = Instant::now();
+        if now >= deadline {
+            let progress = match reached {
+                Some(commitment) => format!("it only reached {}", value_name(&commitment)),
+                None => "it was not seen by the cluster".to_string(),
+            };
+            return Err(
+                format!(
+                    "Transaction {} was not {} within {}s; {}",
+                    signature,
+                    value_name(&options.commitment),
+                    options.confirm_timeout,
+                    progress
+                ).into()
+            );
+        }
+        let wait = interval.min(deadline - now);
+        match notifications.as_ref().map(|watch| watch.notifications.recv_timeout(wait)) {
+            Some(Ok(())) | Some(Err(RecvTimeoutError::Timeout)) => {}
+            // No websocket, or the subscription failed: plain polling.
+            Some(Err(RecvTimeoutError::Disconnected)) => {
+                notifications = None;
+                thread::sleep(wait);
+            }
+            None => thread::sleep(wait),
+        }
+        interval = next_interval(interval);
+    }
+}
+
+fn signature_status(
+    rpc_client: &RpcClient,
+    signature: &Signature
+) -> Result<Option<TransactionStatus>, Box<dyn Error>> {
+    let statuses = rpc_client.get_signature_statuses(&[*signature])?.value;
+    Ok(statuses.into_iter().next().flatten())
+}
+
+/// A `signatureSubscribe` running on its own thread. Dropping it closes the subscription.
+struct SignatureWatch {
+    notifications: Receiver<()>,
+    _stop: oneshot::Sender<()>,
+}
+
+/// Subscribes on a separate thread, so that a slow or unreachable websocket never delays
+/// polling. The watch yields once when the signature reaches `commitment`, and disconnects if
+/// the subscription cannot be made. Once it has yielded, is dropped or `deadline` passes, the
+/// thread unsubscribes, shuts the client down and exits.
+fn subscribe(
+    url: &str,
+    signature: &Signature,
+    commitment: CommitmentConfig,
+    deadline: Instant
+) -> SignatureWatch {
+    let (sender, notifications) = mpsc::channel();
+    let (stop, mut stopped) = oneshot::channel();
+    let url = url.to_string();
+    let signature = *signature;
+    thread::spawn(move || {
+        let Ok(runtime) = runtime::Builder::new_current_thread().enable_all().build() else {
+            return;
+        };
+        runtime.block_on(async move {
+            let expired = time::sleep_until(time::Instant::from_std(deadline));
+            tokio::pin!(expired);
+            let client = tokio::select! {
+                client = PubsubClient::new(&url) => client,
+                _ = &mut stopped => return,
+                _ = &mut expired => return,
+            };
+            let Ok(client) = client else {
+                return;
+            };
+            let config = RpcSignatureSubscribeConfig {
+                commitment: Some(commitment),
+                enable_received_notification: Some(false),
+            };
+            if let Ok((mut updates, unsubscribe)) = client.signature_subscribe(
+                &signature,
+                Some(config)
+            ).await {
+                tokio::select! {
+                    Some(_) = updates.next() => {
+                        let _ = sender.send(());
+                    }
+                    _ = &mut stopped => {}
+                    _ = &mut expired => {}
+                }
+                drop(updates);
+                unsubscribe().await;
+            }
+            let _ = client.shutdown().await;
+        });
+    });
+    SignatureWatch { notifications, _stop: stop }
+}
+
+fn next_interval(interval: Duration) -> Duration {
+    (interval * 2).min(MAX_POLL_INTERVAL)
+}
+
+fn commitment_reached(status: &TransactionConfirmationStatus) -> Commitment {
+    match status {
+        TransactionConfirmationStatus::Processed => Commitment::Processed,
+        TransactionConfirmationStatus::Confirmed => Commitment::Confirmed,
+        TransactionConfirmationStatus::Finalized => Commitment::Finalized,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_the_maximum() {
+        let mut interval = INITIAL_POLL_INTERVAL;
+        let mut waits = Vec::new();
+        for _ in 0..6 {
+            waits.push(interval.as_millis());
+            interval = next_interval(interval);
+        }
+        assert_eq!(waits, [500, 1000, 2000, 4000, 8000, 8000]);
+    }
+
+    #[test]
+    fn unreachable_websockets_disconnect_the_watch() {
+        let deadline = Instant::now() + Duration::from_secs(30);
+        let watch = subscribe(
+            "ws://127.0.0.1:1",
+            &Signature::default(),
+            CommitmentConfig::confirmed(),
+            deadline
+        );
+        let received = watch.notifications.recv_timeout(Duration::from_secs(10));
+        assert_eq!(received, Err(RecvTimeoutError::Disconnected));
+    }
+}
@@ -0,0 +1,407 @@
+use serde::Serialize;
+use serde_json::json;
+use solana_client::{
+    rpc_client::{ GetConfirmedSignaturesForAddress2Config, RpcClient },
+    rpc_config::RpcTransactionConfig,
+};
+use solana_sdk::{ commitment_config::CommitmentConfig, pubkey::Pubkey, signature::Signature };
+use solana_transaction_status::{
+    EncodedConfirmedTransactionWithStatusMeta,
+    EncodedTransaction,
+    UiMessage,
+    UiTransactionEncoding,
+};
+use std::{ collections::BTreeMap, error::Error, fs, str::FromStr };
+
+use crate::{
+    amount::SOL_DECIMALS,
+    keystore::*,
+    output::emit,
+    state::*,
+    token::format_token_amount,
+    wallet::Wallet,
+};
+
+/// Largest page `getSignaturesForAddress` returns.
+const PAGE_SIZE: usize = 1000;
+const SOL_ASSET: &str = "SOL";
+
+/// What one transaction did to the wallet's balances.
+#[derive(Serialize, Debug)]
+pub struct HistoryEntry {
+    pub signature: String,
+    pub slot: u64,
+    pub block_time: Option<i64>,
+    pub success: bool,
+    pub error: Option<String>,
+    /// Fee in lamports, when the wallet paid it. The SOL change already includes it.
+    pub fee: u64,
+    pub changes: Vec<BalanceChange>,
+}
+
+/// Net change of SOL or of one SPL mint, as a signed decimal string.
+#[derive(Serialize, Debug)]
+pub struct BalanceChange {
+    pub asset: String,
+    pub amount: String,
+    pub decimals: u8,
+}
+
+pub fn show_history(
+    rpc_client: &RpcClient,
+    wallet: &Wallet,
+    cmd: &HistoryCommand
+) -> Result<(), Box<dyn Error>> {
+    let address = match cmd.wallet.as_deref().map(Pubkey::from_str) {
+        Some(Ok(address)) => address,
+        _ => read_public_key(&wallet.resolve(cmd.wallet.as_deref())?)?,
+    };
+    let before = cmd.before.as_deref().map(Signature::from_str).transpose()?;
+    let until = cmd.until.as_deref().map(Signature::from_str).transpose()?;
+
+    let signatures = fetch_signatures(rpc_client, &address, before, until, cmd.limit)?;
+    let mut entries = Vec::with_capacity(signatures.len());
+    for signature in &signatures {
+        let transaction = rpc_client.get_transaction_with_config(
+            signature,
+            RpcTransactionConfig {
+                encoding: Some(UiTransactionEncoding::JsonParsed),
+                commitment: Some(CommitmentConfig::confirmed()),
+                max_supported_transaction_version: Some(0),
+            }
+        )?;
+        entries.push(summarize_transaction(&address, signature, &transaction)?);
+    }
+
+    if let Some(path) = &cmd.csv {
+        fs::write(path, history_csv(&entries))?;
+    }
+    if let Some(path) = &cmd.json {
+        fs::write(path, serde_json::to_string_pretty(&entries)?)?;
+    }
+    emit(
+        json!({
+            "address": address.to_string(),
+            "transactions": entries,
+            "csv": cmd.csv,
+            "json": cmd.json,
+        }),
+        || {
+            if entries.is_empty() {
+                println!("No transactions found for {}", address);
+            }
+            for entry in &entries {
+                print_entry(entry);
+            }
+            if let Some(path) = &cmd.csv {
+                println!("CSV written to: {}", path);
+            }
+            if let Some(path) = &cmd.json {
+                println!("JSON written to: {}", path);
+            }
+        }
+    );
+    Ok(())
+}
+
+/// Pages backwards from `before` (or the newest transaction) until `limit` signatures are
+/// collected or `until` is reached. Signatures are returned newest first.
+pub fn fetch_signatures(
+    rpc_client: &RpcClient,
+    address: &Pubkey,
+    mut before: Option<Signature>,
+    until: Option<Signature>,
+    limit: usize
+) -> Result<Vec<Signature>, Box<dyn Error>> {
+    let mut signatures = Vec::new();
+    while signatures.len() < limit {
+        let requested = (limit - signatures.len()).min(PAGE_SIZE);
+        let page = rpc_client.get_signatures_for_address_with_config(
+            address,
+            GetConfirmedSignaturesForAddress2Config {
+                before,
+                until,
+                limit: Some(requested),
+                commitment: Some(CommitmentConfig::confirmed()),
+            }
+        )?;
+        for status in &page {
+            signatures.push(Signature::from_str(&status.signature)?);
+        }
+        if page.len() < requested {
+            break;
+        }
+        before = signatures.last().copied();
+    }
+    Ok(signatures)
+}
+
+/// Computes the SOL and SPL balance deltas of `address` from the transaction's status metadata.
+/// Token accounts count when `address` owns them or is the token account itself.
+pub fn summarize_transaction(
+    address: &Pubkey,
+    signature: &Signature,
+    transaction: &EncodedConfirmedTransactionWithStatusMeta
+) -> Result<HistoryEntry, Box<dyn Error>> {
+    let meta = transaction.transaction.meta
+        .as_ref()
+        .ok_or_else(|| format!("Transaction {} has no status metadata", signature))?;
+    let account_keys = account_keys(signature, transaction)?;
+
+    let address = address.to_string();
+    let index = account_keys.iter().position(|key| *key == address);
+    let mut changes = Vec::new();
+    let balances = index.and_then(|index| {
+        Some((*meta.pre_balances.get(index)?, *meta.post_balances.get(index)?))
+    });
+    if let Some((pre, post)) = balances {
+        let lamports = (post as i128) - (pre as i128);
+        if lamports != 0 {
+            changes.push(BalanceChange {
+                asset: SOL_ASSET.to_string(),
+                amount: format_signed_amount(lamports, SOL_DECIMALS),
+                decimals: SOL_DECIMALS,
+            });
+        }
+    }
+
+    let mut token_deltas: BTreeMap<String, (i128, u8)> = BTreeMap::new();
+    let token_balances = [
+        (-1, meta.pre_token_balances.as_ref().map(Clone::clone)),
+        (1, meta.post_token_balances.as_ref().map(Clone::clone)),
+    ];
+    for (sign, balances) in token_balances {
+        for balance in balances.unwrap_or_default() {
+            let owned =
+                balance.owner.as_ref().map(|owner| *owner == address).unwrap_or(false) ||
+                account_keys.get(balance.account_index as usize) == Some(&address);
+            if !owned {
+                continue;
+            }
+            let amount: i128 = balance.ui_token_amount.amount.parse()?;
+            let delta = token_deltas
+                .entry(balance.mint)
+                .or_insert((0, balance.ui_token_amount.decimals));
+            delta.0 += sign * amount;
+        }
+    }
+    for (mint, (amount, decimals)) in token_deltas {
+        if amount != 0 {
+            changes.push(BalanceChange {
+                asset: mint,
+                amount: format_signed_amount(amount, decimals),
+                decimals,
+            });
+        }
+    }
+
+    Ok(HistoryEntry {
+        signature: signature.to_string(),
+        slot: transaction.slot,
+        block_time: transaction.block_time,
+        success: meta.err.is_none(),
+        error: meta.err.as_ref().map(|err| err.to_string()),
+        fee: if index == Some(0) { meta.fee } else { 0 },
+        changes,
+    })
+}
+
+/// All account keys of a transaction returned as JSON, including those loaded from address
+/// lookup tables, in the order balances and instructions index them.
+pub fn account_keys(
+    signature: &Signature,
+    transaction: &EncodedConfirmedTransactionWithStatusMeta
+) -> Result<Vec<String>, Box<dyn Error>> {
+    let EncodedTransaction::Json(ui_transaction) = &transaction.transaction.transaction else {
+        return Err(format!("Transaction {} was not returned as JSON", signature).into());
+    };
+    Ok(match &ui_transaction.message {
+        UiMessage::Parsed(message) => {
+            message.account_keys
+                .iter()
+                .map(|account| account.pubkey.clone())
+                .collect()
+        }
+        UiMessage::Raw(message) => {
+            let mut keys = message.account_keys.clone();
+            let loaded = transaction.transaction.meta
+                .as_ref()
+                .and_then(|meta| meta.loaded_addresses.as_ref().map(Clone::clone));
+            if let Some(loaded) = loaded {
+                keys.extend(loaded.writable);
+                keys.extend(loaded.readonly);
+            }
+            keys
+        }
+    })
+}
+
+fn print_entry(entry: &HistoryEntry) {
+    println!(
+        "{}  {}  {}",
+        entry.block_time.map_or_else(|| "unknown time".to_string(), format_timestamp),
+        entry.signature,
+        if entry.success { "ok" } else { "failed" }
+    );
+    if entry.fee > 0 {
+        println!(
+            "    fee  {} SOL (included in the SOL change)",
+            format_token_amount(entry.fee, SOL_DECIMALS)
+        );
+    }
+    for change in &entry.changes {
+        println!("    {:<44}  {}", change.asset, change.amount);
+    }
+}
+
+/// One row per balance change; transactions without changes get a single row with no asset.
+/// `fee_included` is informational: the SOL `amount` already has the fee taken out, so summing
+/// `amount` reconciles the balance on its own.
+pub fn history_csv(entries: &[HistoryEntry]) -> String {
+    let mut csv = String::from(
+        "signature,slot,block_time,utc_time,success,fee_included,asset,amount\n"
+    );
+    for entry in entries {
+        let prefix = format!(
+            "{},{},{},{},{},{}",
+            entry.signature,
+            entry.slot,
+            entry.block_time.map(|time| time.to_string()).unwrap_or_default(),
+            entry.block_time.map(format_timestamp).unwrap_or_default(),
+            entry.success,
+            format_token_amount(entry.fee, SOL_DECIMALS)
+        );
+        if entry.changes.is_empty() {
+            csv.push_str(&format!("{},,\n", prefix));
+        }
+        for change in &entry.changes {
+            csv.push_str(&format!("{},{},{}\n", prefix, change.asset, change.amount));
+        }
+    }
+    csv
+}
+
+fn format_signed_amount(amount: i128, decimals: u8) -> String {
+    let sign = if amount < 0 { "-" } else { "+" };
+    format!("{}{}", sign, format_token_amount(amount.unsigned_abs() as u64, decimals))
+}
+
+/// Formats a unix timestamp as `YYYY-MM-DD HH:MM:SS` UTC.
+pub fn format_timestamp(timestamp: i64) -> String {
+    let days = timestamp.div_euclid(86_400);
+    let seconds = timestamp.rem_euclid(86_400);
+    // Civil-from-days conversion for the proleptic Gregorian calendar.
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + ((month <= 2) as i64);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        (seconds % 3600) / 60,
+        seconds % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WALLET: &str = "HAgk14JpMQLgt6rVgv7cBQFJWFto5Dqxi472uT3DKpqk";
+    const OTHER: &str = "6CoQUrRrm5VoWCVEBju3pNiwULKTTdPXotYHECfomJtn";
+    const TOKEN_ACCOUNT: &str = "AJ711G562a3nm2Dg6kt9YtGWVwpbprcuSi37CKPwhWqL";
+    const MINT: &str = "So11111111111111111111111111111111111111112";
+
+    fn parsed_account(pubkey: &str, signer: bool) -> serde_json::Value {
+        serde_json::json!({
+            "pubkey": pubkey,
+            "writable": true,
+            "signer": signer,
+            "source": "transaction"
+        })
+    }
+
+    fn token_balance(amount: &str) -> serde_json::Value {
+        serde_json::json!([{
+            "accountIndex": 2,
+            "mint": MINT,
+            "owner": WALLET,
+            "uiTokenAmount": { "amount": amount, "decimals": 6, "uiAmountString": "" }
+        }])
+    }
+
+    /// A `getTransaction` response in which WALLET pays the fee, sends 0.5 SOL to OTHER and
+    /// burns 1.5 tokens from its TOKEN_ACCOUNT.
+    fn transaction() -> EncodedConfirmedTransactionWithStatusMeta {
+        let json =
+            serde_json::json!({
+            "slot": 42,
+            "blockTime": 1_720_000_000,
+            "transaction": {
+                "signatures": [Signature::default().to_string()],
+                "message": {
+                    "accountKeys": [
+                        parsed_account(WALLET, true),
+                        parsed_account(OTHER, false),
+                        parsed_account(TOKEN_ACCOUNT, false)
+                    ],
+                    "recentBlockhash": "11111111111111111111111111111111",
+                    "instructions": []
+                }
+            },
+            "meta": {
+                "err": null,
+                "status": { "Ok": null },
+                "fee": 5000,
+                "preBalances": [2_000_000_000u64, 0, 2_039_280],
+                "postBalances": [1_499_995_000u64, 500_000_000, 2_039_280],
+                "innerInstructions": [],
+                "logMessages": [],
+                "preTokenBalances": token_balance("2500000"),
+                "postTokenBalances": token_balance("1000000")
+            }
+        });
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn summarizes_sol_and_token_deltas() {
+        let address = Pubkey::from_str(WALLET).unwrap();
+        let entry = summarize_transaction(&address, &Signature::default(), &transaction()).unwrap();
+        assert!(entry.success);
+        assert_eq!(entry.fee, 5000);
+        let changes: Vec<_> = entry.changes
+            .iter()
+            .map(|change| (change.asset.as_str(), change.amount.as_str()))
+            .collect();
+        assert_eq!(changes, [("SOL", "-0.500005"), (MINT, "-1.5")]);
+
+        let csv = history_csv(&[entry]);
+        assert!(csv.starts_with("signature,slot,block_time,utc_time,success,fee_included,"));
+        let row = csv.lines().nth(1).unwrap();
+        assert!(row.ends_with("2024-07-03 09:46:40,true,0.000005,SOL,-0.500005"));
+        assert_eq!(csv.lines().count(), 3);
+
+        let other = Pubkey::from_str(OTHER).unwrap();
+        let entry = summarize_transaction(&other, &Signature::default(), &transaction()).unwrap();
+        assert_eq!(entry.fee, 0);
+        assert_eq!(entry.changes.len(), 1);
+        assert_eq!(entry.changes[0].amount, "+0.5");
+    }
+
+    #[test]
+    fn formats_timestamps_in_utc() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00:00");
+        assert_eq!(format_timestamp(951_782_400), "2000-02-29 00:00:00");
+        assert_eq!(format_timestamp(-1), "1969-12-31 23:59:59");
+    }
+}
@@ -0,0 +1,540 @@
+use borsh::{ BorshDeserialize, BorshSerialize };
+use solana_client::{ nonce_utils, rpc_client::RpcClient };
+use solana_sdk::{
+    account::Account,
+    bpf_loader,
+    bpf_loader_upgradeable,
+    pubkey::Pubkey,
+    system_program,
+};
+use spl_token_2022::{
+    extension::{ BaseStateWithExtensions, StateWithExtensions },
+    state::{ Account as TokenAccount, Mint },
+};
+use serde_json::{ json, Value };
+use std::{ error::Error, str::FromStr };
+
+use crate::{
+    amount::SOL_DECIMALS,
+    cnft::{ BUBBLEGUM_PROGRAM_ID, COMPRESSION_PROGRAM_ID, NOOP_PROGRAM_ID },
+    history::format_timestamp,
+    native_vault::{ decode_total_deposits, VaultState, DEPOSIT_ACCOUNT_SPACE },
+    output::emit,
+    state::*,
+    token::{ format_authority, format_token_amount, is_token_program, program_name },
+    vault::{ account_discriminator, decode_anchor_account, Vault, VaultUser },
+};
+
+/// Bytes of a `VaultUser` after its discriminator. The s5 `User` shares the Anchor name, but
+/// its account is allocated smaller than this.
+const VAULT_USER_LEN: usize = 1 + 32 + 8 + 32 + 8 + 8;
+
+/// `User` account of the s5 secure program.
+#[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq)]
+pub struct PointsUser {
+    pub id: u32,
+    pub owner: Pubkey,
+    pub name: String,
+    pub points: u16,
+}
+
+/// Account data decoded by `fetch`: the layout's name, its fields as JSON, and as text lines.
+/// `guessed` is set when only the data size identified the layout.
+pub struct Decoded {
+    pub layout: &'static str,
+    pub fields: Value,
+    pub lines: Vec<String>,
+    pub guessed: bool,
+}
+
+fn owner_name(owner: &Pubkey) -> Option<&'static str> {
+    if *owner == system_program::id() {
+        Some("System Program")
+    } else if is_token_program(owner) {
+        Some(if *owner == spl_token::id() { "Token Program" } else { "Token-2022 Program" })
+    } else if *owner == bpf_loader_upgradeable::id() {
+        Some("BPF Upgradeable Loader")
+    } else if *owner == bpf_loader::id() {
+        Some("BPF Loader")
+    } else if *owner == BUBBLEGUM_PROGRAM_ID {
+        Some("Bubblegum")
+    } else if *owner == COMPRESSION_PROGRAM_ID {
+        Some("Account Compression")
+    } else if *owner == NOOP_PROGRAM_ID {
+        Some("Noop")
+    } else {
+        None
+    }
+}
+
+/// Decodes the layouts `fetch` knows, by owner for system and token accounts and by
+/// discriminator or size for the course programs, which may be deployed anywhere.
+/// `program_id` is where the s4 program is deployed, if known. `mint_decimals` looks up the
+/// decimals a token account's balance is shown with.
+pub fn decode_account(
+    account: &Account,
+    program_id: Option<&Pubkey>,
+    mint_decimals: impl Fn(&Pubkey) -> Option<u8>
+) -> Option<Decoded> {
+    let data = &account.data;
+    if account.owner == system_program::id() {
+        if data.is_empty() {
+            return Some(Decoded {
+                layout: "system",
+                fields: json!({}),
+                lines: vec!["System account with no data".to_string()],
+                guessed: false,
+            });
+        }
+        let nonce = nonce_utils::data_from_account(account).ok()?;
+        return Some(Decoded {
+            layout: "nonce",
+            fields: json!({
+                "authority": nonce.authority.to_string(),
+                "nonce": nonce.blockhash().to_string(),
+                "lamports_per_signature": nonce.fee_calculator.lamports_per_signature,
+            }),
+            lines: vec![
+                format!("Nonce authority: {}", nonce.authority),
+                format!("Nonce: {}", nonce.blockhash()),
+                format!(
+                    "Fee: {} lamports per signature",
+                    nonce.fee_calculator.lamports_per_signature
+                )
+            ],
+            guessed: false,
+        });
+    }
+    if is_token_program(&account.owner) {
+        return decode_token_account(account, mint_decimals);
+    }
+    if data.len() >= 8 && data[..8] == account_discriminator("Vault") {
+        let vault: Vault = decode_anchor_account("Vault", data).ok()?;
+        return Some(Decoded {
+            layout: "s3-vault",
+            fields: json!({
+                "name": vault.name,
+                "authority": vault.authority.to_string(),
+                "bump": vault.bump,
+                "created": vault.ts,
+                "net_deposits": vault.net_deposits.to_string(),
+                "net_withdraws": vault.net_withdraws.to_string(),
+                "held": vault.held().to_string(),
+            }),
+            lines: vec![
+                format!("Vault '{}' of the s3 vault program", vault.name),
+                format!("Authority: {}", vault.authority),
+                format!("Created: {} UTC", format_timestamp(vault.ts)),
+                format!("Net deposits: {}", vault.net_deposits),
+                format!("Net withdraws: {}", vault.net_withdraws),
+                format!("Held: {} (base units of the vault's mint)", vault.held())
+            ],
+            guessed: false,
+        });
+    }
+    if data.len() >= 8 && data[..8] == account_discriminator("User") {
+        if data.len() >= 8 + VAULT_USER_LEN {
+            let user: VaultUser = decode_anchor_account("User", data).ok()?;
+            return Some(Decoded {
+                layout: "s3-vault-user",
+                fields: json!({
+                    "vault": user.vault.to_string(),
+                    "authority": user.authority.to_string(),
+                    "bump": user.bump,
+                    "last_activity": user.ts,
+                    "net_deposit": user.net_deposit,
+                    "net_withdraw": user.net_withdraw,
+                    "held": user.held(),
+                }),
+                lines: vec![
+                    format!("User account of the s3 vault program in vault {}", user.vault),
+                    format!("Authority: {}", user.authority),
+                    format!("Net deposit: {}", user.net_deposit),
+                    format!("Net withdraw: {}", user.net_withdraw),
+                    format!("Last activity: {} UTC", format_timestamp(user.ts))
+                ],
+                guessed: false,
+            });
+        }
+        let user: PointsUser = decode_anchor_account("User", data).ok()?;
+        return Some(Decoded {
+            layout: "s5-user",
+            fields: json!({
+                "id": user.id,
+                "owner": user.owner.to_string(),
+                "name": user.name,
+                "points": user.points,
+            }),
+            lines: vec![
+                format!("User {} of the s5 secure program: '{}'", user.id, user.name),
+                format!("Owner: {}", user.owner),
+                format!("Points: {}", user.points)
+            ],
+            guessed: false,
+        });
+    }
+    // The s4 program's accounts have no discriminator; only their size identifies them, so
+    // the layout is a guess unless the owner is the given s4 program.
+    let guessed = match program_id {
+        Some(program_id) if *program_id == account.owner => false,
+        Some(_) => {
+            return None;
+        }
+        None => true,
+    };
+    let mut decoded = decode_native_vault(data)?;
+    if guessed {
+        let note = "Layout guessed from the data size; pass --program-id to confirm";
+        decoded.lines.push(note.to_string());
+    }
+    decoded.guessed = guessed;
+    Some(decoded)
+}
+
+/// A deposit account of the s4 native vault program, or its `VaultState`.
+fn decode_native_vault(data: &[u8]) -> Option<Decoded> {
+    if data.len() == DEPOSIT_ACCOUNT_SPACE {
+        let total_deposits = decode_total_deposits(data).ok()?;
+        return Some(Decoded {
+            layout: "s4-deposit",
+            fields: json!({ "total_deposits": total_deposits }),
+            lines: vec![
+                "Deposit account of the s4 native vault program".to_string(),
+                format!(
+                    "Total deposits: {} SOL",
+                    format_token_amount(total_deposits, SOL_DECIMALS)
+                )
+            ],
+            guessed: false,
+        });
+    }
+    let state = VaultState::try_from_slice(data).ok()?;
+    Some(Decoded {
+        layout: "s4-vault-state",
+        fields: json!({
+            "is_initialized": state.is_initialized,
+            "owner": state.owner.to_string(),
+            "total_deposits": state.total_deposits,
+        }),
+        lines: vec![
+            "Vault state of the s4 native vault program".to_string(),
+            format!("Initialized: {}", if state.is_initialized { "yes" } else { "no" }),
+            format!("Owner: {}", state.owner),
+            format!(
+                "Total deposits: {} SOL",
+                format_token_amount(state.total_deposits, SOL_DECIMALS)
+            )
+        ],
+        guessed: false,
+    })
+}
+
+fn decode_token_account(
+    account: &Account,
+    mint_decimals: impl Fn(&Pubkey) -> Option<u8>
+) -> Option<Decoded> {
+    let program = program_name(&account.owner);
+    if let Ok(token_account) = StateWithExtensions::<TokenAccount>::unpack(&account.data) {
+        let extensions: Vec<String> = token_account
+            .get_extension_types()
+            .ok()?
+            .iter()
+            .map(|extension| format!("{:?}", extension))
+            .collect();
+        let base = &token_account.base;
+        let decimals = mint_decimals(&base.mint);
+        let amount = decimals.map(|decimals| format_token_amount(base.amount, decimals));
+        let delegate = Option::<Pubkey>::from(base.delegate);
+        let close_authority = Option::<Pubkey>::from(base.close_authority);
+        let mut lines = vec![
+            format!("{} token account", program),
+            format!("Mint: {}", base.mint),
+            format!("Owner: {}", base.owner),
+            match &amount {
+                Some(amount) => format!("Balance: {} ({} base units)", amount, base.amount),
+                None => format!("Balance: {} base units", base.amount),
+            },
+            format!("State: {:?}", base.state)
+        ];
+        if let Some(delegate) = delegate {
+            lines.push(format!("Delegate: {} ({} base units)", delegate, base.delegated_amount));
+        }
+        if let Some(close_authority) = close_authority {
+            lines.push(format!("Close authority: {}", close_authority));
+        }
+        if !extensions.is_empty() {
+            lines.push(format!("Extensions: {}", extensions.join(", ")));
+        }
+        return Some(Decoded {
+            layout: "spl-token-account",
+            fields: json!({
+                "mint": base.mint.to_string(),
+                "owner": base.owner.to_string(),
+                "amount": amount,
+                "base_units": base.amount,
+                "state": format!("{:?}", base.state),
+                "native": base.is_native(),
+                "delegate": delegate.map(|delegate| delegate.to_string()),
+                "delegated_amount": base.delegated_amount,
+                "close_authority": close_authority.map(|authority| authority.to_string()),
+                "extensions": extensions,
+            }),
+            lines,
+            guessed: false,
+        });
+    }
+    let mint = StateWithExtensions::<Mint>::unpack(&account.data).ok()?;
+    let extensions: Vec<String> = mint
+        .get_extension_types()
+        .ok()?
+        .iter()
+        .map(|extension| format!("{:?}", extension))
+        .collect();
+    let base = &mint.base;
+    let supply = format_token_amount(base.supply, base.decimals);
+    let mint_authority = format_authority(base.mint_authority.into());
+    let freeze_authority = format_authority(base.freeze_authority.into());
+    let mut lines = vec![
+        format!("{} mint", program),
+        format!("Supply: {}", supply),
+        format!("Decimals: {}", base.decimals),
+        format!("Mint authority: {}", mint_authority),
+        format!("Freeze authority: {}", freeze_authority)
+    ];
+    if !extensions.is_empty() {
+        lines.push(format!("Extensions: {}", extensions.join(", ")));
+    }
+    Some(Decoded {
+        layout: "spl-token-mint",
+        fields: json!({
+            "supply": supply,
+            "base_units": base.supply,
+            "decimals": base.decimals,
+            "mint_authority": mint_authority,
+            "freeze_authority": freeze_authority,
+            "extensions": extensions,
+        }),
+        lines,
+        guessed: false,
+    })
+}
+
+/// `xxd`-style lines of at most `limit` bytes: offset, hex bytes and printable ASCII.
+pub fn hexdump(data: &[u8], limit: usize) -> Vec<String> {
+    data[..data.len().min(limit)]
+        .chunks(16)
+        .enumerate()
+        .map(|(line, chunk)| {
+            let hex: Vec<String> = chunk
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect();
+            let ascii: String = chunk
+                .iter()
+                .map(|byte| {
+                    if byte.is_ascii_graphic() || *byte == b' ' { *byte as char } else { '.' }
+                })
+                .collect();
+            format!("{:08x}  {:<47}  |{}|", line * 16, hex.join(" "), ascii)
+        })
+        .collect()
+}
+
+/// Shows an account's owner, balance, rent status and size, with its data decoded when the
+/// layout is known and as a hexdump otherwise.
+pub fn inspect_account(rpc_client: &RpcClient, cmd: &FetchCommand) -> Result<(), Box<dyn Error>> {
+    let address = Pubkey::from_str(&cmd.wallet)?;
+    let program_id = cmd.program_id.as_deref().map(Pubkey::from_str).transpose()?;
+    let account = rpc_client
+        .get_account_with_commitment(&address, rpc_client.commitment())?
+        .value.ok_or_else(|| format!("No account exists at {}", address))?;
+    let minimum = rpc_client.get_minimum_balance_for_rent_exemption(account.data.len())?;
+    let rent_exempt = account.lamports >= minimum;
+    let decoded = decode_account(&account, program_id.as_ref(), |mint| {
+        let account = rpc_client.get_account(mint).ok()?;
+        Some(StateWithExtensions::<Mint>::unpack(&account.data).ok()?.base.decimals)
+    });
+    let dump = match &decoded {
+        Some(_) => Vec::new(),
+        None => hexdump(&account.data, cmd.dump_limit),
+    };
+    let owner_name = owner_name(&account.owner);
+
+    emit(
+        json!({
+            "address": address.to_string(),
+            "owner": account.owner.to_string(),
+            "owner_name": owner_name,
+            "lamports": account.lamports,
+            "sol": format_token_amount(account.lamports, SOL_DECIMALS),
+            "rent_exempt": rent_exempt,
+            "rent_exempt_minimum": minimum,
+            "executable": account.executable,
+            "data_size": account.data.len(),
+            "layout": decoded.as_ref().map_or("unknown", |decoded| decoded.layout),
+            "decoded": decoded.as_ref().map(|decoded| &decoded.fields),
+            "guessed": decoded.as_ref().is_some_and(|decoded| decoded.guessed),
+            "hexdump": decoded.is_none().then_some(&dump),
+        }),
+        || {
+            println!("Address: {}", address);
+            match owner_name {
+                Some(name) => println!("Owner: {} ({})", account.owner, name),
+                None => println!("Owner: {}", account.owner),
+            }
+            println!(
+                "Balance: {} SOL ({} lamports)",
+                format_token_amount(account.lamports, SOL_DECIMALS),
+                account.lamports
+            );
+            println!(
+                "Rent exempt: {} (minimum {} SOL)",
+                if rent_exempt { "yes" } else { "no" },
+                format_token_amount(minimum, SOL_DECIMALS)
+            );
+            println!("Executable: {}", if account.executable { "yes" } else { "no" });
+            println!("Data: {} bytes", account.data.len());
+            match &decoded {
+                Some(decoded) => {
+                    for line in &decoded.lines {
+                        println!("{}", line);
+                    }
+                }
+                None => {
+                    for line in &dump {
+                        println!("{}", line);
+                    }
+                    if account.data.len() > cmd.dump_limit {
+                        println!("... {} more bytes", account.data.len() - cmd.dump_limit);
+                    }
+                }
+            }
+        }
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::program_pack::Pack;
+
+    fn account(owner: Pubkey, data: Vec<u8>) -> Account {
+        Account { lamports: 1_000_000, data, owner, executable: false, rent_epoch: 0 }
+    }
+
+    fn layout(account: &Account) -> Option<&'static str> {
+        decode_account(account, None, |_| Some(6)).map(|decoded| decoded.layout)
+    }
+
+    #[test]
+    fn decodes_known_layouts() {
+        let program = Pubkey::new_unique();
+        assert_eq!(layout(&account(system_program::id(), Vec::new())), Some("system"));
+
+        let mut data = vec![0; Mint::LEN];
+        let mint = Mint { decimals: 6, supply: 1_500_000, is_initialized: true, ..Mint::default() };
+        mint.pack_into_slice(&mut data);
+        let decoded = decode_account(&account(spl_token::id(), data), None, |_| None).unwrap();
+        assert_eq!(decoded.layout, "spl-token-mint");
+        assert_eq!(decoded.fields["supply"], "1.5");
+
+        let mut data = vec![0; TokenAccount::LEN];
+        let token_account = TokenAccount {
+            mint: Pubkey::new_unique(),
+            owner: Pubkey::new_unique(),
+            amount: 2_000_000,
+            state: spl_token_2022::state::AccountState::Initialized,
+            ..TokenAccount::default()
+        };
+        token_account.pack_into_slice(&mut data);
+        let decoded = decode_account(&account(spl_token::id(), data), None, |_| Some(6)).unwrap();
+        assert_eq!(decoded.layout, "spl-token-account");
+        assert_eq!(decoded.fields["amount"], "2");
+
+        let vault = Vault {
+            bump: 255,
+            authority: Pubkey::new_unique(),
+            name: "savings".to_string(),
+            ts: 1_700_000_000,
+            net_deposits: 10,
+            net_withdraws: 4,
+        };
+        let mut data = account_discriminator("Vault").to_vec();
+        data.extend(borsh::to_vec(&vault).unwrap());
+        data.resize(8 + 100, 0);
+        assert_eq!(layout(&account(program, data)), Some("s3-vault"));
+
+        let user = VaultUser {
+            bump: 254,
+            authority: Pubkey::new_unique(),
+            ts: 1_700_000_000,
+            vault: Pubkey::new_unique(),
+            net_deposit: 5,
+            net_withdraw: 1,
+        };
+        let mut data = account_discriminator("User").to_vec();
+        data.extend(borsh::to_vec(&user).unwrap());
+        data.resize(8 + 96, 0);
+        assert_eq!(layout(&account(program, data)), Some("s3-vault-user"));
+
+        let user = PointsUser {
+            id: 7,
+            owner: Pubkey::new_unique(),
+            name: "alice".to_string(),
+            points: 1000,
+        };
+        let mut data = account_discriminator("User").to_vec();
+        data.extend(borsh::to_vec(&user).unwrap());
+        data.resize(8 + 4 + 32 + (4 + 10) + 2, 0);
+        let decoded = decode_account(&account(program, data), None, |_| None).unwrap();
+        assert_eq!(decoded.layout, "s5-user");
+        assert_eq!(decoded.fields["points"], 1000);
+
+        let state = VaultState { is_initialized: true, owner: program, total_deposits: 42 };
+        let data = borsh::to_vec(&state).unwrap();
+        assert_eq!(layout(&account(program, data)), Some("s4-vault-state"));
+
+        assert_eq!(layout(&account(program, vec![7; 41])), None);
+        assert_eq!(layout(&account(program, vec![1, 2, 3])), None);
+    }
+
+    #[test]
+    fn size_matched_layouts_are_guesses_unless_the_program_is_given() {
+        let program = Pubkey::new_unique();
+        // What the s4 program's `Initialize` and `DepositInstruction` leave in an account.
+        let deposit = account(program, 2_500_000_000u64.to_le_bytes().to_vec());
+
+        let decoded = decode_account(&deposit, Some(&program), |_| None).unwrap();
+        assert_eq!(decoded.layout, "s4-deposit");
+        assert_eq!(decoded.fields["total_deposits"], 2_500_000_000u64);
+        assert_eq!(decoded.lines[1], "Total deposits: 2.5 SOL");
+        assert!(!decoded.guessed);
+
+        let decoded = decode_account(&deposit, None, |_| None).unwrap();
+        assert_eq!(decoded.layout, "s4-deposit");
+        assert!(decoded.guessed);
+
+        let other = Pubkey::new_unique();
+        assert!(decode_account(&deposit, Some(&other), |_| None).is_none());
+        let state = VaultState { is_initialized: true, owner: other, total_deposits: 1 };
+        let state = borsh::to_vec(&state).unwrap();
+        assert!(decode_account(&account(other, state.clone()), Some(&program), |_| None).is_none());
+        assert!(decode_account(&account(other, state), None, |_| None).unwrap().guessed);
+    }
+
+    #[test]
+    fn dumps_unknown_data() {
+        let mut data = b"Hello, world!".to_vec();
+        data.extend([0, 255, 16, 32]);
+        let lines = hexdump(&data, 256);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            lines[0],
+            "00000000  48 65 6c 6c 6f 2c 20 77 6f 72 6c 64 21 00 ff 10  |Hello, world!...|"
+        );
+        assert_eq!(lines[1], format!("00000010  20{}  | |", " ".repeat(45)));
+        let short = format!("00000000  48 65 6c 6c{}  |Hell|", " ".repeat(36));
+        assert_eq!(hexdump(&data, 4), vec![short]);
+    }
+}
@@ -0,0 +1,146 @@
+use solana_sdk::{ pubkey::Pubkey, signature::Keypair, signer::Signer };
+use std::{ error::Error, str::FromStr };
+
+use crate::{ keystore::*, state::* };
+
+/// Key material read by `import`, before it is written to the wallet.
+pub enum ImportedKey {
+    Secret(Keypair),
+    Watch(Pubkey),
+}
+
+impl ImportedKey {
+    pub fn pubkey(&self) -> Pubkey {
+        match self {
+            ImportedKey::Secret(keypair) => keypair.pubkey(),
+            ImportedKey::Watch(pubkey) => *pubkey,
+        }
+    }
+}
+
+/// Guesses the format of `data` from its shape.
+pub fn detect_format(data: &str) -> Result<KeyFormat, Box<dyn Error>> {
+    let data = data.trim();
+    if data.starts_with('[') {
+        return Ok(KeyFormat::Keygen);
+    }
+    if data.starts_with('{') {
+        return Ok(KeyFormat::Scw);
+    }
+    match bs58::decode(data).into_vec()?.len() {
+        64 => Ok(KeyFormat::Base58),
+        32 => Ok(KeyFormat::Watch),
+        len => Err(format!("Unrecognized key material ({} bytes after base58 decoding)", len).into()),
+    }
+}
+
+pub fn parse_key(data: &str, format: KeyFormat) -> Result<ImportedKey, Box<dyn Error>> {
+    let data = data.trim();
+    let format = match format {
+        KeyFormat::Auto => detect_format(data)?,
+        format => format,
+    };
+    match format {
+        KeyFormat::Keygen => {
+            let bytes: Vec<u8> = serde_json::from_str(data)?;
+            Ok(ImportedKey::Secret(keypair_from_secret(&bytes)?))
+        }
+        KeyFormat::Base58 => {
+            let bytes = bs58::decode(data).into_vec()?;
+            Ok(ImportedKey::Secret(keypair_from_secret(&bytes)?))
+        }
+        KeyFormat::Watch => {
+            let public_key = match serde_json::from_str::<KeyFile>(data) {
+                Ok(file) => key_file_public_key(&file).to_string(),
+                Err(_) => data.to_string(),
+            };
+            Ok(ImportedKey::Watch(Pubkey::from_str(&public_key)?))
+        }
+        KeyFormat::Scw => {
+            match serde_json::from_str::<KeyFile>(data)? {
+                KeyFile::Plain(account) => {
+                    let bytes = bs58::decode(&account.private_key).into_vec()?;
+                    let keypair = keypair_from_secret(&bytes)?;
+                    check_public_key(&keypair, &account.public_key)?;
+                    Ok(ImportedKey::Secret(keypair))
+                }
+                KeyFile::Encrypted(keystore) => {
+                    let passphrase = read_passphrase(
+                        "Passphrase of the imported key: ",
+                        PASSPHRASE_ENV
+                    )?;
+                    Ok(ImportedKey::Secret(decrypt_keypair(&keystore, &passphrase)?))
+                }
+                KeyFile::Watch(watch) => {
+                    Ok(ImportedKey::Watch(Pubkey::from_str(&watch.public_key)?))
+                }
+            }
+        }
+        KeyFormat::Auto => unreachable!("format was resolved above"),
+    }
+}
+
+/// Renders the secret of `keypair` as a solana-keygen array or a base58 string.
+pub fn encode_key(keypair: &Keypair, format: KeyFormat) -> Result<String, Box<dyn Error>> {
+    match format {
+        KeyFormat::Keygen => Ok(serde_json::to_string(&keypair.to_bytes().to_vec())?),
+        KeyFormat::Base58 => Ok(bs58::encode(keypair.to_bytes()).into_string()),
+        _ => Err(format!("{:?} is not a secret key encoding", format).into()),
+    }
+}
+
+/// Accepts a 64-byte secret whose public half must match the key derived from it.
+fn keypair_from_secret(bytes: &[u8]) -> Result<Keypair, Box<dyn Error>> {
+    if bytes.len() != 64 {
+        return Err(format!("Expected a 64-byte secret key, found {} bytes", bytes.len()).into());
+    }
+    Keypair::from_bytes(bytes).map_err(|e| format!("Invalid secret key: {}", e).into())
+}
+
+fn check_public_key(keypair: &Keypair, public_key: &str) -> Result<(), Box<dyn Error>> {
+    if keypair.pubkey().to_string() != public_key {
+        return Err(
+            format!(
+                "Stored public key {} does not match the derived key {}",
+                public_key,
+                keypair.pubkey()
+            ).into()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_round_trip_through_detection() {
+        let keypair = Keypair::new();
+        for format in [KeyFormat::Keygen, KeyFormat::Base58] {
+            let encoded = encode_key(&keypair, format).unwrap();
+            match parse_key(&encoded, KeyFormat::Auto).unwrap() {
+                ImportedKey::Secret(imported) => assert_eq!(imported.pubkey(), keypair.pubkey()),
+                ImportedKey::Watch(_) => panic!("expected a secret key"),
+            }
+        }
+        let watch = parse_key(&keypair.pubkey().to_string(), KeyFormat::Auto).unwrap();
+        assert!(matches!(watch, ImportedKey::Watch(pubkey) if pubkey == keypair.pubkey()));
+    }
+
+    #[test]
+    fn mismatched_public_key_is_rejected() {
+        let keypair = Keypair::new();
+        let account = AccountKeys {
+            public_key: Keypair::new().pubkey().to_string(),
+            private_key: bs58::encode(keypair.to_bytes()).into_string(),
+        };
+        let data = serde_json::to_string(&account).unwrap();
+        assert!(parse_key(&data, KeyFormat::Scw).is_err());
+
+        let mut bytes = keypair.to_bytes();
+        bytes[40] ^= 1;
+        let tampered = serde_json::to_string(&bytes.to_vec()).unwrap();
+        assert!(parse_key(&tampered, KeyFormat::Keygen).is_err());
+    }
+}
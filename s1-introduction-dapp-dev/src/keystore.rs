@@ -0,0 +1,321 @@
+use aes_gcm_siv::{ aead::{ Aead, KeyInit, Payload }, Aes256GcmSiv, Nonce };
+use rand::{ rngs::OsRng, RngCore };
+use solana_sdk::{ pubkey::Pubkey, signature::Keypair, signer::Signer };
+use std::{ env, error::Error, fs, str::FromStr };
+
+use crate::state::*;
+
+pub const KEYSTORE_VERSION: u8 = 1;
+pub const PASSPHRASE_ENV: &str = "SCW_PASSPHRASE";
+pub const NEW_PASSPHRASE_ENV: &str = "SCW_NEW_PASSPHRASE";
+
+const KDF: &str = "scrypt";
+const CIPHER: &str = "aes-256-gcm-siv";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+// The costs `encrypt_keypair` writes, and the most a key file may ask unlocking to spend:
+// scrypt takes `128 * r * 2^log_n` bytes of memory and `p` times that work.
+const MAX_LOG_N: u8 = 15;
+const MAX_R: u32 = 8;
+const MAX_P: u32 = 1;
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        Self {
+            log_n: MAX_LOG_N,
+            r: MAX_R,
+            p: MAX_P,
+            salt: String::new(),
+        }
+    }
+}
+
+/// How `save_keys` protects the private key on disk.
+pub enum KeyProtection {
+    Plaintext,
+    Passphrase(String),
+}
+
+impl KeyProtection {
+    /// Asks for a new passphrase once, so it can be reused for several key files.
+    pub fn new(encrypt: bool) -> Result<Self, Box<dyn Error>> {
+        if encrypt {
+            Ok(KeyProtection::Passphrase(new_passphrase(PASSPHRASE_ENV)?))
+        } else {
+            Ok(KeyProtection::Plaintext)
+        }
+    }
+}
+
+pub fn save_keys(
+    keypair: &Keypair,
+    file_path: &str,
+    protection: &KeyProtection
+) -> Result<(), Box<dyn Error>> {
+    let file = match protection {
+        KeyProtection::Passphrase(passphrase) =>
+            KeyFile::Encrypted(encrypt_keypair(keypair, passphrase, KdfParams::default())?),
+        KeyProtection::Plaintext =>
+            KeyFile::Plain(AccountKeys {
+                public_key: keypair.pubkey().to_string(),
+                private_key: bs58::encode(keypair.to_bytes()).into_string(),
+            }),
+    };
+    write_key_file(&file, file_path)
+}
+
+/// Reads a keypair from `file_path`, prompting for the passphrase if the file is encrypted.
+pub fn read_keys(file_path: &str) -> Result<Keypair, Box<dyn Error>> {
+    match load_key_file(file_path)? {
+        KeyFile::Plain(account) => decode_plain(&account, file_path),
+        KeyFile::Encrypted(keystore) => {
+            let passphrase = read_passphrase(
+                &format!("Passphrase for '{}': ", file_path),
+                PASSPHRASE_ENV
+            )?;
+            decrypt_keypair(&keystore, &passphrase).map_err(|e| {
+                format!("Failed to unlock keys from '{}': {}", file_path, e).into()
+            })
+        }
+        KeyFile::Watch(_) => {
+            Err(format!("'{}' is a watch-only account and cannot sign", file_path).into())
+        }
+    }
+}
+
+/// Reads only the public key from `file_path`, without unlocking it.
+pub fn read_public_key(file_path: &str) -> Result<Pubkey, Box<dyn Error>> {
+    Ok(Pubkey::from_str(key_file_public_key(&load_key_file(file_path)?))?)
+}
+
+pub fn key_file_public_key(file: &KeyFile) -> &str {
+    match file {
+        KeyFile::Plain(account) => &account.public_key,
+        KeyFile::Encrypted(keystore) => &keystore.public_key,
+        KeyFile::Watch(watch) => &watch.public_key,
+    }
+}
+
+pub fn load_key_file(file_path: &str) -> Result<KeyFile, Box<dyn Error>> {
+    let data = fs::read_to_string(file_path).map_err(|e| {
+        format!("Failed to read keys file '{}': {}", file_path, e)
+    })?;
+    let file: KeyFile = serde_json::from_str(&data).map_err(|e| {
+        format!("Invalid keys file '{}': {}", file_path, e)
+    })?;
+    if let KeyFile::Encrypted(keystore) = &file {
+        if keystore.version != KEYSTORE_VERSION {
+            return Err(
+                format!(
+                    "Unsupported keystore version {} in '{}'",
+                    keystore.version,
+                    file_path
+                ).into()
+            );
+        }
+    }
+    Ok(file)
+}
+
+pub fn write_key_file(file: &KeyFile, file_path: &str) -> Result<(), Box<dyn Error>> {
+    write_secret_file(file_path, &serde_json::to_string_pretty(file)?)
+}
+
+/// Replaces `file_path` atomically so an interrupted write never loses the existing keys.
+pub fn write_secret_file(file_path: &str, contents: &str) -> Result<(), Box<dyn Error>> {
+    let tmp_path = format!("{}.tmp", file_path);
+    write_private(&tmp_path, contents)
+        .and_then(|_| fs::rename(&tmp_path, file_path))
+        .map_err(|e| {
+            let _ = fs::remove_file(&tmp_path);
+            format!("Failed to save keys at '{}': {}", file_path, e).into()
+        })
+}
+
+#[cfg(unix)]
+fn write_private(path: &str, contents: &str) -> std::io::Result<()> {
+    use std::{ io::Write, os::unix::fs::OpenOptionsExt };
+
+    let mut file = fs::OpenOptions
+        ::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    file.write_all(contents.as_bytes())
+}
+
+#[cfg(not(unix))]
+fn write_private(path: &str, contents: &str) -> std::io::Result<()> {
+    fs::write(path, contents)
+}
+
+pub fn decode_plain(account: &AccountKeys, file_path: &str) -> Result<Keypair, Box<dyn Error>> {
+    bs58::decode(&account.private_key)
+        .into_vec()
+        .map_err(|e| e.to_string())
+        .and_then(|bytes| Keypair::from_bytes(&bytes).map_err(|e| e.to_string()))
+        .map_err(|e| format!("Failed to decode keys from '{}': {}", file_path, e).into())
+}
+
+pub fn encrypt_keypair(
+    keypair: &Keypair,
+    passphrase: &str,
+    mut params: KdfParams
+) -> Result<EncryptedAccountKeys, Box<dyn Error>> {
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    OsRng.fill_bytes(&mut salt);
+    OsRng.fill_bytes(&mut nonce);
+    params.salt = bs58::encode(salt).into_string();
+
+    let public_key = keypair.pubkey().to_string();
+    let cipher = Aes256GcmSiv::new_from_slice(&derive_key(passphrase, &params)?)?;
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce), Payload {
+            msg: &keypair.to_bytes(),
+            aad: associated_data(KEYSTORE_VERSION, &public_key).as_bytes(),
+        })
+        .map_err(|_| "Failed to encrypt keys")?;
+
+    Ok(EncryptedAccountKeys {
+        version: KEYSTORE_VERSION,
+        public_key,
+        crypto: KeystoreCrypto {
+            kdf: KDF.to_string(),
+            kdf_params: params,
+            cipher: CIPHER.to_string(),
+            nonce: bs58::encode(nonce).into_string(),
+            ciphertext: bs58::encode(ciphertext).into_string(),
+        },
+    })
+}
+
+pub fn decrypt_keypair(
+    keystore: &EncryptedAccountKeys,
+    passphrase: &str
+) -> Result<Keypair, Box<dyn Error>> {
+    let crypto = &keystore.crypto;
+    if crypto.kdf != KDF || crypto.cipher != CIPHER {
+        return Err(format!("Unsupported keystore scheme {}/{}", crypto.kdf, crypto.cipher).into());
+    }
+    let nonce = bs58::decode(&crypto.nonce).into_vec()?;
+    if nonce.len() != NONCE_LEN {
+        return Err("Invalid keystore nonce".into());
+    }
+    let ciphertext = bs58::decode(&crypto.ciphertext).into_vec()?;
+
+    let cipher = Aes256GcmSiv::new_from_slice(&derive_key(passphrase, &crypto.kdf_params)?)?;
+    let plaintext = cipher
+        .decrypt(Nonce::from_slice(&nonce), Payload {
+            msg: &ciphertext,
+            aad: associated_data(keystore.version, &keystore.public_key).as_bytes(),
+        })
+        .map_err(|_| "Incorrect passphrase or corrupted keystore")?;
+
+    let keypair = Keypair::from_bytes(&plaintext)?;
+    if keypair.pubkey().to_string() != keystore.public_key {
+        return Err("Keystore public key does not match the decrypted private key".into());
+    }
+    Ok(keypair)
+}
+
+fn derive_key(passphrase: &str, params: &KdfParams) -> Result<[u8; 32], Box<dyn Error>> {
+    if params.log_n > MAX_LOG_N || params.r > MAX_R || params.p > MAX_P {
+        return Err(
+            format!(
+                "Keystore scrypt costs log_n={}, r={}, p={} are above the supported maximum",
+                params.log_n,
+                params.r,
+                params.p
+            ).into()
+        );
+    }
+    let salt = bs58::decode(&params.salt).into_vec()?;
+    let scrypt_params = scrypt::Params::new(params.log_n, params.r, params.p, 32)?;
+    let mut key = [0u8; 32];
+    scrypt::scrypt(passphrase.as_bytes(), &salt, &scrypt_params, &mut key)?;
+    Ok(key)
+}
+
+// Binds the header to the ciphertext so the stored public key cannot be swapped.
+fn associated_data(version: u8, public_key: &str) -> String {
+    format!("scw-keystore-v{}:{}", version, public_key)
+}
+
+/// Reads a passphrase from `env_var`, falling back to an interactive prompt.
+pub fn read_passphrase(prompt: &str, env_var: &str) -> Result<String, Box<dyn Error>> {
+    if let Ok(passphrase) = env::var(env_var) {
+        return Ok(passphrase);
+    }
+    Ok(rpassword::prompt_password(prompt)?)
+}
+
+/// Like `read_passphrase`, but asks twice when prompting and rejects empty passphrases.
+pub fn new_passphrase(env_var: &str) -> Result<String, Box<dyn Error>> {
+    let passphrase = match env::var(env_var) {
+        Ok(passphrase) => passphrase,
+        Err(_) => {
+            let passphrase = rpassword::prompt_password("New passphrase: ")?;
+            let confirmation = rpassword::prompt_password("Confirm passphrase: ")?;
+            if passphrase != confirmation {
+                return Err("Passphrases do not match".into());
+            }
+            passphrase
+        }
+    };
+    if passphrase.is_empty() {
+        return Err("Passphrase must not be empty".into());
+    }
+    Ok(passphrase)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fast_params() -> KdfParams {
+        KdfParams { log_n: 4, ..KdfParams::default() }
+    }
+
+    #[test]
+    fn encrypted_keypair_round_trips() {
+        let keypair = Keypair::new();
+        let keystore = encrypt_keypair(&keypair, "hunter2", fast_params()).unwrap();
+        let decrypted = decrypt_keypair(&keystore, "hunter2").unwrap();
+        assert_eq!(decrypted.to_bytes(), keypair.to_bytes());
+    }
+
+    #[test]
+    fn wrong_passphrase_is_rejected() {
+        let keystore = encrypt_keypair(&Keypair::new(), "hunter2", fast_params()).unwrap();
+        assert!(decrypt_keypair(&keystore, "hunter3").is_err());
+    }
+
+    #[test]
+    fn swapped_public_key_is_rejected() {
+        let mut keystore = encrypt_keypair(&Keypair::new(), "hunter2", fast_params()).unwrap();
+        keystore.public_key = Keypair::new().pubkey().to_string();
+        assert!(decrypt_keypair(&keystore, "hunter2").is_err());
+    }
+
+    #[test]
+    fn excessive_kdf_costs_are_rejected() {
+        let mut keystore = encrypt_keypair(&Keypair::new(), "hunter2", fast_params()).unwrap();
+        keystore.crypto.kdf_params.log_n = 30;
+        assert!(decrypt_keypair(&keystore, "hunter2").is_err());
+        keystore.crypto.kdf_params = KdfParams { r: 1 << 20, ..fast_params() };
+        assert!(decrypt_keypair(&keystore, "hunter2").is_err());
+    }
+
+    #[test]
+    fn plaintext_and_encrypted_files_are_distinguished() {
+        let plain = r#"{"public_key": "a", "private_key": "b"}"#;
+        assert!(matches!(serde_json::from_str::<KeyFile>(plain).unwrap(), KeyFile::Plain(_)));
+
+        let keystore = encrypt_keypair(&Keypair::new(), "hunter2", fast_params()).unwrap();
+        let json = serde_json::to_string(&KeyFile::Encrypted(keystore)).unwrap();
+        assert!(matches!(serde_json::from_str::<KeyFile>(&json).unwrap(), KeyFile::Encrypted(_)));
+    }
+}
@@ -1,196 +1,654 @@
 use clap::Parser;
 use solana_client::rpc_client::RpcClient;
 use solana_sdk::{
-    native_token::LAMPORTS_PER_SOL,
+    instruction::Instruction,
     pubkey::Pubkey,
-    signature::{ Keypair, Signature },
+    signature::Keypair,
     signer::Signer,
 };
-use std::{ error::Error, fs, str::FromStr };
+use serde_json::json;
+use std::{ error::Error, fs, path::Path, str::FromStr };
 
+use amount::*;
+use batch::*;
+use cnft::*;
+use config::*;
+use confirm::*;
+use history::*;
+use inspect::*;
+use keyformat::*;
+use keystore::*;
+use mnemonic::*;
+use native_vault::*;
+use nonce::*;
+use offline::*;
+use output::*;
+use pay::*;
 use state::*;
+use token::*;
+use transaction::*;
+use vanity::*;
+use vault::*;
+use wallet::*;
 
+mod airdrop;
+mod amount;
+mod batch;
+mod cnft;
+mod config;
+mod confirm;
+mod history;
+mod inspect;
+mod keyformat;
+mod keystore;
+mod merkle;
+mod mnemonic;
+mod native_vault;
+mod nonce;
+mod offline;
+mod orders;
+mod output;
+mod pay;
+mod pay_server;
 mod state;
-
-const DEFAULT_SETTINGS: &str = r#"{
-    "endpoint": "http://127.0.0.1:8899"
-}"#;
-
-impl ProgramSettings {
-    fn load_settings(file_path: &str) -> Self {
-        let contents = match fs::read_to_string(file_path) {
-            Ok(contents) => contents,
-            Err(_) => {
-                let default_settings: ProgramSettings = serde_json
-                    ::from_str(DEFAULT_SETTINGS)
-                    .expect("Failed to parse default settings");
-                default_settings.save_settings(file_path);
-                serde_json
-                    ::to_string(&default_settings)
-                    .expect("Failed to serialize default settings")
-            }
-        };
-        serde_json::from_str(&contents).expect("Failed to parse settings file")
-    }
-
-    fn save_settings(&self, file_path: &str) {
-        let data: String = serde_json
-            ::to_string_pretty(&self)
-            .expect("Failed to serialize settings");
-        fs::write(file_path, data).expect("Failed to write settings file");
-    }
-}
-
-impl From<Endpoint> for String {
-    fn from(endpoint: Endpoint) -> Self {
-        match endpoint {
-            Endpoint::Local => "http://127.0.0.1:8899".to_string(),
-            Endpoint::Dev => "https://api.devnet.solana.com".to_string(),
-            Endpoint::Test => "https://api.testnet.solana.com".to_string(),
-        }
-    }
-}
-
-fn save_keys(keypair: &Keypair, file_path: &str) -> Result<(), Box<dyn Error>> {
-    let account = AccountKeys {
-        public_key: keypair.pubkey().to_string(),
-        private_key: bs58::encode(keypair.to_bytes()).into_string(),
-    };
-    let json = serde_json::to_string_pretty(&account)?;
-    fs::write(file_path, json).map_err(|e| {
-        eprintln!("Failed to save keys at '{}': {}", file_path, e);
-        e.into()
-    })
-}
-
-fn read_keys(file_path: &str) -> Result<Keypair, Box<dyn Error>> {
-    let data = fs::read_to_string(file_path).map_err(|e| {
-        eprintln!("Failed to read keys file '{}': {}", file_path, e);
-        e
-    })?;
-    let account: AccountKeys = serde_json::from_str(&data)?;
-    let bytes = bs58::decode(account.private_key).into_vec()?;
-    Keypair::from_bytes(&bytes).map_err(|e| {
-        eprintln!("Failed to decode keys from '{}': {}", file_path, e);
-        e.into()
-    })
-}
+mod token;
+mod transaction;
+mod vanity;
+mod vault;
+mod wallet;
 
 async fn request_funds(
     rpc_client: &RpcClient,
+    options: &TransactionArgs,
     pubkey: &Pubkey,
-    amount_sol: u64
-) -> Result<Signature, Box<dyn Error>> {
-    let sig = rpc_client.request_airdrop(pubkey, amount_sol * LAMPORTS_PER_SOL)?;
-    loop {
-        let confirmed = rpc_client.confirm_transaction(&sig)?;
-        if confirmed {
-            break;
-        }
-    }
-    Ok(sig)
+    lamports: u64
+) -> Result<Confirmation, Box<dyn Error>> {
+    // The faucet signs with the blockhash we pass, so its expiry can be detected.
+    let blockhash = rpc_client.get_latest_blockhash()?;
+    let signature = rpc_client.request_airdrop_with_blockhash(pubkey, lamports, &blockhash)?;
+    confirm_transaction(rpc_client, options, &signature, Some(&blockhash))
 }
 
+/// Sends `lamports` at the compute unit `price` that `sendable_lamports` planned the fee with.
 fn send_funds(
     rpc_client: &RpcClient,
+    options: &TransactionArgs,
+    price: u64,
     sender_keypair: &Keypair,
     receiver_pub_key: &Pubkey,
-    amount_sol: f64
-) -> Result<Signature, Box<dyn Error>> {
-    let amount_lamports = (amount_sol * (LAMPORTS_PER_SOL as f64)) as u64;
-    let latest_blockhash = rpc_client.get_latest_blockhash()?;
-
-    let transaction = solana_sdk::transaction::Transaction::new_signed_with_payer(
-        &[
-            solana_sdk::system_instruction::transfer(
-                &sender_keypair.pubkey(),
-                receiver_pub_key,
-                amount_lamports
-            ),
-        ],
-        Some(&sender_keypair.pubkey()),
+    lamports: u64
+) -> Result<Confirmation, Box<dyn Error>> {
+    let instruction = transfer_instruction(&sender_keypair.pubkey(), receiver_pub_key, lamports);
+    let transaction = build_transaction_at_price(
+        rpc_client,
+        options,
+        &[instruction],
+        &sender_keypair.pubkey(),
         &[sender_keypair],
-        latest_blockhash
-    );
+        None,
+        price
+    )?;
+    submit_transaction(rpc_client, options, &transaction)
+}
+
+fn transfer_instruction(sender: &Pubkey, receiver: &Pubkey, lamports: u64) -> Instruction {
+    solana_sdk::system_instruction::transfer(sender, receiver, lamports)
+}
 
-    rpc_client.send_and_confirm_transaction(&transaction).map_err(|e| e.into())
+/// What `send ALL` transfers: the balance minus the transaction fee and the rent-exempt
+/// minimum, which stays behind so the sender's account is not closed.
+fn sendable_lamports(
+    rpc_client: &RpcClient,
+    options: &TransactionArgs,
+    price: u64,
+    sender: &Pubkey,
+    receiver: &Pubkey
+) -> Result<u64, Box<dyn Error>> {
+    let balance = rpc_client.get_balance(sender)?;
+    let rent = rpc_client.get_minimum_balance_for_rent_exemption(0)?;
+    // The fee depends on the signatures and compute budget, not on the amount.
+    let instruction = transfer_instruction(sender, receiver, balance);
+    let probe = build_transaction_at_price(
+        rpc_client,
+        options,
+        &[instruction],
+        sender,
+        &[],
+        None,
+        price
+    )?;
+    let fee = rpc_client.get_fee_for_message(&probe.message)?;
+    balance
+        .checked_sub(fee + rent)
+        .filter(|lamports| *lamports > 0)
+        .ok_or_else(|| {
+            format!(
+                "Balance of {} SOL does not cover the {} SOL fee and {} SOL rent-exempt minimum",
+                format_token_amount(balance, SOL_DECIMALS),
+                format_token_amount(fee, SOL_DECIMALS),
+                format_token_amount(rent, SOL_DECIMALS)
+            ).into()
+        })
 }
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
-    let cli = Cli::parse();
-    let settings = ProgramSettings::load_settings("./settings.json");
-    let rpc_client = RpcClient::new(settings.endpoint.clone());
-
-    (match &cli.operation {
-        Operations::Generate(cmd) => generate_account(&cli.account_file, cmd),
-        Operations::Fetch(cmd) => fetch_address(cmd),
-        Operations::Fund(cmd) => fund_account(&rpc_client, cmd).await,
-        Operations::Send(cmd) => send_funds_command(&rpc_client, &cli.account_file, cmd).await,
-        Operations::Balance(cmd) => check_balance(&rpc_client, cmd).await,
-        Operations::UpdateSettings(cmd) => update_settings(cmd),
-        Operations::List(cmd) => list_accounts(&cli.account_file, cmd),
+async fn main() {
+    let mut cli = Cli::parse();
+    set_output_format(cli.output);
+    if let Err(error) = run(&mut cli).await {
+        report_error(error.as_ref());
+        std::process::exit(1);
+    }
+}
+
+async fn run(cli: &mut Cli) -> Result<(), Box<dyn Error>> {
+    let config_path = config_path()?;
+    let mut settings = ProgramSettings::load_settings(&config_path)?;
+    let operation = match &cli.operation {
+        Operations::Config(cmd) => {
+            let wallet = Wallet::new(&cli.wallet_dir, None);
+            let profile = cli.profile.as_deref();
+            return config_command(&config_path, &mut settings, &wallet, profile, cmd);
+        }
+        Operations::UpdateSettings(cmd) => {
+            return update_settings(&config_path, &mut settings, cli.profile.as_deref(), cmd);
+        }
+        Operations::Profile(operation) => operation.as_ref(),
+    };
+
+    let profile = settings.profile(cli.profile.as_deref())?;
+    let rpc_client = RpcClient::new_with_commitment(
+        profile.rpc_url.clone(),
+        profile.commitment.config()
+    );
+    let wallet = Wallet::new(&cli.wallet_dir, cli.account_file.as_deref()).with_profile_keypair(
+        profile.keypair.clone()
+    );
+    if cli.transaction.priority_fee.is_none() && !cli.transaction.auto_fee {
+        cli.transaction.priority_fee = profile.priority_fee;
+    }
+    cli.transaction.commitment = profile.commitment;
+    cli.transaction.websocket_url = profile.websocket_url().ok();
+    cli.transaction.resolve_nonce_authority(&wallet)?;
+    let options = &cli.transaction;
+
+    (match operation {
+        ProfileOperations::Generate(cmd) => generate_account(&wallet, cmd),
+        ProfileOperations::Recover(cmd) => recover_accounts(&wallet, cmd),
+        ProfileOperations::Fetch(cmd) => inspect_account(&rpc_client, cmd),
+        ProfileOperations::Fund(cmd) => fund_account(&rpc_client, options, cmd).await,
+        ProfileOperations::Send(cmd) => send_funds_command(&rpc_client, options, &wallet, cmd).await,
+        ProfileOperations::Balance(cmd) => check_balance(&rpc_client, cmd).await,
+        ProfileOperations::List(cmd) => list_accounts(&rpc_client, &wallet, cmd),
+        ProfileOperations::Use(cmd) => use_account(&wallet, cmd),
+        ProfileOperations::ChangePassword(cmd) => change_password(&wallet, cmd),
+        ProfileOperations::Migrate(cmd) => migrate_keys(&wallet, cmd),
+        ProfileOperations::Import(cmd) => import_key(&wallet, cmd),
+        ProfileOperations::Export(cmd) => export_key(&wallet, cmd),
+        ProfileOperations::Token(cmd) => token_command(&rpc_client, options, &wallet, cmd),
+        ProfileOperations::History(cmd) => show_history(&rpc_client, &wallet, cmd),
+        ProfileOperations::Broadcast(cmd) => broadcast_transaction(&rpc_client, options, cmd),
+        ProfileOperations::Sign(cmd) => cosign_transaction(&wallet, cmd),
+        ProfileOperations::Nonce(cmd) => nonce_command(&rpc_client, options, &wallet, cmd),
+        ProfileOperations::BatchSend(cmd) => batch_send(&rpc_client, options, &wallet, cmd),
+        ProfileOperations::Vault(cmd) => vault_command(&rpc_client, options, &wallet, cmd),
+        ProfileOperations::NativeVault(cmd) => {
+            native_vault_command(&rpc_client, options, &wallet, cmd)
+        }
+        ProfileOperations::Pay(cmd) => pay_command(&rpc_client, options, &wallet, cmd),
+        ProfileOperations::Cnft(cmd) => cnft_command(&rpc_client, options, &wallet, cmd),
     })?;
 
     Ok(())
 }
 
-fn generate_account(default_path: &str, cmd: &GenerateCommand) -> Result<(), Box<dyn Error>> {
-    let keypair = Keypair::new();
-    let file_path = cmd.file.as_deref().unwrap_or(default_path);
-    save_keys(&keypair, file_path)?;
-    println!("Account created and saved to: {}", file_path);
-    println!("Public key: {}", keypair.pubkey());
+fn generate_account(wallet: &Wallet, cmd: &GenerateCommand) -> Result<(), Box<dyn Error>> {
+    let file_path = wallet.output_path(cmd.file.as_deref(), cmd.label.as_deref())?;
+    let file_path = file_path.as_str();
+    if cmd.mnemonic {
+        let mnemonic = generate_mnemonic(cmd.words.into())?;
+        let phrase = mnemonic.phrase();
+        return save_derived_accounts(phrase, &cmd.derivation, file_path, !cmd.plaintext, true);
+    }
+    if cmd.derivation.count != 1 || cmd.derivation.index != 0 || cmd.derivation.seed_passphrase {
+        return Err("Derivation options require --mnemonic".into());
+    }
+
+    let pattern = VanityPattern {
+        starts_with: cmd.starts_with.clone().unwrap_or_default(),
+        ends_with: cmd.ends_with.clone().unwrap_or_default(),
+        ignore_case: cmd.ignore_case,
+    };
+    let keypair = if pattern.is_empty() {
+        Keypair::new()
+    } else {
+        let threads = cmd.threads
+            .or_else(|| std::thread::available_parallelism().ok().map(|n| n.get()))
+            .unwrap_or(1)
+            .max(1);
+        grind(&pattern, threads, cmd.max_attempts)?.ok_or(
+            "No matching address found within --max-attempts"
+        )?
+    };
+    save_keys(&keypair, file_path, &KeyProtection::new(!cmd.plaintext)?)?;
+    emit(
+        json!({
+            "mnemonic": null,
+            "accounts": [{ "public_key": keypair.pubkey().to_string(), "file": file_path }],
+        }),
+        || {
+            println!("Account created and saved to: {}", file_path);
+            println!("Public key: {}", keypair.pubkey());
+        }
+    );
     Ok(())
 }
 
-fn fetch_address(cmd: &FetchCommand) -> Result<(), Box<dyn Error>> {
-    let pubkey = Pubkey::from_str(&cmd.wallet)?;
-    println!("Address: {}", pubkey);
+fn recover_accounts(wallet: &Wallet, cmd: &RecoverCommand) -> Result<(), Box<dyn Error>> {
+    let file_path = wallet.output_path(cmd.file.as_deref(), cmd.label.as_deref())?;
+    let phrase = read_passphrase("Mnemonic: ", MNEMONIC_ENV)?;
+    let mnemonic = parse_mnemonic(&phrase)?;
+    save_derived_accounts(mnemonic.phrase(), &cmd.derivation, &file_path, !cmd.plaintext, false)
+}
+
+/// Saves the accounts derived from `phrase`. `show_phrase` is set for a newly generated
+/// mnemonic, which is printed once so it can be written down.
+fn save_derived_accounts(
+    phrase: &str,
+    args: &DerivationArgs,
+    file_path: &str,
+    encrypt: bool,
+    show_phrase: bool
+) -> Result<(), Box<dyn Error>> {
+    let seed_passphrase = read_seed_passphrase(args.seed_passphrase)?;
+    let accounts = derive_keypairs(phrase, &seed_passphrase, args.index, args.count)?;
+    let protection = KeyProtection::new(encrypt)?;
+    let mut saved = Vec::with_capacity(accounts.len());
+    for account in &accounts {
+        let account_path = if accounts.len() == 1 {
+            file_path.to_string()
+        } else {
+            indexed_file_path(file_path, account.index)
+        };
+        save_keys(&account.keypair, &account_path, &protection)?;
+        saved.push((account, account_path));
+    }
+
+    let accounts_json: Vec<_> = saved
+        .iter()
+        .map(|(account, path)| {
+            json!({
+                "public_key": account.keypair.pubkey().to_string(),
+                "file": path,
+                "derivation_path": format!("{:?}", account.path),
+            })
+        })
+        .collect();
+    emit(
+        json!({
+            "mnemonic": if show_phrase { Some(phrase) } else { None },
+            "accounts": accounts_json,
+        }),
+        || {
+            if show_phrase {
+                println!("Mnemonic: {}", phrase);
+                println!(
+                    "Write these words down and keep them offline; they recover every account."
+                );
+            }
+            for (account, path) in &saved {
+                println!("Account {:?} saved to: {}", account.path, path);
+                println!("Public key: {}", account.keypair.pubkey());
+            }
+        }
+    );
     Ok(())
 }
 
-async fn fund_account(rpc_client: &RpcClient, cmd: &FundCommand) -> Result<(), Box<dyn Error>> {
+async fn fund_account(
+    rpc_client: &RpcClient,
+    options: &TransactionArgs,
+    cmd: &FundCommand
+) -> Result<(), Box<dyn Error>> {
     let pubkey = Pubkey::from_str(&cmd.wallet)?;
-    let signature = request_funds(rpc_client, &pubkey, cmd.amount).await?;
-    println!("Funding successful: Signature {}", signature);
+    let lamports = Amount::parse(&cmd.amount, SOL_DECIMALS, cmd.lamports)?.exact()?;
+    let confirmation = request_funds(rpc_client, options, &pubkey, lamports).await?;
+    emit(
+        json!({
+            "address": pubkey.to_string(),
+            "lamports": lamports,
+            "signature": confirmation.signature.to_string(),
+            "slot": confirmation.slot,
+            "commitment": confirmation.commitment,
+        }),
+        || println!("Funding successful: Signature {}", confirmation)
+    );
     Ok(())
 }
 
 async fn send_funds_command(
     rpc_client: &RpcClient,
-    default_account: &str,
+    options: &TransactionArgs,
+    wallet: &Wallet,
     cmd: &SendCommand
 ) -> Result<(), Box<dyn Error>> {
-    let sender_keypair = match &cmd.from {
-        Some(path) => read_keys(path)?,
-        None => read_keys(default_account)?,
-    };
+    let sender_keypair = read_keys(&wallet.resolve(cmd.from.as_deref())?)?;
     let receiver_pubkey = Pubkey::from_str(&cmd.to)?;
-    let signature = send_funds(rpc_client, &sender_keypair, &receiver_pubkey, cmd.amount)?;
-    println!("Transfer successful: Signature {}", signature);
+    let sender = sender_keypair.pubkey();
+    let amount = Amount::parse(&cmd.amount, SOL_DECIMALS, cmd.lamports)?;
+    if cmd.offline.sign_only {
+        let Amount::Exact(lamports) = amount else {
+            return Err("ALL needs the current balance and cannot be signed offline".into());
+        };
+        let instruction = transfer_instruction(&sender, &receiver_pubkey, lamports);
+        return sign_offline(rpc_client, options, &[instruction], &sender_keypair, &cmd.offline);
+    }
+    // `ALL` leaves room for the fee at this price, so the transfer must pay the same one.
+    let instruction = transfer_instruction(&sender, &receiver_pubkey, 0);
+    let price = compute_unit_price(rpc_client, options, &[instruction])?;
+    let lamports = match amount {
+        Amount::Exact(lamports) => lamports,
+        Amount::All => sendable_lamports(rpc_client, options, price, &sender, &receiver_pubkey)?,
+    };
+    let confirmation = send_funds(
+        rpc_client,
+        options,
+        price,
+        &sender_keypair,
+        &receiver_pubkey,
+        lamports
+    )?;
+    emit(
+        json!({
+            "from": sender_keypair.pubkey().to_string(),
+            "to": receiver_pubkey.to_string(),
+            "lamports": lamports,
+            "signature": confirmation.signature.to_string(),
+            "slot": confirmation.slot,
+            "commitment": confirmation.commitment,
+        }),
+        || println!("Transfer successful: Signature {}", confirmation)
+    );
     Ok(())
 }
 
 async fn check_balance(rpc_client: &RpcClient, cmd: &BalanceCommand) -> Result<(), Box<dyn Error>> {
     let pubkey = Pubkey::from_str(&cmd.wallet)?;
     let balance = rpc_client.get_balance(&pubkey)?;
-    println!("Balance: {} SOL", (balance as f64) / (LAMPORTS_PER_SOL as f64));
+    emit(
+        json!({
+            "address": pubkey.to_string(),
+            "lamports": balance,
+            "sol": format_token_amount(balance, SOL_DECIMALS),
+        }),
+        || println!("Balance: {} SOL", format_token_amount(balance, SOL_DECIMALS))
+    );
     Ok(())
 }
 
-fn update_settings(cmd: &UpdateSettingsCommand) -> Result<(), Box<dyn Error>> {
-    let mut settings = ProgramSettings::load_settings("./settings.json");
-    settings.endpoint = cmd.endpoint.clone().into();
-    settings.save_settings("./settings.json");
-    println!("Endpoint set to: {}", settings.endpoint);
+/// Shorthand for `config set rpc-url` with one of the public clusters.
+fn update_settings(
+    config_path: &Path,
+    settings: &mut ProgramSettings,
+    profile: Option<&str>,
+    cmd: &UpdateSettingsCommand
+) -> Result<(), Box<dyn Error>> {
+    let name = settings.selected_name(profile);
+    let mut updated = settings.profile(Some(&name))?.clone();
+    updated.rpc_url = cmd.endpoint.clone().into();
+    let endpoint = updated.rpc_url.clone();
+    settings.profiles.insert(name.clone(), updated);
+    settings.save_settings(config_path)?;
+    emit(json!({ "profile": name, "endpoint": endpoint }), || {
+        println!("Endpoint set to: {}", endpoint)
+    });
+    Ok(())
+}
+
+fn list_accounts(
+    rpc_client: &RpcClient,
+    wallet: &Wallet,
+    cmd: &ListCommand
+) -> Result<(), Box<dyn Error>> {
+    let wallet = match cmd.file.as_deref() {
+        Some(path) if Path::new(path).is_file() => {
+            let pubkey = read_public_key(path)?;
+            emit(json!({ "file": path, "public_key": pubkey.to_string() }), || {
+                println!("Public key: {}", pubkey)
+            });
+            return Ok(());
+        }
+        Some(dir) => Wallet::new(dir, None),
+        None => Wallet::new(&wallet.dir().to_string_lossy(), None),
+    };
+
+    let labels = wallet.labels()?;
+    let default_label = wallet.default_label()?;
+    let mut accounts = Vec::with_capacity(labels.len());
+    for label in labels {
+        let path = wallet.key_path(&label);
+        let file = load_key_file(&path)?;
+        let pubkey = Pubkey::from_str(key_file_public_key(&file))?;
+        let kind = match file {
+            KeyFile::Encrypted(_) => "encrypted",
+            KeyFile::Plain(_) => "plaintext",
+            KeyFile::Watch(_) => "watch-only",
+        };
+        accounts.push((label, pubkey, kind));
+    }
+
+    let pubkeys: Vec<Pubkey> = accounts.iter().map(|(_, pubkey, _)| *pubkey).collect();
+    let mut balances = Vec::with_capacity(pubkeys.len());
+    for chunk in pubkeys.chunks(100) {
+        let fetched = rpc_client.get_multiple_accounts(chunk)?;
+        balances.extend(fetched.iter().map(|account| account.as_ref().map_or(0, |a| a.lamports)));
+    }
+
+    let accounts_json: Vec<_> = accounts
+        .iter()
+        .zip(&balances)
+        .map(|((label, pubkey, kind), lamports)| {
+            json!({
+                "label": label,
+                "public_key": pubkey.to_string(),
+                "kind": kind,
+                "lamports": lamports,
+                "default": default_label.as_deref() == Some(label.as_str()),
+            })
+        })
+        .collect();
+    emit(
+        json!({
+            "wallet_dir": wallet.dir().to_string_lossy(),
+            "default": default_label,
+            "accounts": accounts_json,
+        }),
+        || {
+            if accounts.is_empty() {
+                println!("No accounts found in {}", wallet.dir().display());
+            }
+            for ((label, pubkey, kind), lamports) in accounts.iter().zip(&balances) {
+                let is_default = default_label.as_deref() == Some(label.as_str());
+                let marker = if is_default { "*" } else { " " };
+                let note = match *kind {
+                    "encrypted" => String::new(),
+                    kind => format!("  ({})", kind),
+                };
+                println!(
+                    "{} {:<20} {:<44} {:>14} SOL{}",
+                    marker,
+                    label,
+                    pubkey,
+                    format_token_amount(*lamports, SOL_DECIMALS),
+                    note
+                );
+            }
+        }
+    );
+    Ok(())
+}
+
+fn use_account(wallet: &Wallet, cmd: &UseCommand) -> Result<(), Box<dyn Error>> {
+    wallet.set_default(&cmd.label)?;
+    let pubkey = read_public_key(&wallet.key_path(&cmd.label))?;
+    emit(json!({ "default": cmd.label, "public_key": pubkey.to_string() }), || {
+        println!("Default account set to: {} ({})", cmd.label, pubkey)
+    });
+    Ok(())
+}
+
+fn change_password(wallet: &Wallet, cmd: &ChangePasswordCommand) -> Result<(), Box<dyn Error>> {
+    let file_path = wallet.resolve(cmd.file.as_deref())?;
+    let file_path = file_path.as_str();
+    let keystore = match load_key_file(file_path)? {
+        KeyFile::Encrypted(keystore) => keystore,
+        KeyFile::Plain(_) => {
+            return Err(
+                format!("'{}' is not encrypted; run `migrate` to encrypt it", file_path).into()
+            );
+        }
+        KeyFile::Watch(_) => {
+            return Err(format!("'{}' is a watch-only account", file_path).into());
+        }
+    };
+    let passphrase = read_passphrase("Current passphrase: ", PASSPHRASE_ENV)?;
+    let keypair = decrypt_keypair(&keystore, &passphrase)?;
+    let new_passphrase = new_passphrase(NEW_PASSPHRASE_ENV)?;
+    let params = KdfParams { salt: String::new(), ..keystore.crypto.kdf_params };
+    let keystore = encrypt_keypair(&keypair, &new_passphrase, params)?;
+    write_key_file(&KeyFile::Encrypted(keystore), file_path)?;
+    emit(json!({ "file": file_path, "public_key": keypair.pubkey().to_string() }), || {
+        println!("Passphrase changed for: {}", file_path)
+    });
+    Ok(())
+}
+
+fn migrate_keys(wallet: &Wallet, cmd: &MigrateCommand) -> Result<(), Box<dyn Error>> {
+    let file_path = wallet.resolve(cmd.file.as_deref())?;
+    let file_path = file_path.as_str();
+    let account = match load_key_file(file_path)? {
+        KeyFile::Plain(account) => account,
+        KeyFile::Encrypted(_) => {
+            return Err(format!("'{}' is already encrypted", file_path).into());
+        }
+        KeyFile::Watch(_) => {
+            return Err(format!("'{}' is a watch-only account", file_path).into());
+        }
+    };
+    let keypair = decode_plain(&account, file_path)?;
+    if keypair.pubkey().to_string() != account.public_key {
+        return Err(
+            format!("Public key in '{}' does not match its private key", file_path).into()
+        );
+    }
+    save_keys(&keypair, file_path, &KeyProtection::new(true)?)?;
+    emit(json!({ "file": file_path, "public_key": keypair.pubkey().to_string() }), || {
+        println!("Keys in {} are now encrypted", file_path)
+    });
+    Ok(())
+}
+
+fn import_key(wallet: &Wallet, cmd: &ImportCommand) -> Result<(), Box<dyn Error>> {
+    let data = match &cmd.input {
+        Some(path) => fs::read_to_string(path)?,
+        None => rpassword::prompt_password("Secret key or public key: ")?,
+    };
+    let imported = parse_key(&data, cmd.format)?;
+    let file_path = wallet.output_path(cmd.file.as_deref(), cmd.label.as_deref())?;
+    if Path::new(&file_path).exists() {
+        return Err(format!("Refusing to overwrite existing key file '{}'", file_path).into());
+    }
+
+    match &imported {
+        ImportedKey::Secret(keypair) => {
+            save_keys(keypair, &file_path, &KeyProtection::new(!cmd.plaintext)?)?;
+        }
+        ImportedKey::Watch(pubkey) => {
+            let watch = WatchKeys { public_key: pubkey.to_string() };
+            write_key_file(&KeyFile::Watch(watch), &file_path)?;
+        }
+    }
+    let watch_only = matches!(imported, ImportedKey::Watch(_));
+    emit(
+        json!({
+            "file": file_path,
+            "public_key": imported.pubkey().to_string(),
+            "watch_only": watch_only,
+        }),
+        || {
+            let kind = if watch_only { "Watch-only account" } else { "Account" };
+            println!("{} imported to: {}", kind, file_path);
+            println!("Public key: {}", imported.pubkey());
+        }
+    );
     Ok(())
 }
 
-fn list_accounts(default_path: &str, cmd: &ListCommand) -> Result<(), Box<dyn Error>> {
-    let file_path = cmd.file.as_deref().unwrap_or(default_path);
-    let data = fs::read_to_string(file_path)?;
-    let account: AccountKeys = serde_json::from_str(&data)?;
-    println!("Public key: {}", account.public_key);
+fn export_key(wallet: &Wallet, cmd: &ExportCommand) -> Result<(), Box<dyn Error>> {
+    let file_path = wallet.resolve(cmd.file.as_deref())?;
+    if cmd.format == KeyFormat::Auto {
+        return Err("Choose an export format with --format".into());
+    }
+    if let Some(output) = cmd.output_file.as_deref() {
+        check_export_path(&file_path, output, cmd.force)?;
+    }
+    if cmd.format == KeyFormat::Watch {
+        let public_key = read_public_key(&file_path)?.to_string();
+        let watch = serde_json::to_string_pretty(&KeyFile::Watch(WatchKeys { public_key }))?;
+        return write_export(&watch, cmd.format, cmd.output_file.as_deref());
+    }
+
+    let keypair = read_keys(&file_path)?;
+    if cmd.format == KeyFormat::Scw {
+        let output = cmd.output_file
+            .as_deref()
+            .ok_or("Exporting to the scw format requires --output-file")?;
+        save_keys(&keypair, output, &KeyProtection::new(!cmd.plaintext)?)?;
+        emit(json!({ "format": value_name(&cmd.format), "file": output }), || {
+            println!("Account exported to: {}", output)
+        });
+        return Ok(());
+    }
+    write_export(&encode_key(&keypair, cmd.format)?, cmd.format, cmd.output_file.as_deref())
+}
+
+/// Like `import`, refuses to overwrite an existing file unless `force` is given. The key file
+/// being exported is never overwritten.
+fn check_export_path(key_file: &str, output: &str, force: bool) -> Result<(), Box<dyn Error>> {
+    let output_path = Path::new(output);
+    if !output_path.exists() {
+        return Ok(());
+    }
+    if fs::canonicalize(output_path)? == fs::canonicalize(key_file)? {
+        let message = format!("Refusing to overwrite '{}', the key file being exported", output);
+        return Err(message.into());
+    }
+    if !force {
+        return Err(
+            format!("Refusing to overwrite existing file '{}'; pass --force to replace it", output)
+                .into()
+        );
+    }
+    Ok(())
+}
+
+fn write_export(
+    contents: &str,
+    format: KeyFormat,
+    output: Option<&str>
+) -> Result<(), Box<dyn Error>> {
+    match output {
+        Some(path) => {
+            write_secret_file(path, contents)?;
+            emit(json!({ "format": value_name(&format), "file": path }), || {
+                println!("Account exported to: {}", path)
+            });
+        }
+        None => {
+            emit(json!({ "format": value_name(&format), "key": contents }), || {
+                println!("{}", contents)
+            });
+        }
+    }
     Ok(())
 }
@@ -0,0 +1,957 @@
+use borsh::BorshDeserialize;
+use serde_json::{ json, Value };
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{ keccak, pubkey::Pubkey };
+use std::{ collections::{ BTreeMap, HashMap }, error::Error, io, str::FromStr };
+
+use crate::{
+    cnft::{
+        decode_leaf_event,
+        decode_tree_header,
+        fetch_transaction,
+        noop_events,
+        read_tree_file,
+        Creator,
+        LeafSchema,
+        MetadataArgs,
+        BUBBLEGUM_PROGRAM_ID,
+        COMPRESSION_PROGRAM_ID,
+        TREE_HEADER_SIZE,
+    },
+    history::fetch_signatures,
+    output::{ emit, status },
+    state::*,
+};
+
+/// A node of the tree: a leaf hash, or the Keccak-256 hash of its two children.
+pub type Node = [u8; 32];
+/// An empty leaf. Empty subtrees hash up from it.
+pub const EMPTY: Node = [0; 32];
+
+/// Root of an empty subtree `level` levels high.
+pub fn empty_node(level: u32) -> Node {
+    (0..level).fold(EMPTY, |node, _| keccak::hashv(&[&node, &node]).to_bytes())
+}
+
+fn hash_to_parent(node: &mut Node, sibling: &Node, is_left: bool) {
+    let parent = if is_left {
+        keccak::hashv(&[node, sibling])
+    } else {
+        keccak::hashv(&[sibling, node])
+    };
+    *node = parent.to_bytes();
+}
+
+/// Root that `proof`, the siblings from the leaf upwards, gives `leaf` at `index`.
+pub fn recompute(leaf: Node, proof: &[Node], index: u32) -> Node {
+    let mut node = leaf;
+    for (level, sibling) in proof.iter().enumerate() {
+        hash_to_parent(&mut node, sibling, (index >> level) & 1 == 0);
+    }
+    node
+}
+
+/// Level of the highest node the paths of two different leaves do not share.
+fn critbit(index: u32, other: u32) -> usize {
+    (31 - (index ^ other).leading_zeros()) as usize
+}
+
+/// Hash of a Bubblegum V1 leaf, the node the tree stores.
+pub fn hash_leaf(leaf: &LeafSchema) -> Node {
+    keccak
+        ::hashv(
+            &[
+                &[1],
+                leaf.id.as_ref(),
+                leaf.owner.as_ref(),
+                leaf.delegate.as_ref(),
+                &leaf.nonce.to_le_bytes(),
+                &leaf.data_hash,
+                &leaf.creator_hash,
+            ]
+        )
+        .to_bytes()
+}
+
+/// `data_hash` of a leaf: the hashed metadata, hashed again with the royalties.
+pub fn hash_metadata(metadata: &MetadataArgs) -> Result<Node, Box<dyn Error>> {
+    let metadata_hash = keccak::hash(&borsh::to_vec(metadata)?).to_bytes();
+    let royalties = metadata.seller_fee_basis_points.to_le_bytes();
+    Ok(keccak::hashv(&[&metadata_hash, &royalties]).to_bytes())
+}
+
+/// `creator_hash` of a leaf: each creator's address, verified flag and share.
+pub fn hash_creators(creators: &[Creator]) -> Node {
+    let creators: Vec<Vec<u8>> = creators
+        .iter()
+        .map(|creator| {
+            [creator.address.as_ref(), &[creator.verified as u8], &[creator.share]].concat()
+        })
+        .collect();
+    let slices: Vec<&[u8]> = creators.iter().map(Vec::as_slice).collect();
+    keccak::hashv(&slices).to_bytes()
+}
+
+/// Id of the asset minted at leaf `nonce`.
+pub fn asset_id(merkle_tree: &Pubkey, nonce: u64) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"asset", merkle_tree.as_ref(), &nonce.to_le_bytes()],
+        &BUBBLEGUM_PROGRAM_ID
+    ).0
+}
+
+pub fn encode_node(node: &Node) -> String {
+    bs58::encode(node).into_string()
+}
+
+pub fn parse_node(name: &str, value: &str) -> Result<Node, Box<dyn Error>> {
+    let bytes = bs58::decode(value).into_vec().ok();
+    bytes
+        .and_then(|bytes| Node::try_from(bytes).ok())
+        .ok_or_else(|| {
+            format!("Invalid {} '{}': expected a base58 32-byte hash", name, value).into()
+        })
+}
+
+/// One change to the tree: the new root, the new nodes from the leaf upwards, and the leaf.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChangeLog {
+    pub root: Node,
+    pub path: Vec<Node>,
+    pub index: u32,
+}
+
+impl ChangeLog {
+    /// Brings the proof of another leaf up to date with this change, which replaced the one
+    /// node of the proof on its path. A proof of this change's own leaf cannot be updated, so
+    /// the new leaf is returned instead of `leaf`.
+    fn update_proof_or_leaf(&self, index: u32, proof: &mut [Node], leaf: Node) -> Node {
+        if index == self.index {
+            return self.path[0];
+        }
+        let level = critbit(index, self.index);
+        proof[level] = self.path[level];
+        leaf
+    }
+}
+
+/// The rightmost leaf and its proof, which appends extend. `index` counts the leaves appended.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Path {
+    pub proof: Vec<Node>,
+    pub leaf: Node,
+    pub index: u32,
+}
+
+/// The SPL concurrent Merkle tree, as the compression program keeps it in the tree account.
+/// Only the recent changes and the rightmost proof are kept, which is enough to append and to
+/// accept proofs against any root still in the changelog buffer.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConcurrentMerkleTree {
+    pub max_depth: u32,
+    pub max_buffer_size: u32,
+    pub canopy_depth: u32,
+    pub sequence_number: u64,
+    /// Slot of `change_logs` holding the latest change.
+    pub active_index: u64,
+    /// Slots of `change_logs` in use.
+    pub buffer_size: u64,
+    pub change_logs: Vec<ChangeLog>,
+    pub rightmost_proof: Path,
+    /// Every node of the top `canopy_depth` levels below the root, the root's children first.
+    /// Nodes never written are zero and stand for empty subtrees.
+    pub canopy: Vec<Node>,
+}
+
+fn read_nodes(data: &mut &[u8], count: usize) -> io::Result<Vec<Node>> {
+    (0..count).map(|_| Node::deserialize(data)).collect()
+}
+
+impl ConcurrentMerkleTree {
+    /// An empty tree, as `create_tree` initializes it.
+    pub fn new(max_depth: u32, max_buffer_size: u32, canopy_depth: u32) -> Self {
+        let empty_path: Vec<Node> = (0..max_depth).map(empty_node).collect();
+        let mut change_logs = vec![
+            ChangeLog { root: EMPTY, path: vec![EMPTY; max_depth as usize], index: 0 };
+            max_buffer_size as usize
+        ];
+        change_logs[0] = ChangeLog {
+            root: empty_node(max_depth),
+            path: empty_path.clone(),
+            index: 0,
+        };
+        ConcurrentMerkleTree {
+            max_depth,
+            max_buffer_size,
+            canopy_depth,
+            sequence_number: 0,
+            active_index: 0,
+            buffer_size: 1,
+            change_logs,
+            rightmost_proof: Path { proof: empty_path, leaf: EMPTY, index: 0 },
+            canopy: vec![EMPTY; (2 << canopy_depth) - 2],
+        }
+    }
+
+    /// Decodes a tree account: the header, the tree and the canopy.
+    pub fn from_account_data(data: &[u8]) -> Result<Self, Box<dyn Error>> {
+        let header = decode_tree_header(data)?;
+        let depth = header.max_depth as usize;
+        let data = &mut &data[TREE_HEADER_SIZE..];
+        let sequence_number = u64::deserialize(data)?;
+        let active_index = u64::deserialize(data)?;
+        let buffer_size = u64::deserialize(data)?;
+        let max_buffer_size = u64::from(header.max_buffer_size);
+        if active_index >= max_buffer_size || buffer_size > max_buffer_size {
+            return Err("The tree's changelog buffer is corrupt".into());
+        }
+        let mut change_logs = Vec::with_capacity(header.max_buffer_size as usize);
+        for _ in 0..header.max_buffer_size {
+            let root = Node::deserialize(data)?;
+            let path = read_nodes(data, depth)?;
+            let index = u32::deserialize(data)?;
+            u32::deserialize(data)?;
+            change_logs.push(ChangeLog { root, path, index });
+        }
+        let proof = read_nodes(data, depth)?;
+        let leaf = Node::deserialize(data)?;
+        let index = u32::deserialize(data)?;
+        u32::deserialize(data)?;
+        let canopy = read_nodes(data, data.len() / 32)?;
+        Ok(ConcurrentMerkleTree {
+            max_depth: header.max_depth,
+            max_buffer_size: header.max_buffer_size,
+            canopy_depth: header.canopy_depth,
+            sequence_number,
+            active_index,
+            buffer_size,
+            change_logs,
+            rightmost_proof: Path { proof, leaf, index },
+            canopy,
+        })
+    }
+
+    pub fn root(&self) -> Node {
+        self.change_logs[self.active_index as usize].root
+    }
+
+    fn capacity(&self) -> u64 {
+        1 << self.max_depth
+    }
+
+    /// Slot of the change `age` changes before the latest.
+    fn slot(&self, age: u64) -> u64 {
+        let size = u64::from(self.max_buffer_size);
+        (self.active_index + size - (age % size)) % size
+    }
+
+    fn change_log(&self, age: u64) -> &ChangeLog {
+        &self.change_logs[self.slot(age) as usize]
+    }
+
+    fn advance(&mut self) {
+        self.active_index = (self.active_index + 1) % u64::from(self.max_buffer_size);
+        self.buffer_size = (self.buffer_size + 1).min(u64::from(self.max_buffer_size));
+        self.sequence_number += 1;
+    }
+
+    /// Adds `leaf` after the rightmost leaf, as Bubblegum's mints do. Only the rightmost proof
+    /// is needed: below where the new leaf's path meets the old one its siblings are empty.
+    pub fn append(&mut self, leaf: Node) -> Result<Node, Box<dyn Error>> {
+        if leaf == EMPTY {
+            return Err("An empty leaf cannot be appended".into());
+        }
+        let index = self.rightmost_proof.index;
+        if u64::from(index) >= self.capacity() {
+            return Err(format!("The tree is full with {} leaves", index).into());
+        }
+        if index == 0 {
+            let proof = self.rightmost_proof.proof.clone();
+            return Ok(self.write_change(leaf, &proof, 0));
+        }
+        let intersection = index.trailing_zeros() as usize;
+        let mut node = leaf;
+        let mut intersection_node = self.rightmost_proof.leaf;
+        let mut path = vec![EMPTY; self.max_depth as usize];
+        for level in 0..self.max_depth as usize {
+            path[level] = node;
+            let previous_is_left = ((index - 1) >> level) & 1 == 0;
+            let proof = &mut self.rightmost_proof.proof;
+            if level < intersection {
+                let sibling = empty_node(level as u32);
+                hash_to_parent(&mut intersection_node, &proof[level], previous_is_left);
+                hash_to_parent(&mut node, &sibling, true);
+                proof[level] = sibling;
+            } else if level == intersection {
+                hash_to_parent(&mut node, &intersection_node, false);
+                proof[level] = intersection_node;
+            } else {
+                hash_to_parent(&mut node, &proof[level], previous_is_left);
+            }
+        }
+        self.advance();
+        self.change_logs[self.active_index as usize] = ChangeLog { root: node, path, index };
+        self.rightmost_proof.index = index + 1;
+        self.rightmost_proof.leaf = leaf;
+        self.update_canopy();
+        Ok(node)
+    }
+
+    /// Replaces `previous_leaf` at `index` with `new_leaf`, as transfers and burns do. The
+    /// proof may be against any root still in the changelog buffer.
+    pub fn set_leaf(
+        &mut self,
+        root: Node,
+        previous_leaf: Node,
+        new_leaf: Node,
+        proof: &[Node],
+        index: u32
+    ) -> Result<Node, Box<dyn Error>> {
+        let proof = self.prove_leaf(root, previous_leaf, proof, index)?;
+        Ok(self.write_change(new_leaf, &proof, index))
+    }
+
+    /// Checks a proof the way the compression program does: `root` must still be in the
+    /// changelog buffer, and the proof is brought up to date with every change since before it
+    /// is checked against the current root. Returns the updated proof.
+    pub fn prove_leaf(
+        &self,
+        root: Node,
+        leaf: Node,
+        proof: &[Node],
+        index: u32
+    ) -> Result<Vec<Node>, Box<dyn Error>> {
+        if index >= self.rightmost_proof.index {
+            return Err(
+                format!(
+                    "Leaf {} has not been minted; the tree has {} leaves",
+                    index,
+                    self.rightmost_proof.index
+                ).into()
+            );
+        }
+        if proof.len() != (self.max_depth as usize) {
+            return Err(
+                format!("The proof has {} nodes, not {}", proof.len(), self.max_depth).into()
+            );
+        }
+        let age = (0..self.buffer_size)
+            .find(|age| self.change_log(*age).root == root)
+            .ok_or("The proof's root is no longer in the tree's changelog buffer")?;
+        let mut proof = proof.to_vec();
+        let mut current = leaf;
+        for age in (0..age).rev() {
+            current = self.change_log(age).update_proof_or_leaf(index, &mut proof, current);
+        }
+        if current != leaf {
+            return Err(format!("Leaf {} has changed since the proof's root", index).into());
+        }
+        if recompute(leaf, &proof, index) != self.root() {
+            return Err("The proof does not lead to the tree's root".into());
+        }
+        Ok(proof)
+    }
+
+    /// Completes a proof cut short by the canopy, as transfers pass them.
+    pub fn fill_in_proof(&self, proof: &[Node], index: u32) -> Result<Vec<Node>, Box<dyn Error>> {
+        let depth = self.max_depth as usize;
+        let required = depth - (self.canopy_depth as usize);
+        if proof.len() < required || proof.len() > depth {
+            return Err(
+                format!(
+                    "The proof has {} nodes; with a canopy of {}, between {} and {} are needed",
+                    proof.len(),
+                    self.canopy_depth,
+                    required,
+                    depth
+                ).into()
+            );
+        }
+        let mut full = proof.to_vec();
+        for level in proof.len()..depth {
+            let sibling = ((self.capacity() + u64::from(index)) >> level) ^ 1;
+            let cached = self.canopy[(sibling as usize) - 2];
+            full.push(if cached == EMPTY { empty_node(level as u32) } else { cached });
+        }
+        Ok(full)
+    }
+
+    /// Records a change from `leaf` at `index`, with an up-to-date proof, and returns the root.
+    fn write_change(&mut self, leaf: Node, proof: &[Node], index: u32) -> Node {
+        self.advance();
+        let mut node = leaf;
+        let mut path = Vec::with_capacity(proof.len());
+        for (level, sibling) in proof.iter().enumerate() {
+            path.push(node);
+            hash_to_parent(&mut node, sibling, (index >> level) & 1 == 0);
+        }
+        let rightmost = &mut self.rightmost_proof;
+        if u64::from(rightmost.index) < 1 << self.max_depth {
+            if index + 1 < rightmost.index {
+                let level = critbit(index, rightmost.index - 1);
+                rightmost.proof[level] = path[level];
+            } else if index + 1 == rightmost.index {
+                rightmost.leaf = leaf;
+            } else {
+                rightmost.proof = proof.to_vec();
+                rightmost.index = index + 1;
+                rightmost.leaf = leaf;
+            }
+        }
+        self.change_logs[self.active_index as usize] = ChangeLog { root: node, path, index };
+        self.update_canopy();
+        node
+    }
+
+    /// Copies the canopy levels of the latest change's path into the canopy.
+    fn update_canopy(&mut self) {
+        let change_log = &self.change_logs[self.active_index as usize];
+        for level in self.max_depth - self.canopy_depth..self.max_depth {
+            let node_index = (self.capacity() + u64::from(change_log.index)) >> level;
+            self.canopy[(node_index as usize) - 2] = change_log.path[level as usize];
+        }
+    }
+
+    /// Parts of the two trees that differ. Changes are compared as far back as both buffers
+    /// go, so the trees should be at the same sequence number.
+    pub fn differences(&self, other: &Self) -> Vec<&'static str> {
+        let mut differences = Vec::new();
+        if self.sequence_number != other.sequence_number {
+            differences.push("sequence_number");
+        }
+        if self.root() != other.root() {
+            differences.push("root");
+        }
+        let (rightmost, other_rightmost) = (&self.rightmost_proof, &other.rightmost_proof);
+        if rightmost.index != other_rightmost.index || rightmost.leaf != other_rightmost.leaf {
+            differences.push("rightmost_leaf");
+        }
+        let recent = self.buffer_size.min(other.buffer_size);
+        if (0..recent).any(|age| self.change_log(age) != other.change_log(age)) {
+            differences.push("change_logs");
+        }
+        if self.canopy != other.canopy {
+            differences.push("canopy");
+        }
+        differences
+    }
+}
+
+/// Every node of a tree, as rebuilt from its changes. Nodes are numbered from the root, 1, to
+/// the leaves, `2^depth + index`; nodes never written are empty.
+pub struct MerkleTree {
+    max_depth: u32,
+    nodes: HashMap<u64, Node>,
+    empty_nodes: Vec<Node>,
+}
+
+impl MerkleTree {
+    pub fn new(max_depth: u32) -> Self {
+        let empty_nodes = (0..=max_depth).map(empty_node).collect();
+        MerkleTree { max_depth, nodes: HashMap::new(), empty_nodes }
+    }
+
+    fn node(&self, node_index: u64) -> Node {
+        let level = self.max_depth - (63 - node_index.leading_zeros());
+        self.nodes.get(&node_index).copied().unwrap_or(self.empty_nodes[level as usize])
+    }
+
+    fn leaf_node_index(&self, index: u32) -> u64 {
+        (1 << self.max_depth) + u64::from(index)
+    }
+
+    pub fn root(&self) -> Node {
+        self.node(1)
+    }
+
+    pub fn leaf(&self, index: u32) -> Node {
+        self.node(self.leaf_node_index(index))
+    }
+
+    /// Siblings of the path from leaf `index` to the root.
+    pub fn proof(&self, index: u32) -> Vec<Node> {
+        let leaf = self.leaf_node_index(index);
+        (0..self.max_depth).map(|level| self.node((leaf >> level) ^ 1)).collect()
+    }
+
+    pub fn apply(&mut self, change_log: &ChangeLog) {
+        let leaf = self.leaf_node_index(change_log.index);
+        for (level, node) in change_log.path.iter().enumerate() {
+            self.nodes.insert(leaf >> level, *node);
+        }
+        self.nodes.insert(1, change_log.root);
+    }
+}
+
+#[derive(BorshDeserialize)]
+struct PathNode {
+    node: Node,
+    /// Number of the node, the root being 1.
+    index: u32,
+}
+
+/// `ChangeLogEvent` V1, which the compression program logs after every change.
+#[derive(BorshDeserialize)]
+pub struct ChangeLogEvent {
+    pub id: Pubkey,
+    /// The new path from the leaf up to and including the root.
+    path: Vec<PathNode>,
+    pub seq: u64,
+    pub index: u32,
+}
+
+impl ChangeLogEvent {
+    pub fn change_log(&self, max_depth: u32) -> Result<ChangeLog, Box<dyn Error>> {
+        let leaf_node_index = (1u64 << max_depth) + u64::from(self.index);
+        let valid = self.path.len() == (max_depth as usize) + 1 &&
+            self.path
+                .iter()
+                .enumerate()
+                .all(|(level, node)| u64::from(node.index) == leaf_node_index >> level);
+        if !valid {
+            return Err(
+                format!("Change {} does not fit a tree of depth {}", self.seq, max_depth).into()
+            );
+        }
+        let (root, path) = self.path.split_last().unwrap();
+        Ok(ChangeLog {
+            root: root.node,
+            path: path.iter().map(|node| node.node).collect(),
+            index: self.index,
+        })
+    }
+}
+
+/// Decodes the compression program's `ChangeLog` event: tags 0 and 0, then the V1 event.
+pub fn decode_change_log_event(data: &[u8]) -> Option<ChangeLogEvent> {
+    match data {
+        [0, 0, event @ ..] => ChangeLogEvent::deserialize(&mut &event[..]).ok(),
+        _ => None,
+    }
+}
+
+/// A tree rebuilt from its history.
+pub struct Replay {
+    pub tree: ConcurrentMerkleTree,
+    pub nodes: MerkleTree,
+    /// The latest leaf Bubblegum logged at each index, while the tree still holds it.
+    pub leaves: BTreeMap<u32, LeafSchema>,
+}
+
+impl Replay {
+    pub fn new(max_depth: u32, max_buffer_size: u32, canopy_depth: u32) -> Self {
+        Replay {
+            tree: ConcurrentMerkleTree::new(max_depth, max_buffer_size, canopy_depth),
+            nodes: MerkleTree::new(max_depth),
+            leaves: BTreeMap::new(),
+        }
+    }
+
+    /// Applies the next change: an append at the rightmost index, otherwise a replacement
+    /// proven against the rebuilt tree. The root must come out as the program logged it.
+    pub fn apply(
+        &mut self,
+        change_log: &ChangeLog,
+        leaf: Option<LeafSchema>
+    ) -> Result<(), Box<dyn Error>> {
+        let index = change_log.index;
+        let new_leaf = change_log.path[0];
+        let root = if index == self.tree.rightmost_proof.index {
+            self.tree.append(new_leaf)?
+        } else {
+            let proof = self.nodes.proof(index);
+            let previous_leaf = self.nodes.leaf(index);
+            self.tree.set_leaf(self.nodes.root(), previous_leaf, new_leaf, &proof, index)?
+        };
+        if root != change_log.root {
+            return Err(
+                format!(
+                    "Change {} gives the root {}, but the program logged {}",
+                    self.tree.sequence_number,
+                    encode_node(&root),
+                    encode_node(&change_log.root)
+                ).into()
+            );
+        }
+        self.nodes.apply(change_log);
+        match leaf {
+            Some(leaf) if hash_leaf(&leaf) == new_leaf => {
+                self.leaves.insert(index, leaf);
+            }
+            _ => {
+                if self.leaves.get(&index).map(hash_leaf) != Some(new_leaf) {
+                    self.leaves.remove(&index);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Fetches the tree account named by the tree file, checking the shape the file records.
+pub fn load_tree(
+    rpc_client: &RpcClient,
+    tree_file: &str
+) -> Result<(Pubkey, ConcurrentMerkleTree), Box<dyn Error>> {
+    let file = read_tree_file(tree_file)?;
+    let merkle_tree = Pubkey::from_str(&file.public_key)?;
+    let account = rpc_client.get_account(&merkle_tree)?;
+    if account.owner != COMPRESSION_PROGRAM_ID {
+        return Err(format!("{} is not owned by the compression program", merkle_tree).into());
+    }
+    let tree = ConcurrentMerkleTree::from_account_data(&account.data)?;
+    for (name, recorded, actual) in [
+        ("depth", file.max_depth, tree.max_depth),
+        ("buffer size", file.max_buffer_size, tree.max_buffer_size),
+        ("canopy depth", file.canopy_depth, tree.canopy_depth),
+    ] {
+        if recorded.is_some_and(|recorded| recorded != actual) {
+            return Err(
+                format!(
+                    "{} records a {} of {}, but {} has {}",
+                    tree_file,
+                    name,
+                    recorded.unwrap(),
+                    merkle_tree,
+                    actual
+                ).into()
+            );
+        }
+    }
+    Ok((merkle_tree, tree))
+}
+
+/// Rebuilds a tree from the change logs in its transactions, oldest first. Failed
+/// transactions are skipped, and every change since creation must be found.
+pub fn replay_history(
+    rpc_client: &RpcClient,
+    merkle_tree: &Pubkey,
+    shape: &ConcurrentMerkleTree
+) -> Result<(Replay, usize), Box<dyn Error>> {
+    let signatures = fetch_signatures(rpc_client, merkle_tree, None, None, usize::MAX)?;
+    status(&format!("Replaying {} transactions of {}", signatures.len(), merkle_tree));
+    let mut changes = BTreeMap::new();
+    for signature in signatures.iter().rev() {
+        let transaction = fetch_transaction(rpc_client, signature)?;
+        let meta = transaction.transaction.meta.as_ref();
+        if meta.is_some_and(|meta| meta.err.is_some()) {
+            continue;
+        }
+        // Bubblegum logs a leaf just before the change that writes it.
+        let mut leaf_event = None;
+        for event in noop_events(signature, &transaction)? {
+            if let Some(leaf) = decode_leaf_event(&event) {
+                leaf_event = Some(leaf);
+                continue;
+            }
+            let Some(change) = decode_change_log_event(&event) else {
+                continue;
+            };
+            let leaf = leaf_event.take();
+            if change.id != *merkle_tree || change.seq == 0 {
+                continue;
+            }
+            let leaf = leaf.filter(|leaf| {
+                leaf.nonce == u64::from(change.index) &&
+                    leaf.id == asset_id(merkle_tree, leaf.nonce)
+            });
+            changes.insert(change.seq, (change.change_log(shape.max_depth)?, leaf));
+        }
+    }
+
+    let mut replay = Replay::new(shape.max_depth, shape.max_buffer_size, shape.canopy_depth);
+    for (expected, (seq, (change_log, leaf))) in (1..).zip(changes) {
+        if seq != expected {
+            return Err(
+                format!(
+                    "Change {} is missing from the history; the RPC node may not keep \
+                     transactions that old",
+                    expected
+                ).into()
+            );
+        }
+        replay.apply(&change_log, leaf)?;
+    }
+    Ok((replay, signatures.len()))
+}
+
+fn leaf_json(index: u32, leaf: &Node, schema: Option<&LeafSchema>) -> Value {
+    json!({
+        "index": index,
+        "leaf": encode_node(leaf),
+        "asset_id": schema.map(|schema| schema.id.to_string()),
+        "owner": schema.map(|schema| schema.owner.to_string()),
+        "delegate": schema.map(|schema| schema.delegate.to_string()),
+        "data_hash": schema.map(|schema| encode_node(&schema.data_hash)),
+        "creator_hash": schema.map(|schema| encode_node(&schema.creator_hash)),
+    })
+}
+
+pub fn replay_tree(rpc_client: &RpcClient, tree_file: &str) -> Result<(), Box<dyn Error>> {
+    let (merkle_tree, shape) = load_tree(rpc_client, tree_file)?;
+    let (replay, transactions) = replay_history(rpc_client, &merkle_tree, &shape)?;
+    let (_, on_chain) = load_tree(rpc_client, tree_file)?;
+    let differences = replay.tree.differences(&on_chain);
+
+    let tree = &replay.tree;
+    let leaves: Vec<Value> = (0..tree.rightmost_proof.index)
+        .map(|index| leaf_json(index, &replay.nodes.leaf(index), replay.leaves.get(&index)))
+        .collect();
+    emit(
+        json!({
+            "tree": merkle_tree.to_string(),
+            "max_depth": tree.max_depth,
+            "max_buffer_size": tree.max_buffer_size,
+            "canopy_depth": tree.canopy_depth,
+            "transactions": transactions,
+            "sequence_number": tree.sequence_number,
+            "root": encode_node(&tree.root()),
+            "matches_chain": differences.is_empty(),
+            "differences": differences,
+            "leaves": leaves,
+        }),
+        || {
+            println!("Tree: {}", merkle_tree);
+            println!(
+                "Replayed {} changes from {} transactions",
+                tree.sequence_number,
+                transactions
+            );
+            println!("Root: {}", encode_node(&tree.root()));
+            if differences.is_empty() {
+                println!("Matches the tree account");
+            } else {
+                println!("Differs from the tree account in: {}", differences.join(", "));
+                if on_chain.sequence_number > tree.sequence_number {
+                    println!("The tree changed during the replay; run it again");
+                }
+            }
+            println!("Leaves: {}", tree.rightmost_proof.index);
+            for index in 0..tree.rightmost_proof.index {
+                let leaf = replay.nodes.leaf(index);
+                match replay.leaves.get(&index) {
+                    Some(schema) => println!("{}: {} owned by {}", index, schema.id, schema.owner),
+                    None if leaf == EMPTY => println!("{}: empty", index),
+                    None => println!("{}: {}", index, encode_node(&leaf)),
+                }
+            }
+        }
+    );
+    Ok(())
+}
+
+pub fn leaf_proof(
+    rpc_client: &RpcClient,
+    tree_file: &str,
+    cmd: &CnftProofCommand
+) -> Result<(), Box<dyn Error>> {
+    let (merkle_tree, shape) = load_tree(rpc_client, tree_file)?;
+    let index = cmd.leaf_index;
+    if u64::from(index) >= 1 << shape.max_depth {
+        return Err(
+            format!("Leaf {} is outside a tree of {} leaves", index, 1u64 << shape.max_depth).into()
+        );
+    }
+    let (replay, _) = replay_history(rpc_client, &merkle_tree, &shape)?;
+    let leaf = replay.nodes.leaf(index);
+    let root = replay.nodes.root();
+    let proof = replay.nodes.proof(index);
+    let (_, on_chain) = load_tree(rpc_client, tree_file)?;
+    on_chain.prove_leaf(root, leaf, &proof, index)?;
+
+    let required = shape.max_depth - shape.canopy_depth;
+    let mut result = leaf_json(index, &leaf, replay.leaves.get(&index));
+    result["tree"] = json!(merkle_tree.to_string());
+    result["root"] = json!(encode_node(&root));
+    result["proof"] = json!(proof.iter().map(encode_node).collect::<Vec<_>>());
+    result["required_proof_length"] = json!(required);
+    emit(result, || {
+        println!("Tree: {}", merkle_tree);
+        println!("Leaf {}: {}", index, encode_node(&leaf));
+        if let Some(schema) = replay.leaves.get(&index) {
+            println!("Asset id: {}", schema.id);
+            println!("Owner: {}", schema.owner);
+            println!("Delegate: {}", schema.delegate);
+        }
+        println!("Root: {}", encode_node(&root));
+        println!("Proof, from the leaf's sibling upwards:");
+        for (level, node) in proof.iter().enumerate() {
+            let canopy = if (level as u32) < required { "" } else { " (in the canopy)" };
+            println!("  {}{}", encode_node(node), canopy);
+        }
+        println!("Verified against the tree account");
+    });
+    Ok(())
+}
+
+pub fn verify_proof(
+    rpc_client: &RpcClient,
+    tree_file: &str,
+    cmd: &CnftVerifyCommand
+) -> Result<(), Box<dyn Error>> {
+    let (merkle_tree, tree) = load_tree(rpc_client, tree_file)?;
+    let index = cmd.leaf_index;
+    let leaf = match &cmd.leaf {
+        Some(leaf) => parse_node("leaf", leaf)?,
+        None => {
+            let missing = "Pass --leaf, or --owner with --data-hash and --creator-hash";
+            let owner = Pubkey::from_str(cmd.owner.as_deref().ok_or(missing)?)?;
+            let delegate = match &cmd.delegate {
+                Some(delegate) => Pubkey::from_str(delegate)?,
+                None => owner,
+            };
+            hash_leaf(
+                &(LeafSchema {
+                    id: asset_id(&merkle_tree, u64::from(index)),
+                    owner,
+                    delegate,
+                    nonce: u64::from(index),
+                    data_hash: parse_node("data hash", cmd.data_hash.as_deref().ok_or(missing)?)?,
+                    creator_hash: parse_node(
+                        "creator hash",
+                        cmd.creator_hash.as_deref().ok_or(missing)?
+                    )?,
+                })
+            )
+        }
+    };
+    let root = parse_node("root", &cmd.root)?;
+    let proof = cmd.proof
+        .iter()
+        .map(|node| parse_node("proof node", node))
+        .collect::<Result<Vec<_>, _>>()?;
+    let proof = tree.fill_in_proof(&proof, index)?;
+    tree.prove_leaf(root, leaf, &proof, index).map_err(|e| {
+        format!("The proof of leaf {} is not valid: {}", index, e)
+    })?;
+    emit(
+        json!({
+            "tree": merkle_tree.to_string(),
+            "leaf_index": index,
+            "leaf": encode_node(&leaf),
+            "root": encode_node(&root),
+            "current_root": encode_node(&tree.root()),
+            "valid": true,
+        }),
+        || {
+            println!("The proof of leaf {} is valid", index);
+            if root != tree.root() {
+                println!("Its root is older than the current one, but still in the buffer");
+            }
+        }
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaf(byte: u8) -> Node {
+        [byte; 32]
+    }
+
+    /// Root of a full tree of `leaves`, hashed level by level.
+    fn naive_root(max_depth: u32, leaves: &[Node]) -> Node {
+        let mut level: Vec<Node> = leaves.to_vec();
+        level.resize(1 << max_depth, EMPTY);
+        while level.len() > 1 {
+            level = level
+                .chunks(2)
+                .map(|pair| keccak::hashv(&[&pair[0], &pair[1]]).to_bytes())
+                .collect();
+        }
+        level[0]
+    }
+
+    #[test]
+    fn hashes_empty_subtrees_with_keccak() {
+        assert_eq!(empty_node(0), EMPTY);
+        // Keccak-256 of 64 zero bytes.
+        assert_eq!(encode_node(&empty_node(1)), "Cf5tmmFZ4D31tviuJezHdFLf5WF7yFvzfxNyftKsqTwr");
+        let tree = ConcurrentMerkleTree::new(3, 8, 0);
+        assert_eq!(tree.root(), naive_root(3, &[]));
+    }
+
+    #[test]
+    fn appends_and_replaces_like_a_full_tree() {
+        let mut tree = ConcurrentMerkleTree::new(4, 8, 2);
+        let mut nodes = MerkleTree::new(4);
+        let mut leaves = Vec::new();
+        let record = |tree: &ConcurrentMerkleTree, nodes: &mut MerkleTree| {
+            nodes.apply(&tree.change_logs[tree.active_index as usize]);
+        };
+        for byte in 1..=11 {
+            tree.append(leaf(byte)).unwrap();
+            record(&tree, &mut nodes);
+            leaves.push(leaf(byte));
+            assert_eq!(tree.root(), naive_root(4, &leaves));
+        }
+        // A transfer of the rightmost leaf, then another append.
+        let proof = nodes.proof(10);
+        tree.set_leaf(nodes.root(), leaf(11), leaf(99), &proof, 10).unwrap();
+        record(&tree, &mut nodes);
+        leaves[10] = leaf(99);
+        tree.append(leaf(12)).unwrap();
+        record(&tree, &mut nodes);
+        leaves.push(leaf(12));
+        assert_eq!(tree.root(), naive_root(4, &leaves));
+        assert_eq!(nodes.root(), tree.root());
+        assert_eq!(tree.sequence_number, 13);
+
+        // A proof made before later changes is brought up to date.
+        let stale_root = tree.root();
+        let stale_proof = nodes.proof(3);
+        tree.append(leaf(13)).unwrap();
+        record(&tree, &mut nodes);
+        let proof = nodes.proof(1);
+        tree.set_leaf(nodes.root(), leaf(2), EMPTY, &proof, 1).unwrap();
+        record(&tree, &mut nodes);
+        assert!(tree.prove_leaf(stale_root, leaf(4), &stale_proof, 3).is_ok());
+        assert!(tree.prove_leaf(stale_root, leaf(2), &nodes.proof(1), 1).is_err());
+        assert!(tree.prove_leaf(stale_root, leaf(5), &stale_proof, 3).is_err());
+        assert!(tree.prove_leaf(nodes.root(), leaf(1), &nodes.proof(0), 14).is_err());
+
+        // The top two levels come from the canopy.
+        let full = nodes.proof(6);
+        assert_eq!(tree.fill_in_proof(&full[..2], 6).unwrap(), full);
+        assert!(tree.fill_in_proof(&full[..1], 6).is_err());
+    }
+
+    #[test]
+    fn replays_change_log_events() {
+        let merkle_tree = Pubkey::new_unique();
+        let mut tree = ConcurrentMerkleTree::new(3, 8, 1);
+        let mut replay = Replay::new(3, 8, 1);
+        for nonce in 0..3 {
+            let schema = LeafSchema {
+                id: asset_id(&merkle_tree, nonce),
+                owner: Pubkey::new_unique(),
+                delegate: Pubkey::new_unique(),
+                nonce,
+                data_hash: [1; 32],
+                creator_hash: hash_creators(&[]),
+            };
+            tree.append(hash_leaf(&schema)).unwrap();
+
+            // Encoded as the compression program logs it.
+            let change_log = &tree.change_logs[tree.active_index as usize];
+            let mut event = vec![0, 0];
+            event.extend(merkle_tree.to_bytes());
+            event.extend(4u32.to_le_bytes());
+            for (level, node) in change_log.path.iter().chain([&change_log.root]).enumerate() {
+                event.extend(node);
+                event.extend((((8 + nonce) >> level) as u32).to_le_bytes());
+            }
+            event.extend(tree.sequence_number.to_le_bytes());
+            event.extend((nonce as u32).to_le_bytes());
+            let change = decode_change_log_event(&event).unwrap();
+            assert_eq!(change.change_log(3).unwrap(), *change_log);
+            assert!(change.change_log(4).is_err());
+            replay.apply(change_log, Some(schema)).unwrap();
+        }
+        assert!(replay.tree.differences(&tree).is_empty());
+        assert_eq!(replay.leaves.len(), 3);
+        assert!(decode_change_log_event(&[1, 0, 0]).is_none());
+    }
+}
@@ -0,0 +1,133 @@
+use bip39::{ Language, Mnemonic, MnemonicType };
+use solana_sdk::{
+    derivation_path::DerivationPath,
+    signature::{
+        generate_seed_from_seed_phrase_and_passphrase,
+        keypair_from_seed_and_derivation_path,
+        Keypair,
+    },
+};
+use std::{ error::Error, path::Path };
+
+use crate::{ keystore::read_passphrase, state::WordCount };
+
+pub const MNEMONIC_ENV: &str = "SCW_MNEMONIC";
+pub const SEED_PASSPHRASE_ENV: &str = "SCW_SEED_PASSPHRASE";
+
+impl From<WordCount> for usize {
+    fn from(words: WordCount) -> Self {
+        match words {
+            WordCount::Twelve => 12,
+            WordCount::TwentyFour => 24,
+        }
+    }
+}
+
+pub fn generate_mnemonic(word_count: usize) -> Result<Mnemonic, Box<dyn Error>> {
+    let mnemonic_type = MnemonicType::for_word_count(word_count)?;
+    Ok(Mnemonic::new(mnemonic_type, Language::English))
+}
+
+/// Validates the word list and checksum, tolerating extra whitespace and capitals.
+pub fn parse_mnemonic(phrase: &str) -> Result<Mnemonic, Box<dyn Error>> {
+    let normalized = phrase.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+    Mnemonic::from_phrase(&normalized, Language::English).map_err(|e| {
+        format!("Invalid mnemonic: {}", e).into()
+    })
+}
+
+pub fn read_seed_passphrase(enabled: bool) -> Result<String, Box<dyn Error>> {
+    if !enabled {
+        return Ok(String::new());
+    }
+    read_passphrase("BIP39 passphrase: ", SEED_PASSPHRASE_ENV)
+}
+
+/// The path Phantom and Solflare use for account `index`: m/44'/501'/index'/0'.
+pub fn derivation_path(index: u32) -> DerivationPath {
+    DerivationPath::new_bip44(Some(index), Some(0))
+}
+
+pub struct DerivedAccount {
+    pub index: u32,
+    pub path: DerivationPath,
+    pub keypair: Keypair,
+}
+
+/// Derives `count` SLIP-10 keypairs starting at account `start`.
+pub fn derive_keypairs(
+    phrase: &str,
+    seed_passphrase: &str,
+    start: u32,
+    count: u32
+) -> Result<Vec<DerivedAccount>, Box<dyn Error>> {
+    let end = start.checked_add(count).ok_or("Derivation index out of range")?;
+    let seed = generate_seed_from_seed_phrase_and_passphrase(phrase, seed_passphrase);
+    (start..end)
+        .map(|index| {
+            let path = derivation_path(index);
+            let keypair = keypair_from_seed_and_derivation_path(&seed, Some(path.clone()))?;
+            Ok(DerivedAccount { index, path, keypair })
+        })
+        .collect()
+}
+
+/// Turns `account.json` into `account-3.json` for the account at `index`.
+pub fn indexed_file_path(file_path: &str, index: u32) -> String {
+    let path = Path::new(file_path);
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy())
+        .unwrap_or_default();
+    let file_name = match path.extension() {
+        Some(ext) => format!("{}-{}.{}", stem, index, ext.to_string_lossy()),
+        None => format!("{}-{}", stem, index),
+    };
+    path.with_file_name(file_name).to_string_lossy().into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::signer::Signer;
+
+    const PHRASE: &str =
+        "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+
+    #[test]
+    fn derivation_is_deterministic_per_index() {
+        let first = derive_keypairs(PHRASE, "", 0, 2).unwrap();
+        let again = derive_keypairs(PHRASE, "", 1, 1).unwrap();
+        assert_eq!(first[1].keypair.pubkey(), again[0].keypair.pubkey());
+        assert_ne!(first[0].keypair.pubkey(), first[1].keypair.pubkey());
+        assert_eq!(format!("{:?}", first[1].path), "m/44'/501'/1'/0'");
+    }
+
+    #[test]
+    fn matches_phantom_first_account() {
+        let accounts = derive_keypairs(PHRASE, "", 0, 1).unwrap();
+        assert_eq!(
+            accounts[0].keypair.pubkey().to_string(),
+            "HAgk14JpMQLgt6rVgv7cBQFJWFto5Dqxi472uT3DKpqk"
+        );
+    }
+
+    #[test]
+    fn seed_passphrase_changes_accounts() {
+        let plain = derive_keypairs(PHRASE, "", 0, 1).unwrap();
+        let protected = derive_keypairs(PHRASE, "extra", 0, 1).unwrap();
+        assert_ne!(plain[0].keypair.pubkey(), protected[0].keypair.pubkey());
+    }
+
+    #[test]
+    fn mnemonic_checksum_is_validated() {
+        assert!(parse_mnemonic(&PHRASE.to_uppercase()).is_ok());
+        assert!(parse_mnemonic(&PHRASE.replace("about", "abandon")).is_err());
+    }
+
+    #[test]
+    fn indexed_paths_keep_extension() {
+        assert_eq!(indexed_file_path("keys/account.json", 3), "keys/account-3.json");
+        assert_eq!(indexed_file_path("account", 0), "account-0");
+    }
+}
@@ -0,0 +1,283 @@
+use borsh::{ BorshDeserialize, BorshSerialize };
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{
+    instruction::{ AccountMeta, Instruction },
+    pubkey::Pubkey,
+    signature::Keypair,
+    signer::Signer,
+    system_program,
+};
+use serde_json::json;
+use std::{ error::Error, str::FromStr };
+
+use crate::{
+    amount::{ Amount, SOL_DECIMALS },
+    keystore::read_keys,
+    output::emit,
+    state::*,
+    token::format_token_amount,
+    transaction::send_instructions,
+    wallet::Wallet,
+};
+
+/// Size of a deposit account: the total deposited, as a little-endian `u64`.
+pub const DEPOSIT_ACCOUNT_SPACE: usize = 8;
+
+/// `VaultState` of the s4 program: whether a vault is set up, its owner and what it holds.
+#[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq)]
+pub struct VaultState {
+    pub is_initialized: bool,
+    pub owner: Pubkey,
+    pub total_deposits: u64,
+}
+
+/// Instructions of the s4 native vault program, Borsh-encoded in this variant order.
+#[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq)]
+pub enum VaultInstruction {
+    DepositInstruction(u64),
+    WithdrawalInstruction(),
+    Initialize,
+}
+
+/// Creates `deposit_account`, which must sign, with `payer` funding it.
+pub fn initialize_instruction(
+    program_id: &Pubkey,
+    deposit_account: &Pubkey,
+    payer: &Pubkey
+) -> Instruction {
+    Instruction::new_with_borsh(*program_id, &VaultInstruction::Initialize, vec![
+        AccountMeta::new(*deposit_account, true),
+        AccountMeta::new(*payer, true),
+        AccountMeta::new_readonly(system_program::id(), false)
+    ])
+}
+
+pub fn deposit_instruction(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    deposit_account: &Pubkey,
+    lamports: u64
+) -> Instruction {
+    Instruction::new_with_borsh(*program_id, &VaultInstruction::DepositInstruction(lamports), vec![
+        AccountMeta::new(*payer, true),
+        AccountMeta::new(*deposit_account, false),
+        AccountMeta::new_readonly(system_program::id(), false)
+    ])
+}
+
+/// Moves a tenth of the total deposited to `recipient`. The program requires no signature.
+pub fn withdraw_instruction(
+    program_id: &Pubkey,
+    deposit_account: &Pubkey,
+    recipient: &Pubkey
+) -> Instruction {
+    Instruction::new_with_borsh(*program_id, &VaultInstruction::WithdrawalInstruction(), vec![
+        AccountMeta::new(*deposit_account, false),
+        AccountMeta::new(*recipient, false),
+        AccountMeta::new_readonly(system_program::id(), false)
+    ])
+}
+
+/// Reads the total deposited from a deposit account's data.
+pub fn decode_total_deposits(data: &[u8]) -> Result<u64, Box<dyn Error>> {
+    let total = data.get(..DEPOSIT_ACCOUNT_SPACE).ok_or_else(|| {
+        format!("A deposit account holds at least {} bytes", DEPOSIT_ACCOUNT_SPACE)
+    })?;
+    Ok(u64::from_le_bytes(total.try_into()?))
+}
+
+pub fn native_vault_command(
+    rpc_client: &RpcClient,
+    options: &TransactionArgs,
+    wallet: &Wallet,
+    cmd: &NativeVaultCommand
+) -> Result<(), Box<dyn Error>> {
+    let program_id = Pubkey::from_str(
+        cmd.program_id
+            .as_deref()
+            .ok_or("--program-id is required; the native vault program has no fixed address")?
+    )?;
+    let signer_path = wallet.resolve(cmd.from.as_deref())?;
+    let payer = || read_keys(&signer_path);
+    match &cmd.operation {
+        NativeVaultOperations::Initialize => {
+            initialize(rpc_client, options, &program_id, &payer()?)
+        }
+        NativeVaultOperations::Deposit(cmd) => {
+            deposit(rpc_client, options, &program_id, &payer()?, cmd)
+        }
+        NativeVaultOperations::Withdraw(cmd) => {
+            withdraw(rpc_client, options, &program_id, &payer()?, cmd)
+        }
+        NativeVaultOperations::Show(cmd) => show_deposit_account(rpc_client, &program_id, cmd),
+    }
+}
+
+/// Fetches a deposit account and its total deposited, checking the program owns it.
+fn fetch_deposit_account(
+    rpc_client: &RpcClient,
+    program_id: &Pubkey,
+    address: &Pubkey
+) -> Result<(u64, u64), Box<dyn Error>> {
+    let account = rpc_client.get_account(address)?;
+    if account.owner != *program_id {
+        return Err(format!("{} is not owned by the vault program {}", address, program_id).into());
+    }
+    Ok((account.lamports, decode_total_deposits(&account.data)?))
+}
+
+fn initialize(
+    rpc_client: &RpcClient,
+    options: &TransactionArgs,
+    program_id: &Pubkey,
+    payer: &Keypair
+) -> Result<(), Box<dyn Error>> {
+    let deposit_account = Keypair::new();
+    let address = deposit_account.pubkey();
+    let instruction = initialize_instruction(program_id, &address, &payer.pubkey());
+    let confirmation = send_instructions(
+        rpc_client,
+        options,
+        &[instruction],
+        payer,
+        &[&deposit_account]
+    )?;
+    let signature = confirmation.signature;
+    emit(
+        json!({
+            "deposit_account": address.to_string(),
+            "signature": signature.to_string(),
+        }),
+        || {
+            println!("Deposit account created: {}", address);
+            println!("Signature: {}", signature);
+        }
+    );
+    Ok(())
+}
+
+fn deposit(
+    rpc_client: &RpcClient,
+    options: &TransactionArgs,
+    program_id: &Pubkey,
+    payer: &Keypair,
+    cmd: &NativeDepositCommand
+) -> Result<(), Box<dyn Error>> {
+    let address = Pubkey::from_str(&cmd.account)?;
+    let lamports = Amount::parse(&cmd.amount, SOL_DECIMALS, cmd.lamports)?.exact()?;
+    fetch_deposit_account(rpc_client, program_id, &address)?;
+
+    let instruction = deposit_instruction(program_id, &payer.pubkey(), &address, lamports);
+    let signature = send_instructions(rpc_client, options, &[instruction], payer, &[])?.signature;
+    let (_, total) = fetch_deposit_account(rpc_client, program_id, &address)?;
+    emit(
+        json!({
+            "deposit_account": address.to_string(),
+            "lamports": lamports,
+            "total_deposits": total,
+            "signature": signature.to_string(),
+        }),
+        || {
+            println!("Deposited {} SOL", format_token_amount(lamports, SOL_DECIMALS));
+            println!("Total deposited: {} SOL", format_token_amount(total, SOL_DECIMALS));
+            println!("Signature: {}", signature);
+        }
+    );
+    Ok(())
+}
+
+fn withdraw(
+    rpc_client: &RpcClient,
+    options: &TransactionArgs,
+    program_id: &Pubkey,
+    payer: &Keypair,
+    cmd: &NativeWithdrawCommand
+) -> Result<(), Box<dyn Error>> {
+    let address = Pubkey::from_str(&cmd.account)?;
+    let recipient = match &cmd.to {
+        Some(to) => Pubkey::from_str(to)?,
+        None => payer.pubkey(),
+    };
+    let (_, total) = fetch_deposit_account(rpc_client, program_id, &address)?;
+    let lamports = total / 10;
+    if lamports == 0 {
+        return Err(
+            format!(
+                "Nothing to withdraw: a tenth of the {} lamports deposited rounds down to zero",
+                total
+            ).into()
+        );
+    }
+
+    let instruction = withdraw_instruction(program_id, &address, &recipient);
+    let signature = send_instructions(rpc_client, options, &[instruction], payer, &[])?.signature;
+    emit(
+        json!({
+            "deposit_account": address.to_string(),
+            "recipient": recipient.to_string(),
+            "lamports": lamports,
+            "total_deposits": total - lamports,
+            "signature": signature.to_string(),
+        }),
+        || {
+            println!(
+                "Withdrew {} SOL to {}",
+                format_token_amount(lamports, SOL_DECIMALS),
+                recipient
+            );
+            println!(
+                "Total deposited: {} SOL",
+                format_token_amount(total - lamports, SOL_DECIMALS)
+            );
+            println!("Signature: {}", signature);
+        }
+    );
+    Ok(())
+}
+
+fn show_deposit_account(
+    rpc_client: &RpcClient,
+    program_id: &Pubkey,
+    cmd: &NativeVaultAccountCommand
+) -> Result<(), Box<dyn Error>> {
+    let address = Pubkey::from_str(&cmd.account)?;
+    let (lamports, total) = fetch_deposit_account(rpc_client, program_id, &address)?;
+    emit(
+        json!({
+            "deposit_account": address.to_string(),
+            "lamports": lamports,
+            "total_deposits": total,
+            "next_withdrawal": total / 10,
+        }),
+        || {
+            println!("Deposit account: {}", address);
+            println!("Balance: {} SOL", format_token_amount(lamports, SOL_DECIMALS));
+            println!("Total deposited: {} SOL", format_token_amount(total, SOL_DECIMALS));
+            println!("Next withdrawal: {} SOL", format_token_amount(total / 10, SOL_DECIMALS));
+        }
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_instructions_like_the_program() {
+        let program_id = Pubkey::new_unique();
+        let account = Pubkey::new_unique();
+        let payer = Pubkey::new_unique();
+
+        let deposit = deposit_instruction(&program_id, &payer, &account, 1_000_000);
+        assert_eq!(deposit.data, [0, 64, 66, 15, 0, 0, 0, 0, 0]);
+        assert_eq!(deposit.accounts[0], AccountMeta::new(payer, true));
+        assert_eq!(withdraw_instruction(&program_id, &account, &payer).data, [1]);
+        let initialize = initialize_instruction(&program_id, &account, &payer);
+        assert_eq!(initialize.data, [2]);
+        assert!(initialize.accounts[0].is_signer);
+
+        assert_eq!(decode_total_deposits(&1_000_000u64.to_le_bytes()).unwrap(), 1_000_000);
+        assert!(decode_total_deposits(&[0; 4]).is_err());
+    }
+}
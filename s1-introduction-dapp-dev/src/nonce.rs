@@ -0,0 +1,286 @@
+use solana_client::{ nonce_utils, rpc_client::RpcClient };
+use solana_sdk::{
+    account::Account,
+    hash::Hash,
+    nonce::State as NonceState,
+    pubkey::Pubkey,
+    signature::Keypair,
+    signer::Signer,
+    system_instruction,
+};
+use serde_json::json;
+use std::{ error::Error, str::FromStr };
+
+use crate::{
+    amount::{ Amount, SOL_DECIMALS },
+    keystore::read_keys,
+    output::emit,
+    state::*,
+    token::format_token_amount,
+    transaction::send_instructions,
+    wallet::Wallet,
+};
+
+/// Durable value of a nonce account, which replaces the recent blockhash of the transactions
+/// that advance it.
+pub fn nonce_value(account: &Account) -> Result<Hash, Box<dyn Error>> {
+    Ok(nonce_utils::data_from_account(account)?.blockhash())
+}
+
+pub fn nonce_command(
+    rpc_client: &RpcClient,
+    options: &TransactionArgs,
+    wallet: &Wallet,
+    cmd: &NonceCommand
+) -> Result<(), Box<dyn Error>> {
+    let signer_path = wallet.resolve(cmd.from.as_deref())?;
+    let signer = || read_keys(&signer_path);
+    match &cmd.operation {
+        NonceOperations::Create(cmd) => create_nonce(rpc_client, options, &signer()?, cmd),
+        NonceOperations::Show(cmd) => show_nonce(rpc_client, cmd),
+        NonceOperations::Advance(cmd) => advance_nonce(rpc_client, options, &signer()?, cmd),
+        NonceOperations::Withdraw(cmd) => withdraw_nonce(rpc_client, options, &signer()?, cmd),
+        NonceOperations::Authorize(cmd) => authorize_nonce(rpc_client, options, &signer()?, cmd),
+    }
+}
+
+fn create_nonce(
+    rpc_client: &RpcClient,
+    options: &TransactionArgs,
+    payer: &Keypair,
+    cmd: &CreateNonceCommand
+) -> Result<(), Box<dyn Error>> {
+    let nonce = Keypair::new();
+    let authority = match &cmd.authority {
+        Some(authority) => Pubkey::from_str(authority)?,
+        None => payer.pubkey(),
+    };
+    let minimum = rpc_client.get_minimum_balance_for_rent_exemption(NonceState::size())?;
+    let lamports = match &cmd.amount {
+        Some(amount) => Amount::parse(amount, SOL_DECIMALS, cmd.lamports)?.exact()?,
+        None => minimum,
+    };
+    if lamports < minimum {
+        return Err(
+            format!(
+                "A nonce account needs at least {} SOL to be rent exempt",
+                format_token_amount(minimum, SOL_DECIMALS)
+            ).into()
+        );
+    }
+
+    let instructions = system_instruction::create_nonce_account(
+        &payer.pubkey(),
+        &nonce.pubkey(),
+        &authority,
+        lamports
+    );
+    let confirmation = send_instructions(rpc_client, options, &instructions, payer, &[&nonce])?;
+    let signature = confirmation.signature;
+    emit(
+        json!({
+            "nonce_account": nonce.pubkey().to_string(),
+            "authority": authority.to_string(),
+            "lamports": lamports,
+            "signature": signature.to_string(),
+        }),
+        || {
+            println!("Nonce account created: {}", nonce.pubkey());
+            println!("Authority: {}", authority);
+            println!("Signature: {}", signature);
+        }
+    );
+    Ok(())
+}
+
+fn show_nonce(rpc_client: &RpcClient, cmd: &NonceAccountCommand) -> Result<(), Box<dyn Error>> {
+    let address = Pubkey::from_str(&cmd.nonce)?;
+    let account = nonce_utils::get_account(rpc_client, &address)?;
+    let data = nonce_utils::data_from_account(&account)?;
+    emit(
+        json!({
+            "nonce_account": address.to_string(),
+            "lamports": account.lamports,
+            "authority": data.authority.to_string(),
+            "nonce": data.blockhash().to_string(),
+            "lamports_per_signature": data.fee_calculator.lamports_per_signature,
+        }),
+        || {
+            println!("Nonce account: {}", address);
+            println!("Balance: {} SOL", format_token_amount(account.lamports, SOL_DECIMALS));
+            println!("Authority: {}", data.authority);
+            println!("Nonce: {}", data.blockhash());
+            println!(
+                "Fee per signature: {} lamports",
+                data.fee_calculator.lamports_per_signature
+            );
+        }
+    );
+    Ok(())
+}
+
+fn advance_nonce(
+    rpc_client: &RpcClient,
+    options: &TransactionArgs,
+    authority: &Keypair,
+    cmd: &NonceAccountCommand
+) -> Result<(), Box<dyn Error>> {
+    let address = Pubkey::from_str(&cmd.nonce)?;
+    let instruction = system_instruction::advance_nonce_account(&address, &authority.pubkey());
+    let confirmation = send_instructions(rpc_client, options, &[instruction], authority, &[])?;
+    let signature = confirmation.signature;
+    let nonce = nonce_value(&nonce_utils::get_account(rpc_client, &address)?)?;
+    emit(
+        json!({
+            "nonce_account": address.to_string(),
+            "nonce": nonce.to_string(),
+            "signature": signature.to_string(),
+        }),
+        || {
+            println!("Nonce advanced to: {}", nonce);
+            println!("Signature: {}", signature);
+        }
+    );
+    Ok(())
+}
+
+fn withdraw_nonce(
+    rpc_client: &RpcClient,
+    options: &TransactionArgs,
+    authority: &Keypair,
+    cmd: &WithdrawNonceCommand
+) -> Result<(), Box<dyn Error>> {
+    let address = Pubkey::from_str(&cmd.nonce)?;
+    let recipient = Pubkey::from_str(&cmd.to)?;
+    let lamports = Amount::parse(&cmd.amount, SOL_DECIMALS, cmd.lamports)?.exact()?;
+    let instruction = system_instruction::withdraw_nonce_account(
+        &address,
+        &authority.pubkey(),
+        &recipient,
+        lamports
+    );
+    let confirmation = send_instructions(rpc_client, options, &[instruction], authority, &[])?;
+    let signature = confirmation.signature;
+    emit(
+        json!({
+            "nonce_account": address.to_string(),
+            "to": recipient.to_string(),
+            "lamports": lamports,
+            "signature": signature.to_string(),
+        }),
+        || {
+            let amount = format_token_amount(lamports, SOL_DECIMALS);
+            println!("Withdrew {} SOL to {}", amount, recipient);
+            println!("Signature: {}", signature);
+        }
+    );
+    Ok(())
+}
+
+fn authorize_nonce(
+    rpc_client: &RpcClient,
+    options: &TransactionArgs,
+    authority: &Keypair,
+    cmd: &AuthorizeNonceCommand
+) -> Result<(), Box<dyn Error>> {
+    let address = Pubkey::from_str(&cmd.nonce)?;
+    let new_authority = Pubkey::from_str(&cmd.new_authority)?;
+    let instruction = system_instruction::authorize_nonce_account(
+        &address,
+        &authority.pubkey(),
+        &new_authority
+    );
+    let confirmation = send_instructions(rpc_client, options, &[instruction], authority, &[])?;
+    let signature = confirmation.signature;
+    emit(
+        json!({
+            "nonce_account": address.to_string(),
+            "authority": new_authority.to_string(),
+            "signature": signature.to_string(),
+        }),
+        || {
+            println!("Nonce authority of {} set to: {}", address, new_authority);
+            println!("Signature: {}", signature);
+        }
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::{
+        compute_budget,
+        hash::Hash,
+        nonce::{ state::{ Data, DurableNonce, Versions }, State },
+        system_program,
+        transaction::uses_durable_nonce,
+    };
+
+    use crate::{ offline::missing_signers, transaction::{ build_transaction, NonceAuthority } };
+
+    fn nonce_account(state: State) -> Account {
+        let data = bincode::serialize(&Versions::new(state)).unwrap();
+        Account { lamports: 1_447_680, data, owner: system_program::id(), ..Account::default() }
+    }
+
+    fn options(nonce: &Pubkey, authority: Option<NonceAuthority>) -> TransactionArgs {
+        TransactionArgs {
+            priority_fee: Some(10),
+            compute_unit_limit: Some(200_000),
+            nonce: Some(nonce.to_string()),
+            nonce_authority_signer: authority,
+            ..TransactionArgs::for_tests()
+        }
+    }
+
+    #[test]
+    fn reads_the_durable_value_of_a_nonce_account() {
+        let durable = DurableNonce::from_blockhash(&Hash::new_unique());
+        let data = Data::new(Pubkey::new_unique(), durable, 5000);
+        let account = nonce_account(State::Initialized(data));
+        assert_eq!(nonce_value(&account).unwrap(), *durable.as_hash());
+
+        assert!(nonce_value(&nonce_account(State::Uninitialized)).is_err());
+        let foreign = Account { owner: Pubkey::new_unique(), ..account };
+        assert!(nonce_value(&foreign).is_err());
+    }
+
+    #[test]
+    fn advances_the_nonce_before_the_compute_budget_and_the_payment() {
+        // Nothing is fetched when the nonce value is given.
+        let rpc_client = RpcClient::new("http://127.0.0.1:1".to_string());
+        let payer = Keypair::new();
+        let nonce = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let options = options(&nonce, Some(NonceAuthority::Pubkey(authority)));
+        let transfer = system_instruction::transfer(&payer.pubkey(), &Pubkey::new_unique(), 1);
+        let nonce_value = Hash::new_unique();
+        let transaction = build_transaction(
+            &rpc_client,
+            &options,
+            &[transfer],
+            &payer.pubkey(),
+            &[&payer],
+            Some(nonce_value)
+        ).unwrap();
+
+        let message = &transaction.message;
+        assert_eq!(message.recent_blockhash, nonce_value);
+        let programs: Vec<Pubkey> = message.instructions
+            .iter()
+            .map(|instruction| *instruction.program_id(&message.account_keys))
+            .collect();
+        assert_eq!(programs, [
+            system_program::id(),
+            compute_budget::id(),
+            compute_budget::id(),
+            system_program::id(),
+        ]);
+        let advance = uses_durable_nonce(&transaction).unwrap();
+        assert_eq!(message.account_keys[advance.accounts[0] as usize], nonce);
+        assert_eq!(message.account_keys[advance.accounts[2] as usize], authority);
+        // A bare public key signs later, with `sign`.
+        assert_eq!(missing_signers(&transaction), [authority]);
+    }
+}
@@ -0,0 +1,224 @@
+use base64::{ engine::general_purpose::STANDARD as BASE64, Engine };
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{
+    hash::Hash,
+    instruction::Instruction,
+    pubkey::Pubkey,
+    signature::{ Keypair, Signature },
+    signer::Signer,
+    transaction::Transaction,
+};
+use serde_json::json;
+use std::{ error::Error, fs, path::Path, str::FromStr };
+
+use crate::{
+    keystore::read_keys,
+    output::{ emit, value_name },
+    state::*,
+    transaction::{ build_transaction, submit_transaction },
+    wallet::Wallet,
+};
+
+/// Signs `instructions` for `--sign-only` and writes the transaction out instead of sending it.
+pub fn sign_offline(
+    rpc_client: &RpcClient,
+    options: &TransactionArgs,
+    instructions: &[Instruction],
+    signer: &Keypair,
+    args: &OfflineArgs
+) -> Result<(), Box<dyn Error>> {
+    if options.auto_fee {
+        return Err(
+            "--auto-fee asks the cluster for recent fees; sign offline with --priority-fee".into()
+        );
+    }
+    let blockhash = args.blockhash.as_deref().map(Hash::from_str).transpose()?;
+    let fee_payer = match &args.fee_payer {
+        Some(fee_payer) => Pubkey::from_str(fee_payer)?,
+        None => signer.pubkey(),
+    };
+    let transaction = build_transaction(
+        rpc_client,
+        options,
+        instructions,
+        &fee_payer,
+        &[signer],
+        blockhash
+    )?;
+    write_transaction(&transaction, &args.output)
+}
+
+/// Submits a fully signed transaction produced by `send --sign-only` or `sign`.
+pub fn broadcast_transaction(
+    rpc_client: &RpcClient,
+    options: &TransactionArgs,
+    cmd: &BroadcastCommand
+) -> Result<(), Box<dyn Error>> {
+    let transaction = read_transaction(&cmd.transaction)?;
+    let missing = missing_signers(&transaction);
+    if !missing.is_empty() {
+        let missing: Vec<String> = missing.iter().map(Pubkey::to_string).collect();
+        return Err(
+            format!("Transaction is missing signatures from: {}", missing.join(", ")).into()
+        );
+    }
+    transaction.verify()?;
+    let confirmation = submit_transaction(rpc_client, options, &transaction)?;
+    emit(confirmation.to_json(), || {
+        println!("Broadcast successful: Signature {}", confirmation)
+    });
+    Ok(())
+}
+
+/// Adds the signature of one co-signer to a partially signed transaction.
+pub fn cosign_transaction(wallet: &Wallet, cmd: &SignCommand) -> Result<(), Box<dyn Error>> {
+    let mut transaction = read_transaction(&cmd.transaction)?;
+    let signer = read_keys(&wallet.resolve(cmd.from.as_deref())?)?;
+    if !missing_signers(&transaction).contains(&signer.pubkey()) {
+        return Err(
+            format!(
+                "{} is not a signer still missing from this transaction",
+                signer.pubkey()
+            ).into()
+        );
+    }
+    let blockhash = transaction.message.recent_blockhash;
+    transaction.try_partial_sign(&[&signer], blockhash)?;
+    write_transaction(&transaction, &cmd.output)
+}
+
+pub fn encode_transaction(
+    transaction: &Transaction,
+    encoding: TransactionEncoding
+) -> Result<String, Box<dyn Error>> {
+    let bytes = bincode::serialize(transaction)?;
+    Ok(match encoding {
+        TransactionEncoding::Base58 => bs58::encode(bytes).into_string(),
+        TransactionEncoding::Base64 => BASE64.encode(bytes),
+    })
+}
+
+/// Decodes a base64 or base58 transaction; the encoding is detected.
+pub fn decode_transaction(data: &str) -> Result<Transaction, Box<dyn Error>> {
+    let data = data.trim();
+    let candidates = [BASE64.decode(data).ok(), bs58::decode(data).into_vec().ok()];
+    candidates
+        .into_iter()
+        .flatten()
+        .find_map(|bytes| bincode::deserialize::<Transaction>(&bytes).ok())
+        .ok_or_else(|| "Not a base58 or base64 encoded transaction".into())
+}
+
+/// Required signers whose signature is still empty.
+pub fn missing_signers(transaction: &Transaction) -> Vec<Pubkey> {
+    let required = transaction.message.header.num_required_signatures as usize;
+    transaction.message.account_keys
+        .iter()
+        .zip(&transaction.signatures)
+        .take(required)
+        .filter(|(_, signature)| **signature == Signature::default())
+        .map(|(key, _)| *key)
+        .collect()
+}
+
+fn read_transaction(input: &str) -> Result<Transaction, Box<dyn Error>> {
+    if Path::new(input).is_file() {
+        return decode_transaction(&fs::read_to_string(input)?);
+    }
+    decode_transaction(input)
+}
+
+/// Prints or saves the encoded transaction. Signer status goes to stderr so the transaction
+/// itself can be piped; in JSON modes everything is part of one document on stdout.
+fn write_transaction(
+    transaction: &Transaction,
+    output: &SignedTransactionArgs
+) -> Result<(), Box<dyn Error>> {
+    let encoded = encode_transaction(transaction, output.encoding)?;
+    if let Some(path) = &output.transaction_file {
+        fs::write(path, &encoded)?;
+    }
+
+    let missing = missing_signers(transaction);
+    let required = transaction.message.header.num_required_signatures as usize;
+    let signers: Vec<_> = transaction.message.account_keys
+        .iter()
+        .take(required)
+        .map(|key| json!({ "public_key": key.to_string(), "signed": !missing.contains(key) }))
+        .collect();
+    emit(
+        json!({
+            "transaction": encoded,
+            "encoding": value_name(&output.encoding),
+            "file": output.transaction_file,
+            "signers": signers,
+            "fully_signed": missing.is_empty(),
+        }),
+        || {
+            match &output.transaction_file {
+                Some(path) => eprintln!("Transaction written to: {}", path),
+                None => println!("{}", encoded),
+            }
+            for key in transaction.message.account_keys.iter().take(required) {
+                let status = if missing.contains(key) { "missing" } else { "signed" };
+                eprintln!("Signer {}: {}", key, status);
+            }
+            if missing.is_empty() {
+                eprintln!("Fully signed; submit it with `broadcast`");
+            }
+        }
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::{ message::Message, system_instruction };
+
+    #[test]
+    fn partially_signed_transactions_round_trip() {
+        let sender = Keypair::new();
+        let fee_payer = Keypair::new();
+        let instruction = system_instruction::transfer(&sender.pubkey(), &Pubkey::new_unique(), 1);
+        let mut transaction = Transaction::new_unsigned(
+            Message::new(&[instruction], Some(&fee_payer.pubkey()))
+        );
+        transaction.try_partial_sign(&[&sender], Hash::new_unique()).unwrap();
+        assert_eq!(missing_signers(&transaction), [fee_payer.pubkey()]);
+
+        for encoding in [TransactionEncoding::Base58, TransactionEncoding::Base64] {
+            let encoded = encode_transaction(&transaction, encoding).unwrap();
+            let mut decoded = decode_transaction(&encoded).unwrap();
+            assert_eq!(decoded, transaction);
+
+            let blockhash = decoded.message.recent_blockhash;
+            decoded.try_partial_sign(&[&fee_payer], blockhash).unwrap();
+            assert!(missing_signers(&decoded).is_empty());
+            assert!(decoded.verify().is_ok());
+        }
+    }
+
+    #[test]
+    fn auto_fee_is_refused_offline() {
+        // Refused before the cluster would be asked for fees.
+        let rpc_client = RpcClient::new("http://127.0.0.1:1".to_string());
+        let options = TransactionArgs {
+            auto_fee: true,
+            ..TransactionArgs::for_tests()
+        };
+        let sender = Keypair::new();
+        let instruction = system_instruction::transfer(&sender.pubkey(), &Pubkey::new_unique(), 1);
+        let args = OfflineArgs {
+            sign_only: true,
+            blockhash: Some(Hash::new_unique().to_string()),
+            fee_payer: None,
+            output: SignedTransactionArgs {
+                encoding: TransactionEncoding::Base64,
+                transaction_file: None,
+            },
+        };
+        let error = sign_offline(&rpc_client, &options, &[instruction], &sender, &args);
+        assert!(error.unwrap_err().to_string().contains("--priority-fee"));
+    }
+}
@@ -0,0 +1,204 @@
+use rusqlite::{ params, Connection, OptionalExtension, Row };
+use serde::{ Deserialize, Serialize };
+use std::{ collections::BTreeMap, error::Error };
+
+/// An order taken by `pay serve`, found on chain by its reference key.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Order {
+    pub reference: String,
+    pub recipient: String,
+    pub spl_token: Option<String>,
+    /// Base units of SOL, or of `spl_token`.
+    pub amount: u64,
+    pub decimals: u8,
+    pub quantity: u32,
+    pub memo: Option<String>,
+    pub message: String,
+    pub status: OrderStatus,
+    /// Transaction that paid the order.
+    pub signature: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum OrderStatus {
+    Pending,
+    Paid,
+}
+
+/// Where `pay serve` keeps its orders, keyed by reference.
+pub trait OrderStore {
+    /// Adds a new order; its reference must not be taken.
+    fn insert(&mut self, order: &Order) -> Result<(), Box<dyn Error>>;
+    fn get(&self, reference: &str) -> Result<Option<Order>, Box<dyn Error>>;
+    /// Records the transaction that paid an order.
+    fn mark_paid(&mut self, reference: &str, signature: &str) -> Result<(), Box<dyn Error>>;
+}
+
+/// Orders that last as long as the process.
+#[derive(Default)]
+pub struct MemoryOrderStore {
+    orders: BTreeMap<String, Order>,
+}
+
+impl OrderStore for MemoryOrderStore {
+    fn insert(&mut self, order: &Order) -> Result<(), Box<dyn Error>> {
+        if self.orders.contains_key(&order.reference) {
+            return Err(format!("Order {} already exists", order.reference).into());
+        }
+        self.orders.insert(order.reference.clone(), order.clone());
+        Ok(())
+    }
+
+    fn get(&self, reference: &str) -> Result<Option<Order>, Box<dyn Error>> {
+        Ok(self.orders.get(reference).cloned())
+    }
+
+    fn mark_paid(&mut self, reference: &str, signature: &str) -> Result<(), Box<dyn Error>> {
+        let order = self.orders
+            .get_mut(reference)
+            .ok_or_else(|| format!("Order {} not found", reference))?;
+        order.status = OrderStatus::Paid;
+        order.signature = Some(signature.to_string());
+        Ok(())
+    }
+}
+
+/// Orders in an SQLite database, which survive restarts.
+pub struct SqliteOrderStore {
+    connection: Connection,
+}
+
+impl SqliteOrderStore {
+    /// Opens or creates the database at `path`.
+    pub fn open(path: &str) -> Result<Self, Box<dyn Error>> {
+        Self::init(Connection::open(path)?)
+    }
+
+    #[cfg(test)]
+    pub fn open_in_memory() -> Result<Self, Box<dyn Error>> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(connection: Connection) -> Result<Self, Box<dyn Error>> {
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS orders (
+                reference TEXT PRIMARY KEY,
+                recipient TEXT NOT NULL,
+                spl_token TEXT,
+                amount INTEGER NOT NULL,
+                decimals INTEGER NOT NULL,
+                quantity INTEGER NOT NULL,
+                memo TEXT,
+                message TEXT NOT NULL,
+                status TEXT NOT NULL,
+                signature TEXT
+            )"
+        )?;
+        Ok(Self { connection })
+    }
+}
+
+fn status_name(status: OrderStatus) -> &'static str {
+    match status {
+        OrderStatus::Pending => "pending",
+        OrderStatus::Paid => "paid",
+    }
+}
+
+fn read_order(row: &Row) -> rusqlite::Result<Order> {
+    let status: String = row.get("status")?;
+    Ok(Order {
+        reference: row.get("reference")?,
+        recipient: row.get("recipient")?,
+        spl_token: row.get("spl_token")?,
+        // Stored as SQLite's signed 64-bit integer; `insert` only accepts amounts that fit.
+        amount: row.get::<_, i64>("amount")? as u64,
+        decimals: row.get("decimals")?,
+        quantity: row.get("quantity")?,
+        memo: row.get("memo")?,
+        message: row.get("message")?,
+        status: if status == "paid" { OrderStatus::Paid } else { OrderStatus::Pending },
+        signature: row.get("signature")?,
+    })
+}
+
+impl OrderStore for SqliteOrderStore {
+    fn insert(&mut self, order: &Order) -> Result<(), Box<dyn Error>> {
+        let amount = i64::try_from(order.amount).map_err(|_| {
+            format!("Order amount {} is too large to store", order.amount)
+        })?;
+        self.connection.execute(
+            "INSERT INTO orders (reference, recipient, spl_token, amount, decimals, quantity, memo,
+                message, status, signature)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            params![
+                order.reference,
+                order.recipient,
+                order.spl_token,
+                amount,
+                order.decimals,
+                order.quantity,
+                order.memo,
+                order.message,
+                status_name(order.status),
+                order.signature
+            ]
+        )?;
+        Ok(())
+    }
+
+    fn get(&self, reference: &str) -> Result<Option<Order>, Box<dyn Error>> {
+        let order = self.connection
+            .query_row("SELECT * FROM orders WHERE reference = ?1", [reference], read_order)
+            .optional()?;
+        Ok(order)
+    }
+
+    fn mark_paid(&mut self, reference: &str, signature: &str) -> Result<(), Box<dyn Error>> {
+        let updated = self.connection.execute(
+            "UPDATE orders SET status = ?1, signature = ?2 WHERE reference = ?3",
+            params![status_name(OrderStatus::Paid), signature, reference]
+        )?;
+        if updated == 0 {
+            return Err(format!("Order {} not found", reference).into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check_store(store: &mut dyn OrderStore) {
+        let order = Order {
+            reference: "reference".to_string(),
+            recipient: "recipient".to_string(),
+            spl_token: None,
+            amount: 200_000_000,
+            decimals: 9,
+            quantity: 2,
+            memo: Some("T-shirt purchase".to_string()),
+            message: "Purchase of 2 shirt(s) for 0.2 SOL".to_string(),
+            status: OrderStatus::Pending,
+            signature: None,
+        };
+        store.insert(&order).unwrap();
+        assert!(store.insert(&order).is_err());
+        assert_eq!(store.get("reference").unwrap(), Some(order.clone()));
+        assert_eq!(store.get("unknown").unwrap(), None);
+
+        store.mark_paid("reference", "signature").unwrap();
+        let paid = store.get("reference").unwrap().unwrap();
+        assert_eq!(paid.status, OrderStatus::Paid);
+        assert_eq!(paid.signature.as_deref(), Some("signature"));
+        assert!(store.mark_paid("unknown", "signature").is_err());
+    }
+
+    #[test]
+    fn stores_orders_in_memory_and_in_sqlite() {
+        check_store(&mut MemoryOrderStore::default());
+        check_store(&mut SqliteOrderStore::open_in_memory().unwrap());
+    }
+}
@@ -0,0 +1,117 @@
+use clap::ValueEnum;
+use serde_json::{ json, Value };
+use solana_client::{
+    client_error::{ ClientError, ClientErrorKind },
+    rpc_request::{ RpcError, RpcResponseErrorData },
+};
+use solana_sdk::transaction::TransactionError;
+use std::{ error::Error, sync::OnceLock };
+
+use crate::state::OutputFormat;
+
+static FORMAT: OnceLock<OutputFormat> = OnceLock::new();
+
+/// Selects the `--output` format once, before any command runs.
+pub fn set_output_format(format: OutputFormat) {
+    let _ = FORMAT.set(format);
+}
+
+pub fn output_format() -> OutputFormat {
+    FORMAT.get().copied().unwrap_or(OutputFormat::Text)
+}
+
+/// Prints the result of a command: `text` prints it in text mode, otherwise `value` is
+/// printed as a single JSON document on stdout.
+pub fn emit(value: Value, text: impl FnOnce()) {
+    match output_format() {
+        OutputFormat::Text => text(),
+        OutputFormat::Json => println!("{}", render_json(&value, true)),
+        OutputFormat::JsonCompact => println!("{}", render_json(&value, false)),
+    }
+}
+
+/// Progress while a command runs. Part of stdout in text mode; moved to stderr in JSON modes
+/// so stdout only carries the final document.
+pub fn status(message: &str) {
+    match output_format() {
+        OutputFormat::Text => println!("{}", message),
+        _ => eprintln!("{}", message),
+    }
+}
+
+/// The command-line spelling of a value enum, e.g. `token-2022`, used in JSON documents.
+pub fn value_name(value: &impl ValueEnum) -> String {
+    value
+        .to_possible_value()
+        .map(|value| value.get_name().to_string())
+        .unwrap_or_default()
+}
+
+/// Prints a failed command's error on stderr, as `{"error": {...}}` in JSON modes.
+pub fn report_error(error: &(dyn Error + 'static)) {
+    match output_format() {
+        OutputFormat::Text => eprintln!("Error: {}", error),
+        OutputFormat::Json => eprintln!("{}", render_json(&error_json(error), true)),
+        OutputFormat::JsonCompact => eprintln!("{}", render_json(&error_json(error), false)),
+    }
+}
+
+/// `{"error": {"kind", "message", ...}}`. RPC errors add the request and error code, and
+/// failed transactions add the transaction error and simulation logs when available.
+pub fn error_json(error: &(dyn Error + 'static)) -> Value {
+    let mut details = json!({ "kind": "error", "message": error.to_string() });
+    if let Some(client_error) = error.downcast_ref::<ClientError>() {
+        details["kind"] = json!("rpc");
+        details["request"] = json!(client_error.request.map(|request| request.to_string()));
+        match &client_error.kind {
+            ClientErrorKind::RpcError(RpcError::RpcResponseError { code, message, data }) => {
+                details["code"] = json!(code);
+                details["rpc_message"] = json!(message);
+                if let RpcResponseErrorData::SendTransactionPreflightFailure(result) = data {
+                    details["kind"] = json!("transaction");
+                    details["transaction_error"] = json!(result.err);
+                    details["logs"] = json!(result.logs);
+                }
+            }
+            ClientErrorKind::TransactionError(transaction_error) => {
+                details["kind"] = json!("transaction");
+                details["transaction_error"] = json!(transaction_error);
+            }
+            _ => {}
+        }
+    } else if let Some(transaction_error) = error.downcast_ref::<TransactionError>() {
+        details["kind"] = json!("transaction");
+        details["transaction_error"] = json!(transaction_error);
+    }
+    json!({ "error": details })
+}
+
+fn render_json(value: &Value, pretty: bool) -> String {
+    let rendered = if pretty {
+        serde_json::to_string_pretty(value)
+    } else {
+        serde_json::to_string(value)
+    };
+    rendered.expect("JSON values always serialize")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::instruction::InstructionError;
+
+    #[test]
+    fn transaction_errors_keep_their_details() {
+        let error: Box<dyn Error> = Box::new(
+            TransactionError::InstructionError(0, InstructionError::Custom(1))
+        );
+        let value = error_json(error.as_ref());
+        assert_eq!(value["error"]["kind"], "transaction");
+        assert_eq!(value["error"]["transaction_error"]["InstructionError"][1]["Custom"], 1);
+
+        let error: Box<dyn Error> = "Invalid label".into();
+        assert_eq!(error_json(error.as_ref()), json!({
+            "error": { "kind": "error", "message": "Invalid label" }
+        }));
+    }
+}
@@ -0,0 +1,635 @@
+use percent_encoding::{ percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC };
+use qrcode::{ render::unicode::Dense1x2, QrCode };
+use serde_json::{ json, Value };
+use solana_client::{ rpc_client::RpcClient, rpc_config::RpcTransactionConfig };
+use solana_sdk::{
+    commitment_config::CommitmentConfig,
+    instruction::Instruction,
+    pubkey::Pubkey,
+    signature::{ Keypair, Signature },
+    signer::Signer,
+};
+use solana_transaction_status::{
+    EncodedConfirmedTransactionWithStatusMeta,
+    EncodedTransaction,
+    UiMessage,
+    UiTransactionEncoding,
+    UiTransactionStatusMeta,
+};
+use std::{ collections::BTreeMap, error::Error, str::FromStr };
+use url::Url;
+
+use crate::{
+    amount::{ Amount, SOL_DECIMALS },
+    history::{ account_keys, fetch_signatures },
+    keystore::read_public_key,
+    output::emit,
+    pay_server::pay_serve,
+    state::*,
+    token::{ format_token_amount, parse_token_amount, MintInfo },
+    wallet::Wallet,
+};
+
+pub const SOLANA_PAY_SCHEME: &str = "solana";
+/// Wallets attach a transfer request's memo with either version of the memo program.
+const MEMO_PROGRAM_IDS: [&str; 2] = [
+    "MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr",
+    "Memo1UhkJRfHyvLMcVucJwxXeuD728EqVDDwQDxFMNo",
+];
+/// Characters `encodeURIComponent` leaves as they are, as `@solana/pay` does.
+const COMPONENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'!')
+    .remove(b'~')
+    .remove(b'*')
+    .remove(b'\'')
+    .remove(b'(')
+    .remove(b')');
+
+/// A Solana Pay transfer request: `solana:<recipient>?amount=..&spl-token=..&reference=..`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TransferRequest {
+    pub recipient: Pubkey,
+    /// Decimal amount of SOL, or of `spl_token` tokens, exactly as written in the URL.
+    pub amount: Option<String>,
+    pub spl_token: Option<Pubkey>,
+    pub references: Vec<Pubkey>,
+    pub label: Option<String>,
+    pub message: Option<String>,
+    pub memo: Option<String>,
+}
+
+impl TransferRequest {
+    /// Encodes the URL, with parameters in the order `@solana/pay`'s `encodeURL` uses.
+    pub fn encode(&self) -> Result<String, Box<dyn Error>> {
+        self.validate()?;
+        let mut params = Vec::new();
+        if let Some(amount) = &self.amount {
+            params.push(("amount", amount.clone()));
+        }
+        if let Some(mint) = &self.spl_token {
+            params.push(("spl-token", mint.to_string()));
+        }
+        for reference in &self.references {
+            params.push(("reference", reference.to_string()));
+        }
+        let texts = [("label", &self.label), ("message", &self.message), ("memo", &self.memo)];
+        for (key, value) in texts {
+            if let Some(value) = value {
+                params.push((key, value.clone()));
+            }
+        }
+
+        let mut url = format!("{}:{}", SOLANA_PAY_SCHEME, self.recipient);
+        for (index, (key, value)) in params.iter().enumerate() {
+            let separator = if index == 0 { '?' } else { '&' };
+            let value = utf8_percent_encode(value, COMPONENT);
+            url.push_str(&format!("{}{}={}", separator, key, value));
+        }
+        Ok(url)
+    }
+
+    /// Parses a transfer request URL. Every parameter is validated, and parameters the
+    /// specification allows only once are rejected when repeated.
+    pub fn parse(value: &str) -> Result<Self, Box<dyn Error>> {
+        let url = Url::parse(value.trim()).map_err(|e| format!("Invalid URL '{}': {}", value, e))?;
+        if url.scheme() != SOLANA_PAY_SCHEME {
+            return Err(format!("'{}' is not a solana: URL", value).into());
+        }
+        let path = percent_decode_str(url.path()).decode_utf8()?;
+        if path.starts_with("https:") {
+            return Err("This is a Solana Pay transaction request, not a transfer request".into());
+        }
+        let recipient = Pubkey::from_str(&path).map_err(|_| {
+            format!("Invalid recipient '{}': not a base58 public key", path)
+        })?;
+
+        let mut request = Self { recipient, ..Default::default() };
+        let mut spl_token = None;
+        for (key, value) in url.query_pairs() {
+            let value = value.into_owned();
+            let slot = match key.as_ref() {
+                "reference" => {
+                    request.references.push(parse_pubkey("reference", &value)?);
+                    continue;
+                }
+                "amount" => &mut request.amount,
+                "spl-token" => &mut spl_token,
+                "label" => &mut request.label,
+                "message" => &mut request.message,
+                "memo" => &mut request.memo,
+                // Parameters from later versions of the specification are ignored.
+                _ => continue,
+            };
+            if slot.replace(value).is_some() {
+                return Err(format!("The {} parameter appears more than once", key).into());
+            }
+        }
+        request.spl_token = spl_token.map(|mint| parse_pubkey("spl-token", &mint)).transpose()?;
+        request.validate()?;
+        Ok(request)
+    }
+
+    /// Checks the amount: a non-negative decimal with a digit before any point and, for SOL,
+    /// at most 9 decimal places.
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        let Some(amount) = &self.amount else {
+            return Ok(());
+        };
+        let (whole, fraction) = match amount.split_once('.') {
+            Some((whole, fraction)) => (whole, Some(fraction)),
+            None => (amount.as_str(), None),
+        };
+        let digits = |part: &str| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit());
+        if !digits(whole) || !fraction.is_none_or(digits) {
+            return Err(
+                format!(
+                    "Invalid amount '{}': it must be a decimal number such as 1 or 0.5",
+                    amount
+                ).into()
+            );
+        }
+        if self.spl_token.is_none() {
+            parse_token_amount(amount, SOL_DECIMALS).map_err(|e| {
+                format!("Invalid SOL amount '{}': {}", amount, e)
+            })?;
+        }
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "recipient": self.recipient.to_string(),
+            "amount": self.amount,
+            "spl_token": self.spl_token.map(|mint| mint.to_string()),
+            "references": self.references.iter().map(Pubkey::to_string).collect::<Vec<_>>(),
+            "label": self.label,
+            "message": self.message,
+            "memo": self.memo,
+        })
+    }
+}
+
+/// A transaction request URL: `solana:` followed by the HTTPS link the wallet fetches the
+/// transaction from, percent-encoded when it has a query, as `@solana/pay`'s `encodeURL` does.
+pub fn encode_transaction_request(link: &Url) -> String {
+    let link = link.as_str();
+    if link.contains('?') {
+        format!("{}:{}", SOLANA_PAY_SCHEME, utf8_percent_encode(link, COMPONENT))
+    } else {
+        format!("{}:{}", SOLANA_PAY_SCHEME, link)
+    }
+}
+
+/// Attaches `memo` with the current memo program, as wallets do for transfer requests.
+pub fn memo_instruction(memo: &str) -> Instruction {
+    let program_id = Pubkey::from_str(MEMO_PROGRAM_IDS[0]).expect("valid memo program id");
+    Instruction::new_with_bytes(program_id, memo.as_bytes(), vec![])
+}
+
+pub fn parse_pubkey(name: &str, value: &str) -> Result<Pubkey, Box<dyn Error>> {
+    Pubkey::from_str(value).map_err(|_| {
+        format!("Invalid {} '{}': not a base58 public key", name, value).into()
+    })
+}
+
+/// What a transaction must do to settle a transfer request.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExpectedPayment {
+    pub recipient: Pubkey,
+    /// Base units of SOL, or of `spl_token`.
+    pub amount: u64,
+    pub decimals: u8,
+    pub spl_token: Option<Pubkey>,
+    pub reference: Pubkey,
+    pub memo: Option<String>,
+}
+
+impl ExpectedPayment {
+    fn format(&self, amount: u64) -> String {
+        let amount = format_token_amount(amount, self.decimals);
+        match &self.spl_token {
+            Some(mint) => format!("{} tokens of {}", amount, mint),
+            None => format!("{} SOL", amount),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Verification {
+    /// No transaction references the request yet.
+    NotFound,
+    /// `received` is in base units and at least the amount expected.
+    Paid {
+        signature: Signature,
+        slot: u64,
+        received: u64,
+    },
+    /// Transactions reference the request but none settles it; `reason` is the newest one's.
+    Mismatch {
+        signature: Signature,
+        reason: String,
+    },
+}
+
+/// Looks up the transactions that reference the request and checks them, oldest first, until
+/// one settles it: `findReference` and `validateTransfer` of `@solana/pay` in one step.
+pub fn verify_payment(
+    rpc_client: &RpcClient,
+    expected: &ExpectedPayment
+) -> Result<Verification, Box<dyn Error>> {
+    let signatures = fetch_signatures(rpc_client, &expected.reference, None, None, usize::MAX)?;
+    let mut verification = Verification::NotFound;
+    for signature in signatures.iter().rev() {
+        let transaction = rpc_client.get_transaction_with_config(
+            signature,
+            RpcTransactionConfig {
+                encoding: Some(UiTransactionEncoding::Json),
+                commitment: Some(CommitmentConfig::confirmed()),
+                max_supported_transaction_version: Some(0),
+            }
+        )?;
+        verification = match check_payment(signature, &transaction, expected) {
+            Ok(received) => {
+                return Ok(Verification::Paid {
+                    signature: *signature,
+                    slot: transaction.slot,
+                    received,
+                });
+            }
+            Err(reason) => Verification::Mismatch { signature: *signature, reason },
+        };
+    }
+    Ok(verification)
+}
+
+/// Returns what the recipient received, or why the transaction does not settle the request.
+pub fn check_payment(
+    signature: &Signature,
+    transaction: &EncodedConfirmedTransactionWithStatusMeta,
+    expected: &ExpectedPayment
+) -> Result<u64, String> {
+    let meta = transaction.transaction.meta.as_ref().ok_or("it has no status metadata")?;
+    if let Some(err) = &meta.err {
+        return Err(format!("the transaction failed: {}", err));
+    }
+    let keys = account_keys(signature, transaction).map_err(|e| e.to_string())?;
+    if !keys.contains(&expected.reference.to_string()) {
+        return Err(format!("reference {} is not one of its accounts", expected.reference));
+    }
+
+    let recipient = expected.recipient.to_string();
+    let received = match &expected.spl_token {
+        None => {
+            let index = keys
+                .iter()
+                .position(|key| *key == recipient)
+                .ok_or_else(|| format!("recipient {} is not one of its accounts", recipient))?;
+            let (pre, post) = match (meta.pre_balances.get(index), meta.post_balances.get(index)) {
+                (Some(pre), Some(post)) => (*pre, *post),
+                _ => return Err(format!("it has no balances for recipient {}", recipient)),
+            };
+            // A recipient that also paid the fee received that much more than its delta.
+            let fee = if index == 0 { meta.fee } else { 0 };
+            (post + fee).saturating_sub(pre)
+        }
+        Some(mint) => tokens_received(meta, &recipient, &mint.to_string())?,
+    };
+    if received < expected.amount {
+        return Err(
+            format!(
+                "recipient {} received {}, not {}",
+                recipient,
+                expected.format(received),
+                expected.format(expected.amount)
+            )
+        );
+    }
+
+    if let Some(memo) = &expected.memo {
+        let memos = transaction_memos(transaction, &keys);
+        if memos.is_empty() {
+            return Err(format!("it has no memo; expected '{}'", memo));
+        }
+        if !memos.contains(memo) {
+            return Err(format!("its memo is '{}', not '{}'", memos.join("', '"), memo));
+        }
+    }
+    Ok(received)
+}
+
+/// Net tokens of `mint` received by token accounts `recipient` owns.
+fn tokens_received(
+    meta: &UiTransactionStatusMeta,
+    recipient: &str,
+    mint: &str
+) -> Result<u64, String> {
+    let mut deltas: BTreeMap<String, i128> = BTreeMap::new();
+    let token_balances = [
+        (-1, meta.pre_token_balances.as_ref().map(Clone::clone)),
+        (1, meta.post_token_balances.as_ref().map(Clone::clone)),
+    ];
+    for (sign, balances) in token_balances {
+        for balance in balances.unwrap_or_default() {
+            if balance.owner.as_ref().map(|owner| owner != recipient).unwrap_or(true) {
+                continue;
+            }
+            let amount: i128 = balance.ui_token_amount.amount.parse().map_err(|_| {
+                format!("it has an invalid token amount '{}'", balance.ui_token_amount.amount)
+            })?;
+            *deltas.entry(balance.mint).or_default() += sign * amount;
+        }
+    }
+    let received = deltas.get(mint).copied().unwrap_or_default();
+    if received <= 0 {
+        if let Some((other, _)) = deltas.iter().find(|(_, delta)| **delta > 0) {
+            return Err(
+                format!("recipient {} received tokens of {}, not {}", recipient, other, mint)
+            );
+        }
+    }
+    Ok(received.clamp(0, u64::MAX as i128) as u64)
+}
+
+/// UTF-8 texts of the transaction's memo instructions.
+fn transaction_memos(
+    transaction: &EncodedConfirmedTransactionWithStatusMeta,
+    keys: &[String]
+) -> Vec<String> {
+    let EncodedTransaction::Json(ui_transaction) = &transaction.transaction.transaction else {
+        return Vec::new();
+    };
+    let UiMessage::Raw(message) = &ui_transaction.message else {
+        return Vec::new();
+    };
+    message.instructions
+        .iter()
+        .filter(|instruction| {
+            keys.get(instruction.program_id_index as usize)
+                .is_some_and(|program| MEMO_PROGRAM_IDS.contains(&program.as_str()))
+        })
+        .filter_map(|instruction| bs58::decode(&instruction.data).into_vec().ok())
+        .map(|data| String::from_utf8_lossy(&data).into_owned())
+        .collect()
+}
+
+pub fn pay_command(
+    rpc_client: &RpcClient,
+    options: &TransactionArgs,
+    wallet: &Wallet,
+    cmd: &PayCommand
+) -> Result<(), Box<dyn Error>> {
+    match &cmd.operation {
+        PayOperations::Request(cmd) => pay_request(wallet, cmd),
+        PayOperations::Parse(cmd) => pay_parse(cmd),
+        PayOperations::Verify(cmd) => pay_verify(rpc_client, cmd),
+        PayOperations::Serve(cmd) => pay_serve(rpc_client, options, wallet, cmd),
+    }
+}
+
+fn pay_request(wallet: &Wallet, cmd: &PayRequestCommand) -> Result<(), Box<dyn Error>> {
+    let recipient = match &cmd.recipient {
+        Some(recipient) => parse_pubkey("recipient", recipient)?,
+        None => read_public_key(&wallet.resolve(None)?)?,
+    };
+    let mut references = cmd.references
+        .iter()
+        .map(|reference| parse_pubkey("reference", reference))
+        .collect::<Result<Vec<_>, _>>()?;
+    if references.is_empty() {
+        references.push(Keypair::new().pubkey());
+    }
+    let request = TransferRequest {
+        recipient,
+        amount: cmd.amount.clone(),
+        spl_token: cmd.spl_token
+            .as_deref()
+            .map(|mint| parse_pubkey("spl-token", mint))
+            .transpose()?,
+        references,
+        label: cmd.label.clone(),
+        message: cmd.message.clone(),
+        memo: cmd.memo.clone(),
+    };
+    let url = request.encode()?;
+
+    let mut value = request.to_json();
+    value["url"] = json!(url);
+    emit(value, || {
+        if !cmd.no_qr {
+            match render_qr(&url) {
+                Ok(qr) => println!("{}", qr),
+                Err(e) => eprintln!("Cannot render a QR code: {}", e),
+            }
+        }
+        println!("{}", url);
+        for reference in &request.references {
+            println!("Reference: {}", reference);
+        }
+    });
+    Ok(())
+}
+
+fn pay_parse(cmd: &PayParseCommand) -> Result<(), Box<dyn Error>> {
+    let request = TransferRequest::parse(&cmd.url)?;
+    emit(request.to_json(), || {
+        println!("Recipient: {}", request.recipient);
+        match (&request.amount, &request.spl_token) {
+            (Some(amount), Some(mint)) => println!("Amount: {} tokens of {}", amount, mint),
+            (Some(amount), None) => println!("Amount: {} SOL", amount),
+            (None, Some(mint)) => println!("Amount: chosen by the payer, in tokens of {}", mint),
+            (None, None) => println!("Amount: chosen by the payer, in SOL"),
+        }
+        for reference in &request.references {
+            println!("Reference: {}", reference);
+        }
+        for (name, value) in [
+            ("Label", &request.label),
+            ("Message", &request.message),
+            ("Memo", &request.memo),
+        ] {
+            if let Some(value) = value {
+                println!("{}: {}", name, value);
+            }
+        }
+    });
+    Ok(())
+}
+
+fn pay_verify(rpc_client: &RpcClient, cmd: &PayVerifyCommand) -> Result<(), Box<dyn Error>> {
+    let spl_token = cmd.spl_token
+        .as_deref()
+        .map(|mint| parse_pubkey("spl-token", mint))
+        .transpose()?;
+    let decimals = match &spl_token {
+        Some(mint) => MintInfo::fetch(rpc_client, mint)?.decimals,
+        None => SOL_DECIMALS,
+    };
+    let expected = ExpectedPayment {
+        recipient: parse_pubkey("recipient", &cmd.recipient)?,
+        amount: Amount::parse(&cmd.amount, decimals, false)?.exact()?,
+        decimals,
+        spl_token,
+        reference: parse_pubkey("reference", &cmd.reference)?,
+        memo: cmd.memo.clone(),
+    };
+
+    match verify_payment(rpc_client, &expected)? {
+        Verification::Paid { signature, slot, received } => {
+            emit(
+                json!({
+                    "status": "paid",
+                    "reference": expected.reference.to_string(),
+                    "recipient": expected.recipient.to_string(),
+                    "amount": format_token_amount(received, decimals),
+                    "base_units": received,
+                    "spl_token": spl_token.map(|mint| mint.to_string()),
+                    "signature": signature.to_string(),
+                    "slot": slot,
+                }),
+                || {
+                    println!("Paid: {} received {}", expected.recipient, expected.format(received));
+                    println!("Signature: {}", signature);
+                }
+            );
+            Ok(())
+        }
+        Verification::NotFound => {
+            Err(format!("No transaction references {} yet", expected.reference).into())
+        }
+        Verification::Mismatch { signature, reason } => {
+            Err(format!("Transaction {} does not settle the request: {}", signature, reason).into())
+        }
+    }
+}
+
+/// Renders light modules as blocks, which reads as dark-on-light on a dark terminal.
+fn render_qr(url: &str) -> Result<String, Box<dyn Error>> {
+    let code = QrCode::new(url.as_bytes())?;
+    Ok(code.render::<Dense1x2>().dark_color(Dense1x2::Light).light_color(Dense1x2::Dark).build())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STORE: &str = "HjJQdfTHgC3EBX3471w4st8BXbBmtbaMyCAXNgcUb7dq";
+    const SYSTEM_PROGRAM: &str = "11111111111111111111111111111111";
+    const MEMO: &str = MEMO_PROGRAM_IDS[0];
+
+    #[test]
+    fn encodes_and_parses_transfer_requests() {
+        let request = TransferRequest {
+            recipient: Pubkey::from_str(STORE).unwrap(),
+            amount: Some("0.1".to_string()),
+            references: vec![Pubkey::new_unique(), Pubkey::new_unique()],
+            label: Some("Solana Shirts".to_string()),
+            message: Some("Purchase of 1 shirt(s) for 0.1 SOL".to_string()),
+            memo: Some("T-shirt purchase".to_string()),
+            ..Default::default()
+        };
+        let url = request.encode().unwrap();
+        assert!(url.starts_with(&format!("solana:{}?amount=0.1&reference=", STORE)));
+        assert!(
+            url.ends_with(
+                "&label=Solana%20Shirts&message=Purchase%20of%201%20shirt(s)%20for%200.1%20SOL\
+                 &memo=T-shirt%20purchase"
+            )
+        );
+        assert_eq!(TransferRequest::parse(&url).unwrap(), request);
+
+        // Form encoding, as URLSearchParams writes it, is accepted too.
+        let parsed = TransferRequest::parse(&format!("solana:{}?label=Solana+Shirts", STORE));
+        assert_eq!(parsed.unwrap().label.as_deref(), Some("Solana Shirts"));
+    }
+
+    #[test]
+    fn rejects_invalid_requests() {
+        let invalid = [
+            "https://example.com".to_string(),
+            "solana:not-a-key".to_string(),
+            "solana:https://example.com/pay".to_string(),
+            format!("solana:{}?amount=.5", STORE),
+            format!("solana:{}?amount=1e3", STORE),
+            format!("solana:{}?amount=-1", STORE),
+            format!("solana:{}?amount=0.0000000001", STORE),
+            format!("solana:{}?amount=1&amount=2", STORE),
+            format!("solana:{}?reference=nope", STORE),
+        ];
+        for url in &invalid {
+            assert!(TransferRequest::parse(url).is_err(), "{} should be rejected", url);
+        }
+        let tokens = format!("solana:{}?amount=0.0000000001&spl-token={}", STORE, STORE);
+        assert!(TransferRequest::parse(&tokens).is_ok());
+    }
+
+    /// A `getTransaction` result in which the payer sends `lamports` to the store.
+    fn payment(reference: &Pubkey, lamports: u64, memo: &str, err: Value) -> Value {
+        let payer = Pubkey::new_unique().to_string();
+        let memo = bs58::encode(memo).into_string();
+        json!({
+            "slot": 42,
+            "blockTime": null,
+            "transaction": {
+                "signatures": [Signature::default().to_string()],
+                "message": {
+                    "header": {
+                        "numRequiredSignatures": 1,
+                        "numReadonlySignedAccounts": 0,
+                        "numReadonlyUnsignedAccounts": 3,
+                    },
+                    "accountKeys": [payer, STORE, reference.to_string(), SYSTEM_PROGRAM, MEMO],
+                    "recentBlockhash": Signature::default().to_string()[..44].to_string(),
+                    "instructions": [
+                        { "programIdIndex": 4, "accounts": [], "data": memo },
+                        { "programIdIndex": 3, "accounts": [0, 1, 2], "data": "" },
+                    ],
+                },
+            },
+            "meta": {
+                "err": err,
+                "status": { "Ok": null },
+                "fee": 5000,
+                "preBalances": [1_000_000_000u64, 0, 0, 1, 1],
+                "postBalances": [1_000_000_000 - lamports - 5000, lamports, 0, 1, 1],
+                "innerInstructions": [],
+                "logMessages": [],
+                "preTokenBalances": [],
+                "postTokenBalances": [],
+                "rewards": [],
+            },
+        })
+    }
+
+    #[test]
+    fn checks_payments_against_the_request() {
+        let reference = Pubkey::new_unique();
+        let expected = ExpectedPayment {
+            recipient: Pubkey::from_str(STORE).unwrap(),
+            amount: 100_000_000,
+            decimals: SOL_DECIMALS,
+            spl_token: None,
+            reference,
+            memo: Some("T-shirt purchase".to_string()),
+        };
+        let check = |transaction: Value| {
+            let transaction = serde_json::from_value(transaction).unwrap();
+            check_payment(&Signature::default(), &transaction, &expected)
+        };
+
+        let paid = payment(&reference, 100_000_000, "T-shirt purchase", Value::Null);
+        assert_eq!(check(paid), Ok(100_000_000));
+        let short = check(payment(&reference, 50_000_000, "T-shirt purchase", Value::Null));
+        assert!(short.unwrap_err().ends_with("received 0.05 SOL, not 0.1 SOL"));
+        let memo = check(payment(&reference, 100_000_000, "Hoodie", Value::Null));
+        assert_eq!(memo.unwrap_err(), "its memo is 'Hoodie', not 'T-shirt purchase'");
+        let unrelated = Pubkey::new_unique();
+        let other = check(payment(&unrelated, 100_000_000, "T-shirt purchase", Value::Null));
+        assert!(other.unwrap_err().starts_with("reference"));
+        let failed = json!({ "InstructionError": [1, { "Custom": 1 }] });
+        let failed = check(payment(&reference, 0, "T-shirt purchase", failed));
+        assert!(failed.unwrap_err().starts_with("the transaction failed"));
+    }
+}
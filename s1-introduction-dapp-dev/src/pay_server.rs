@@ -0,0 +1,564 @@
+use serde_json::{ json, Value };
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{
+    hash::Hash,
+    instruction::{ AccountMeta, Instruction },
+    message::Message,
+    pubkey::Pubkey,
+    signature::Keypair,
+    signer::Signer,
+    system_instruction,
+    transaction::Transaction,
+};
+use std::{ error::Error, fmt::Display, io::Read, str::FromStr };
+use tiny_http::{ Header, Response, Server };
+use url::Url;
+
+use crate::{
+    amount::{ Amount, SOL_DECIMALS },
+    keystore::{ read_keys, read_public_key },
+    offline::encode_transaction,
+    orders::{ MemoryOrderStore, Order, OrderStatus, OrderStore, SqliteOrderStore },
+    output::status,
+    pay::*,
+    state::*,
+    token::{ format_token_amount, MintInfo },
+    transaction::compute_budget_instructions,
+    wallet::Wallet,
+};
+
+/// Largest request body read; transaction requests and orders are a few dozen bytes.
+const MAX_BODY: u64 = 64 * 1024;
+
+/// Served at `/icon.svg` and shown by wallets when `--icon` is not given.
+const DEFAULT_ICON: &str = concat!(
+    r##"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 64 64">"##,
+    r##"<rect width="64" height="64" rx="12" fill="#14f195"/>"##,
+    r##"<path d="M22 12l-12 8 5 9 5-3v26h24V26l5 3 5-9-12-8c-2 4-6 6-10 6s-8-2-10-6z" "##,
+    r##"fill="#9945ff"/></svg>"##
+);
+
+/// A response to an HTTP request. Every body is JSON except the icon's.
+#[derive(Debug)]
+pub struct Reply {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+impl Reply {
+    fn json(status: u16, value: Value) -> Self {
+        Self { status, content_type: "application/json", body: value.to_string() }
+    }
+
+    fn error(status: u16, message: impl Display) -> Self {
+        Self::json(status, json!({ "error": message.to_string() }))
+    }
+}
+
+/// Failures of the server itself, or of the cluster, rather than of the request.
+fn internal(error: impl Display) -> Reply {
+    Reply::error(500, error)
+}
+
+/// What `pay serve` sells and how it is paid for.
+pub struct Storefront {
+    pub recipient: Pubkey,
+    /// Price of one item, in base units of SOL or of `mint`.
+    pub price: u64,
+    pub mint: Option<MintInfo>,
+    pub item: String,
+    pub label: String,
+    pub icon: String,
+    pub memo: Option<String>,
+    /// Base of the links handed out; it ends with a slash.
+    pub public_url: Url,
+}
+
+impl Storefront {
+    fn decimals(&self) -> u8 {
+        self.mint.as_ref().map_or(SOL_DECIMALS, |mint| mint.decimals)
+    }
+
+    fn format(&self, amount: u64) -> String {
+        let amount = format_token_amount(amount, self.decimals());
+        match &self.mint {
+            Some(mint) => format!("{} tokens of {}", amount, mint.address),
+            None => format!("{} SOL", amount),
+        }
+    }
+}
+
+/// A Solana Pay transaction request server: orders are created over HTTP, paid with a
+/// transaction the server builds and signs as fee payer, and settled by their reference.
+pub struct PayServer<'a> {
+    rpc_client: &'a RpcClient,
+    options: &'a TransactionArgs,
+    fee_payer: Keypair,
+    store: Box<dyn OrderStore>,
+    shop: Storefront,
+}
+
+impl<'a> PayServer<'a> {
+    pub fn new(
+        rpc_client: &'a RpcClient,
+        options: &'a TransactionArgs,
+        fee_payer: Keypair,
+        store: Box<dyn OrderStore>,
+        shop: Storefront
+    ) -> Self {
+        Self { rpc_client, options, fee_payer, store, shop }
+    }
+
+    /// Routes a request; `url` is the path and query, as sent in the request line.
+    pub fn handle(&mut self, method: &str, url: &str, body: &str) -> Reply {
+        let url = match self.shop.public_url.join(url) {
+            Ok(url) => url,
+            Err(e) => {
+                return Reply::error(400, format!("Invalid URL '{}': {}", url, e));
+            }
+        };
+        let result = match (method, url.path()) {
+            ("OPTIONS", _) => {
+                Ok(Reply { status: 204, content_type: "text/plain", body: String::new() })
+            }
+            ("GET", "/icon.svg") => {
+                Ok(Reply { status: 200, content_type: "image/svg+xml", body: DEFAULT_ICON.into() })
+            }
+            ("GET", "/pay") => {
+                Ok(Reply::json(200, json!({ "label": self.shop.label, "icon": self.shop.icon })))
+            }
+            ("POST", "/pay") => self.create_transaction(&url, body),
+            ("POST", "/orders") => self.create_order(body),
+            ("GET", path) if path.starts_with("/orders/") => {
+                self.order_status(&path["/orders/".len()..])
+            }
+            (_, "/pay" | "/orders") => {
+                Err(Reply::error(405, format!("{} is not allowed on {}", method, url.path())))
+            }
+            _ => Err(Reply::error(404, format!("Nothing at {}", url.path()))),
+        };
+        result.unwrap_or_else(|reply| reply)
+    }
+
+    /// `POST /orders {"quantity": n}`: records a pending order and returns its payment link.
+    fn create_order(&mut self, body: &str) -> Result<Reply, Reply> {
+        let request = parse_body(body)?;
+        let quantity = request["quantity"]
+            .as_u64()
+            .filter(|quantity| *quantity > 0)
+            .and_then(|quantity| u32::try_from(quantity).ok())
+            .ok_or_else(|| Reply::error(400, "quantity must be a positive whole number"))?;
+        let amount = self.shop.price
+            .checked_mul(quantity.into())
+            .ok_or_else(|| Reply::error(400, "The order total is too large"))?;
+
+        let order = Order {
+            reference: Keypair::new().pubkey().to_string(),
+            recipient: self.shop.recipient.to_string(),
+            spl_token: self.shop.mint.as_ref().map(|mint| mint.address.to_string()),
+            amount,
+            decimals: self.shop.decimals(),
+            quantity,
+            memo: self.shop.memo.clone(),
+            message: format!(
+                "Purchase of {} {}(s) for {}",
+                quantity,
+                self.shop.item,
+                self.shop.format(amount)
+            ),
+            status: OrderStatus::Pending,
+            signature: None,
+        };
+        self.store.insert(&order).map_err(internal)?;
+        status(&format!("Order {}: {}", order.reference, order.message));
+        Ok(Reply::json(201, self.order_json(&order).map_err(internal)?))
+    }
+
+    /// `POST /pay?reference=..`, the transaction request: `{"account"}` is the wallet paying.
+    fn create_transaction(&mut self, url: &Url, body: &str) -> Result<Reply, Reply> {
+        let reference = url
+            .query_pairs()
+            .find(|(key, _)| key == "reference")
+            .map(|(_, value)| value.into_owned())
+            .ok_or_else(|| Reply::error(400, "The reference query parameter is missing"))?;
+        let order = self.find_order(&reference)?;
+        if order.status == OrderStatus::Paid {
+            return Err(Reply::error(409, format!("Order {} is already paid", reference)));
+        }
+        let account = parse_body(body)?["account"]
+            .as_str()
+            .ok_or_else(|| Reply::error(400, "account is missing"))
+            .and_then(|account| {
+                parse_pubkey("account", account).map_err(|e| Reply::error(400, e))
+            })?;
+        if account == self.fee_payer.pubkey() {
+            let message = "account must be the customer's wallet, not the fee payer";
+            return Err(Reply::error(400, message));
+        }
+
+        let instructions = self.payment_instructions(&order, &account).map_err(internal)?;
+        let mut all_instructions = compute_budget_instructions(
+            self.rpc_client,
+            self.options,
+            &instructions
+        ).map_err(internal)?;
+        all_instructions.extend(instructions);
+        let blockhash = self.rpc_client.get_latest_blockhash().map_err(internal)?;
+        let transaction = sign_payment_transaction(&all_instructions, &self.fee_payer, blockhash)
+            .map_err(internal)?;
+        let encoded = encode_transaction(&transaction, TransactionEncoding::Base64).map_err(
+            internal
+        )?;
+        status(&format!("Order {}: transaction built for {}", reference, account));
+        Ok(Reply::json(200, json!({ "transaction": encoded, "message": order.message })))
+    }
+
+    /// `GET /orders/<reference>`: pending orders are looked up on chain first.
+    fn order_status(&mut self, reference: &str) -> Result<Reply, Reply> {
+        let mut order = self.find_order(reference)?;
+        let mut mismatch = None;
+        if order.status == OrderStatus::Pending {
+            let expected = expected_payment(&order).map_err(internal)?;
+            match verify_payment(self.rpc_client, &expected).map_err(internal)? {
+                Verification::Paid { signature, .. } => {
+                    let signature = signature.to_string();
+                    self.store.mark_paid(reference, &signature).map_err(internal)?;
+                    status(&format!("Order {}: paid by {}", reference, signature));
+                    order.status = OrderStatus::Paid;
+                    order.signature = Some(signature);
+                }
+                Verification::NotFound => {}
+                Verification::Mismatch { signature, reason } => {
+                    let signature = signature.to_string();
+                    mismatch = Some(json!({ "signature": signature, "reason": reason }));
+                }
+            }
+        }
+        let mut value = self.order_json(&order).map_err(internal)?;
+        if let Some(mismatch) = mismatch {
+            value["mismatch"] = mismatch;
+        }
+        Ok(Reply::json(200, value))
+    }
+
+    fn find_order(&self, reference: &str) -> Result<Order, Reply> {
+        self.store
+            .get(reference)
+            .map_err(internal)?
+            .ok_or_else(|| Reply::error(404, format!("Order {} not found", reference)))
+    }
+
+    /// The order, its formatted total, its transaction request URL and where to poll it.
+    fn order_json(&self, order: &Order) -> Result<Value, Box<dyn Error>> {
+        let mut link = self.shop.public_url.join("pay")?;
+        link.query_pairs_mut().append_pair("reference", &order.reference);
+        let status_url = self.shop.public_url.join(&format!("orders/{}", order.reference))?;
+
+        let mut value = serde_json::to_value(order)?;
+        value["total"] = json!(format_token_amount(order.amount, order.decimals));
+        value["url"] = json!(encode_transaction_request(&link));
+        value["status_url"] = json!(status_url.to_string());
+        Ok(value)
+    }
+
+    /// The memo, then the transfer from `customer` with the order's reference appended as a
+    /// read-only account, so `verify_payment` can find it. The fee payer signs whatever it
+    /// builds, so it is never the customer: that transaction would pay the order itself.
+    pub fn payment_instructions(
+        &self,
+        order: &Order,
+        customer: &Pubkey
+    ) -> Result<Vec<Instruction>, Box<dyn Error>> {
+        if *customer == self.fee_payer.pubkey() {
+            return Err("The fee payer cannot pay for an order".into());
+        }
+        let recipient = Pubkey::from_str(&order.recipient)?;
+        let reference = Pubkey::from_str(&order.reference)?;
+        let mint_address = self.shop.mint.as_ref().map(|mint| mint.address.to_string());
+        if order.spl_token != mint_address {
+            return Err(format!("Order {} is priced in another currency", order.reference).into());
+        }
+
+        let mut instructions = Vec::new();
+        if let Some(memo) = &order.memo {
+            instructions.push(memo_instruction(memo));
+        }
+        let mut transfer = match &self.shop.mint {
+            Some(mint) => {
+                let fee_payer = self.fee_payer.pubkey();
+                instructions.push(mint.create_associated_account(&fee_payer, &recipient));
+                let source = mint.associated_account(customer);
+                let destination = mint.associated_account(&recipient);
+                let (instruction, _) = mint.transfer(
+                    self.rpc_client,
+                    &source,
+                    &destination,
+                    customer,
+                    order.amount
+                )?;
+                instruction
+            }
+            None => system_instruction::transfer(customer, &recipient, order.amount),
+        };
+        transfer.accounts.push(AccountMeta::new_readonly(reference, false));
+        instructions.push(transfer);
+        Ok(instructions)
+    }
+}
+
+/// Signs as fee payer only; the customer's signature is left for their wallet to add.
+pub fn sign_payment_transaction(
+    instructions: &[Instruction],
+    fee_payer: &Keypair,
+    blockhash: Hash
+) -> Result<Transaction, Box<dyn Error>> {
+    let mut transaction = Transaction::new_unsigned(
+        Message::new(instructions, Some(&fee_payer.pubkey()))
+    );
+    transaction.try_partial_sign(&[fee_payer], blockhash)?;
+    Ok(transaction)
+}
+
+fn expected_payment(order: &Order) -> Result<ExpectedPayment, Box<dyn Error>> {
+    Ok(ExpectedPayment {
+        recipient: Pubkey::from_str(&order.recipient)?,
+        amount: order.amount,
+        decimals: order.decimals,
+        spl_token: order.spl_token.as_deref().map(Pubkey::from_str).transpose()?,
+        reference: Pubkey::from_str(&order.reference)?,
+        memo: order.memo.clone(),
+    })
+}
+
+fn parse_body(body: &str) -> Result<Value, Reply> {
+    serde_json::from_str(body).map_err(|e| Reply::error(400, format!("Invalid JSON body: {}", e)))
+}
+
+/// Reads a request body of at most `MAX_BODY` bytes.
+fn read_body(reader: impl Read) -> Result<String, Reply> {
+    let mut body = String::new();
+    reader
+        .take(MAX_BODY + 1)
+        .read_to_string(&mut body)
+        .map_err(|e| Reply::error(400, format!("Cannot read the request body: {}", e)))?;
+    if body.len() as u64 > MAX_BODY {
+        return Err(Reply::error(413, format!("The request body is over {} bytes", MAX_BODY)));
+    }
+    Ok(body)
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).expect("valid header")
+}
+
+pub fn pay_serve(
+    rpc_client: &RpcClient,
+    options: &TransactionArgs,
+    wallet: &Wallet,
+    cmd: &PayServeCommand
+) -> Result<(), Box<dyn Error>> {
+    if options.nonce.is_some() {
+        return Err(
+            "--nonce is not supported: every payment transaction has its own blockhash".into()
+        );
+    }
+    let fee_payer = read_keys(&wallet.resolve(cmd.fee_payer.as_deref())?)?;
+    let recipient = match &cmd.recipient {
+        Some(recipient) => parse_pubkey("recipient", recipient)?,
+        None => read_public_key(&wallet.resolve(None)?)?,
+    };
+    let mint = match &cmd.spl_token {
+        Some(mint) => Some(MintInfo::fetch(rpc_client, &parse_pubkey("spl-token", mint)?)?),
+        None => None,
+    };
+    if mint.as_ref().is_some_and(|mint| mint.transfer_fee.is_some()) {
+        return Err(
+            "Mints with transfer fees are not supported: the price would not arrive whole".into()
+        );
+    }
+    let decimals = mint.as_ref().map_or(SOL_DECIMALS, |mint| mint.decimals);
+    let mut public_url = match &cmd.public_url {
+        Some(url) => url.clone(),
+        None => format!("http://{}/", cmd.bind),
+    };
+    if !public_url.ends_with('/') {
+        public_url.push('/');
+    }
+    let public_url = Url::parse(&public_url).map_err(|e| {
+        format!("Invalid public URL '{}': {}", public_url, e)
+    })?;
+    let shop = Storefront {
+        recipient,
+        price: Amount::parse(&cmd.price, decimals, false)?.exact()?,
+        mint,
+        item: cmd.item.clone(),
+        label: cmd.label.clone(),
+        icon: cmd.icon.clone().unwrap_or_else(|| public_url.join("icon.svg").unwrap().to_string()),
+        memo: if cmd.no_memo { None } else { Some(cmd.memo.clone()) },
+        public_url,
+    };
+    let store: Box<dyn OrderStore> = match &cmd.database {
+        Some(path) => Box::new(SqliteOrderStore::open(path)?),
+        None => Box::new(MemoryOrderStore::default()),
+    };
+
+    let server = Server::http(&cmd.bind).map_err(|e| {
+        format!("Cannot listen on {}: {}", cmd.bind, e)
+    })?;
+    status(&format!("{}: {} per {}", shop.label, shop.format(shop.price), shop.item));
+    status(&format!("Fee payer: {}", fee_payer.pubkey()));
+    status(&format!("Listening on {}", shop.public_url));
+    let mut pay_server = PayServer::new(rpc_client, options, fee_payer, store, shop);
+    for mut request in server.incoming_requests() {
+        let reply = match read_body(request.as_reader()) {
+            Ok(body) => pay_server.handle(request.method().as_str(), request.url(), &body),
+            Err(reply) => reply,
+        };
+        status(&format!("{} {} {}", request.method(), request.url(), reply.status));
+        let response = Response::from_string(reply.body)
+            .with_status_code(reply.status)
+            .with_header(header("Content-Type", reply.content_type))
+            .with_header(header("Access-Control-Allow-Origin", "*"))
+            .with_header(header("Access-Control-Allow-Methods", "GET, POST, OPTIONS"))
+            .with_header(header("Access-Control-Allow-Headers", "Content-Type"));
+        if let Err(e) = request.respond(response) {
+            eprintln!("Cannot send the response: {}", e);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::offline::missing_signers;
+
+    const STORE: &str = "HjJQdfTHgC3EBX3471w4st8BXbBmtbaMyCAXNgcUb7dq";
+
+    fn shop() -> Storefront {
+        Storefront {
+            recipient: Pubkey::from_str(STORE).unwrap(),
+            price: 100_000_000,
+            mint: None,
+            item: "shirt".to_string(),
+            label: "Solana Shirts".to_string(),
+            icon: "https://example.com/icon.svg".to_string(),
+            memo: Some("T-shirt purchase".to_string()),
+            public_url: Url::parse("https://shop.example.com/").unwrap(),
+        }
+    }
+
+    fn body(reply: &Reply) -> Value {
+        serde_json::from_str(&reply.body).unwrap()
+    }
+
+    #[test]
+    fn takes_orders_and_answers_transaction_requests() {
+        // Nothing here reaches the cluster.
+        let rpc_client = RpcClient::new("http://127.0.0.1:1".to_string());
+        let options = TransactionArgs::for_tests();
+        let store = Box::new(MemoryOrderStore::default());
+        let mut server = PayServer::new(&rpc_client, &options, Keypair::new(), store, shop());
+
+        let reply = server.handle("GET", "/pay?reference=x", "");
+        assert_eq!(reply.status, 200);
+        assert_eq!(body(&reply)["label"], "Solana Shirts");
+        assert_eq!(body(&reply)["icon"], "https://example.com/icon.svg");
+
+        let reply = server.handle("POST", "/orders", r#"{"quantity": 2}"#);
+        assert_eq!(reply.status, 201);
+        let order = body(&reply);
+        let reference = order["reference"].as_str().unwrap().to_string();
+        assert_eq!(order["amount"], 200_000_000);
+        assert_eq!(order["total"], "0.2");
+        assert_eq!(order["status"], "pending");
+        assert_eq!(order["message"], "Purchase of 2 shirt(s) for 0.2 SOL");
+        assert_eq!(
+            order["url"],
+            format!("solana:https%3A%2F%2Fshop.example.com%2Fpay%3Freference%3D{}", reference)
+        );
+        assert_eq!(
+            order["status_url"],
+            format!("https://shop.example.com/orders/{}", reference)
+        );
+
+        assert_eq!(server.handle("POST", "/orders", r#"{"quantity": 0}"#).status, 400);
+        assert_eq!(server.handle("POST", "/orders", "shirts").status, 400);
+        assert_eq!(server.handle("GET", "/orders/unknown", "").status, 404);
+        assert_eq!(server.handle("POST", "/pay", r#"{"account": "x"}"#).status, 400);
+        assert_eq!(server.handle("POST", "/pay?reference=unknown", "{}").status, 404);
+        let url = format!("/pay?reference={}", reference);
+        assert_eq!(server.handle("POST", &url, r#"{"account": "x"}"#).status, 400);
+        assert_eq!(server.handle("DELETE", "/orders", "").status, 405);
+        assert_eq!(server.handle("OPTIONS", "/pay", "").status, 204);
+        assert_eq!(server.handle("GET", "/", "").status, 404);
+    }
+
+    #[test]
+    fn limits_the_request_body() {
+        let body = vec![b' '; MAX_BODY as usize];
+        assert_eq!(read_body(body.as_slice()).unwrap().len(), body.len());
+        let body = vec![b' '; MAX_BODY as usize + 1];
+        assert_eq!(read_body(body.as_slice()).unwrap_err().status, 413);
+    }
+
+    #[test]
+    fn payment_transactions_are_signed_by_the_fee_payer_only() {
+        let rpc_client = RpcClient::new("http://127.0.0.1:1".to_string());
+        let options = TransactionArgs::for_tests();
+        let fee_payer = Keypair::new();
+        let fee_payer_key = fee_payer.pubkey();
+        let store = Box::new(MemoryOrderStore::default());
+        let server = PayServer::new(&rpc_client, &options, fee_payer, store, shop());
+        let reference = Pubkey::new_unique();
+        let customer = Pubkey::new_unique();
+        let order = Order {
+            reference: reference.to_string(),
+            recipient: STORE.to_string(),
+            spl_token: None,
+            amount: 100_000_000,
+            decimals: SOL_DECIMALS,
+            quantity: 1,
+            memo: Some("T-shirt purchase".to_string()),
+            message: "Purchase of 1 shirt(s) for 0.1 SOL".to_string(),
+            status: OrderStatus::Pending,
+            signature: None,
+        };
+
+        let instructions = server.payment_instructions(&order, &customer).unwrap();
+        assert_eq!(instructions.len(), 2);
+        assert_eq!(instructions[0].data, b"T-shirt purchase");
+        let transfer = &instructions[1];
+        assert_eq!(transfer.accounts[0], AccountMeta::new(customer, true));
+        assert_eq!(transfer.accounts[2], AccountMeta::new_readonly(reference, false));
+
+        let blockhash = Hash::new_unique();
+        let transaction = sign_payment_transaction(&instructions, &server.fee_payer, blockhash)
+            .unwrap();
+        assert_eq!(transaction.message.account_keys[0], fee_payer_key);
+        assert_eq!(transaction.message.recent_blockhash, blockhash);
+        assert_eq!(missing_signers(&transaction), vec![customer]);
+        assert_eq!(transaction.verify_with_results(), vec![true, false]);
+    }
+
+    #[test]
+    fn refuses_to_build_payments_from_the_fee_payer() {
+        let rpc_client = RpcClient::new("http://127.0.0.1:1".to_string());
+        let options = TransactionArgs::for_tests();
+        let fee_payer = Keypair::new();
+        let fee_payer_key = fee_payer.pubkey();
+        let store = Box::new(MemoryOrderStore::default());
+        let mut server = PayServer::new(&rpc_client, &options, fee_payer, store, shop());
+
+        let reply = server.handle("POST", "/orders", r#"{"quantity": 1}"#);
+        let reference = body(&reply)["reference"].as_str().unwrap().to_string();
+        let order = server.find_order(&reference).unwrap();
+        let url = format!("/pay?reference={}", reference);
+        let request = json!({ "account": fee_payer_key.to_string() }).to_string();
+        let reply = server.handle("POST", &url, &request);
+        assert_eq!(reply.status, 400);
+        assert!(body(&reply).get("transaction").is_none());
+        assert!(server.payment_instructions(&order, &fee_payer_key).is_err());
+    }
+}
@@ -1,20 +1,75 @@
-use clap::{ Parser, Subcommand, ValueEnum };
+use clap::{ Args, Parser, Subcommand, ValueEnum };
 use serde::{ Deserialize, Serialize };
+use std::collections::BTreeMap;
 
+use crate::transaction::NonceAuthority;
+
+/// Named connection profiles, stored in `<config dir>/scw/config.json`.
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ProgramSettings {
-    pub endpoint: String,
+    pub active_profile: String,
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Profile {
+    pub rpc_url: String,
+    /// Derived from `rpc_url` when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub websocket_url: Option<String>,
+    #[serde(default)]
+    pub commitment: Commitment,
+    /// Key file path or wallet label used when `--account-file` is not given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keypair: Option<String>,
+    /// Micro-lamports per compute unit, used when `--priority-fee` is not given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority_fee: Option<u64>,
 }
 
+#[derive(Serialize, Deserialize, ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Commitment {
+    Processed,
+    #[default]
+    Confirmed,
+    Finalized,
+}
+
+// Settings commands run without a profile; the rest connect through the selected one.
 #[derive(Subcommand)]
 pub enum Operations {
+    #[clap(flatten)]
+    Profile(Box<ProfileOperations>),
+    UpdateSettings(UpdateSettingsCommand),
+    Config(ConfigCommand),
+}
+
+#[derive(Subcommand)]
+pub enum ProfileOperations {
     Generate(GenerateCommand),
+    Recover(RecoverCommand),
     Fetch(FetchCommand),
     Fund(FundCommand),
     Send(SendCommand),
     Balance(BalanceCommand),
     List(ListCommand),
-    UpdateSettings(UpdateSettingsCommand),
+    Use(UseCommand),
+    #[clap(alias = "reencrypt")]
+    ChangePassword(ChangePasswordCommand),
+    Migrate(MigrateCommand),
+    Import(ImportCommand),
+    Export(ExportCommand),
+    Token(TokenCommand),
+    History(HistoryCommand),
+    Broadcast(BroadcastCommand),
+    Sign(SignCommand),
+    Nonce(NonceCommand),
+    BatchSend(BatchSendCommand),
+    Vault(VaultCommand),
+    NativeVault(NativeVaultCommand),
+    Pay(PayCommand),
+    Cnft(CnftCommand),
 }
 
 #[derive(Serialize, Deserialize)]
@@ -23,6 +78,52 @@ pub struct AccountKeys {
     pub private_key: String,
 }
 
+/// Passphrase-protected keystore, written by `save_keys` unless `--plaintext` is passed.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct EncryptedAccountKeys {
+    pub version: u8,
+    pub public_key: String,
+    pub crypto: KeystoreCrypto,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct KeystoreCrypto {
+    pub kdf: String,
+    pub kdf_params: KdfParams,
+    pub cipher: String,
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct KdfParams {
+    pub log_n: u8,
+    pub r: u32,
+    pub p: u32,
+    pub salt: String,
+}
+
+/// Metadata stored alongside the labelled key files of a wallet directory.
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct WalletConfig {
+    pub default: Option<String>,
+}
+
+/// Watch-only entry: a public key tracked without its secret.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct WatchKeys {
+    pub public_key: String,
+}
+
+/// Any key file format `read_keys` understands.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum KeyFile {
+    Encrypted(EncryptedAccountKeys),
+    Plain(AccountKeys),
+    Watch(WatchKeys),
+}
+
 #[derive(Parser)]
 #[clap(author, version, about)]
 pub struct Cli {
@@ -31,33 +132,186 @@ pub struct Cli {
         short,
         long,
         global = true,
-        default_value = "account.json",
-        help = "Global path to the account keys file"
+        help = "Global path to the account keys file [default: wallet default, then account.json]"
+    )]
+    pub account_file: Option<String>,
+
+    #[clap(
+        long,
+        global = true,
+        default_value = "wallet",
+        help = "Directory holding labelled account key files"
     )]
-    pub account_file: String,
+    pub wallet_dir: String,
+
+    #[clap(
+        long,
+        global = true,
+        env = "SCW_PROFILE",
+        help = "Configuration profile to use [default: the active profile]"
+    )]
+    pub profile: Option<String>,
+
+    #[clap(
+        long,
+        global = true,
+        value_enum,
+        default_value = "text",
+        help = "Output format; JSON modes print one document per command and errors as JSON"
+    )]
+    pub output: OutputFormat,
+
+    #[clap(flatten)]
+    pub transaction: TransactionArgs,
 
     #[clap(subcommand)]
     pub operation: Operations,
 }
 
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum OutputFormat {
+    Text,
+    /// Pretty-printed JSON
+    Json,
+    /// JSON on a single line
+    JsonCompact,
+}
+
+/// Options shared by every command that sends a transaction.
+#[derive(Args, Debug)]
+pub struct TransactionArgs {
+    #[clap(long, global = true, help = "Priority fee in micro-lamports per compute unit")]
+    pub priority_fee: Option<u64>,
+    #[clap(long, global = true, help = "Maximum compute units each transaction may use")]
+    pub compute_unit_limit: Option<u32>,
+    #[clap(
+        long,
+        global = true,
+        conflicts_with = "priority_fee",
+        help = "Derive the priority fee from fees recently paid for the same accounts. Not \
+                available with --sign-only."
+    )]
+    pub auto_fee: bool,
+    #[clap(
+        long,
+        global = true,
+        default_value_t = 75,
+        value_parser = clap::value_parser!(u8).range(0..=100),
+        help = "Percentile of recent priority fees used by --auto-fee"
+    )]
+    pub fee_percentile: u8,
+    #[clap(
+        long,
+        global = true,
+        help = "Durable nonce account to use instead of a recent blockhash"
+    )]
+    pub nonce: Option<String>,
+    #[clap(
+        long,
+        global = true,
+        requires = "nonce",
+        help = "Key file path or wallet label of the nonce authority, or with --sign-only its \
+                public key, for it to sign later. Defaults to the fee payer."
+    )]
+    pub nonce_authority: Option<String>,
+    #[clap(
+        long,
+        global = true,
+        default_value_t = 90,
+        help = "Seconds to wait for a transaction to reach the profile's commitment"
+    )]
+    pub confirm_timeout: u64,
+    /// `--nonce-authority`, resolved against the wallet but not yet unlocked.
+    #[clap(skip)]
+    pub nonce_authority_signer: Option<NonceAuthority>,
+    /// Commitment of the selected profile that transactions are confirmed at.
+    #[clap(skip)]
+    pub commitment: Commitment,
+    /// Websocket endpoint of the selected profile, for `signatureSubscribe`.
+    #[clap(skip)]
+    pub websocket_url: Option<String>,
+}
+
 #[derive(Parser)]
 pub struct GenerateCommand {
     #[clap(short, long, help = "Optional path to save the keys file")]
     pub file: Option<String>,
+    #[clap(short, long, conflicts_with = "file", help = "Save the keys in the wallet under this label")]
+    pub label: Option<String>,
+    #[clap(long, help = "Store the private key unencrypted (not recommended)")]
+    pub plaintext: bool,
+    #[clap(long, help = "Derive the account from a new BIP39 mnemonic")]
+    pub mnemonic: bool,
+    #[clap(long, value_enum, default_value = "12", help = "Number of mnemonic words")]
+    pub words: WordCount,
+    #[clap(flatten)]
+    pub derivation: DerivationArgs,
+    #[clap(long, conflicts_with = "mnemonic", help = "Grind for an address starting with this")]
+    pub starts_with: Option<String>,
+    #[clap(long, conflicts_with = "mnemonic", help = "Grind for an address ending with this")]
+    pub ends_with: Option<String>,
+    #[clap(long, help = "Match the vanity pattern case-insensitively")]
+    pub ignore_case: bool,
+    #[clap(long, help = "Number of grinding threads. Defaults to all cores.")]
+    pub threads: Option<usize>,
+    #[clap(long, help = "Give up after this many attempts")]
+    pub max_attempts: Option<u64>,
+}
+
+#[derive(Parser)]
+pub struct RecoverCommand {
+    #[clap(short, long, help = "Optional path to save the recovered keys file")]
+    pub file: Option<String>,
+    #[clap(short, long, conflicts_with = "file", help = "Save the keys in the wallet under this label")]
+    pub label: Option<String>,
+    #[clap(long, help = "Store the private key unencrypted (not recommended)")]
+    pub plaintext: bool,
+    #[clap(flatten)]
+    pub derivation: DerivationArgs,
+}
+
+/// Selects which accounts are derived along m/44'/501'/<index>'/0'.
+#[derive(Parser)]
+pub struct DerivationArgs {
+    #[clap(long, help = "Prompt for an optional BIP39 passphrase")]
+    pub seed_passphrase: bool,
+    #[clap(long, default_value_t = 0, help = "First account index to derive")]
+    pub index: u32,
+    #[clap(
+        long,
+        default_value_t = 1,
+        value_parser = clap::value_parser!(u32).range(1..),
+        help = "Number of consecutive accounts to derive"
+    )]
+    pub count: u32,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum WordCount {
+    #[value(name = "12")]
+    Twelve,
+    #[value(name = "24")]
+    TwentyFour,
 }
 
 #[derive(Parser)]
 pub struct FetchCommand {
-    #[clap(short, long, help = "The public key of the account to fetch.")]
+    #[clap(short, long, help = "The public key of the account to inspect.")]
     pub wallet: String,
+    #[clap(long, default_value_t = 256, help = "Bytes of undecoded data shown as a hexdump")]
+    pub dump_limit: usize,
+    #[clap(long, help = "Address of the s4 native vault program, to decode its accounts")]
+    pub program_id: Option<String>,
 }
 
 #[derive(Parser)]
 pub struct FundCommand {
     #[clap(short, long, help = "The public key of the account to receive funds.")]
     pub wallet: String,
-    #[clap(short, long, help = "The amount of SOL to request.")]
-    pub amount: u64,
+    #[clap(long, help = "The amount of SOL to request, e.g. 1.5")]
+    pub amount: String,
+    #[clap(long, help = "Read the amount as lamports instead of SOL")]
+    pub lamports: bool,
 }
 
 #[derive(Parser)]
@@ -65,13 +319,75 @@ pub struct SendCommand {
     #[clap(
         short,
         long,
-        help = "The public key of the sender account. Defaults to the account file."
+        help = "Key file path or wallet label of the sender. Defaults to the account file."
     )]
     pub from: Option<String>,
     #[clap(short, long, help = "The public key of the recipient account.")]
     pub to: String,
-    #[clap(short, long, help = "The amount of SOL to send.")]
-    pub amount: f64,
+    #[clap(
+        long,
+        help = "The amount of SOL to send, e.g. 0.3, or ALL for the balance minus fees and rent"
+    )]
+    pub amount: String,
+    #[clap(long, help = "Read the amount as lamports instead of SOL")]
+    pub lamports: bool,
+    #[clap(flatten)]
+    pub offline: OfflineArgs,
+}
+
+/// Options for signing a transaction without submitting it.
+#[derive(Args, Debug)]
+pub struct OfflineArgs {
+    #[clap(long, help = "Sign the transaction and print it instead of submitting it")]
+    pub sign_only: bool,
+    #[clap(
+        long,
+        requires = "sign_only",
+        help = "Blockhash, or durable nonce value with --nonce, to sign with [default: fetched]"
+    )]
+    pub blockhash: Option<String>,
+    #[clap(
+        long,
+        requires = "sign_only",
+        help = "Public key of the fee payer, if it signs later with `sign`"
+    )]
+    pub fee_payer: Option<String>,
+    #[clap(flatten)]
+    pub output: SignedTransactionArgs,
+}
+
+/// Where and how a signed transaction is written.
+#[derive(Args, Debug)]
+pub struct SignedTransactionArgs {
+    #[clap(long, value_enum, default_value = "base64", help = "Encoding of the signed transaction")]
+    pub encoding: TransactionEncoding,
+    #[clap(long, help = "Write the signed transaction to this file instead of printing it")]
+    pub transaction_file: Option<String>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum TransactionEncoding {
+    Base58,
+    Base64,
+}
+
+#[derive(Parser)]
+pub struct BroadcastCommand {
+    #[clap(help = "Signed transaction, base58 or base64, or a file containing one")]
+    pub transaction: String,
+}
+
+#[derive(Parser)]
+pub struct SignCommand {
+    #[clap(help = "Partially signed transaction, base58 or base64, or a file containing one")]
+    pub transaction: String,
+    #[clap(
+        long,
+        help = "Key file path or wallet label of the co-signer. Defaults to the account file."
+    )]
+    pub from: Option<String>,
+    #[clap(flatten)]
+    pub output: SignedTransactionArgs,
 }
 
 #[derive(Parser)]
@@ -86,15 +402,779 @@ pub struct ListCommand {
     pub file: Option<String>,
 }
 
+#[derive(Parser)]
+pub struct UseCommand {
+    #[clap(help = "Wallet label to use as the default signer")]
+    pub label: String,
+}
+
 #[derive(Parser)]
 pub struct UpdateSettingsCommand {
-    #[clap(short, long, help = "Endpoint to set (local, dev, test)")]
+    #[clap(short, long, help = "Endpoint to set in the selected profile")]
     pub endpoint: Endpoint,
 }
 
-#[derive(Parser, ValueEnum, Clone, Debug)]
+#[derive(Parser)]
+pub struct ConfigCommand {
+    #[clap(subcommand)]
+    pub operation: ConfigOperations,
+}
+
+#[derive(Subcommand)]
+pub enum ConfigOperations {
+    /// Show the selected profile, or one of its settings
+    Get(ConfigGetCommand),
+    /// Change a setting of the selected profile, creating the profile if needed
+    Set(ConfigSetCommand),
+    /// List every profile
+    List,
+    /// Make a profile the active one
+    Use(ConfigUseCommand),
+}
+
+#[derive(Parser)]
+pub struct ConfigGetCommand {
+    #[clap(value_enum, help = "Setting to show")]
+    pub key: Option<ConfigKey>,
+}
+
+#[derive(Parser)]
+pub struct ConfigSetCommand {
+    #[clap(value_enum, help = "Setting to change")]
+    pub key: ConfigKey,
+    #[clap(help = "New value; omit it to clear an optional setting")]
+    pub value: Option<String>,
+}
+
+#[derive(Parser)]
+pub struct ConfigUseCommand {
+    #[clap(help = "Profile to activate")]
+    pub name: String,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum ConfigKey {
+    /// URL or moniker: local, devnet, testnet, mainnet-beta
+    RpcUrl,
+    WebsocketUrl,
+    Commitment,
+    Keypair,
+    PriorityFee,
+}
+
+#[derive(Parser)]
+pub struct ChangePasswordCommand {
+    #[clap(short, long, help = "Encrypted key file path or wallet label. Defaults to the account file.")]
+    pub file: Option<String>,
+}
+
+#[derive(Parser)]
+pub struct MigrateCommand {
+    #[clap(short, long, help = "Plaintext key file path or wallet label. Defaults to the account file.")]
+    pub file: Option<String>,
+}
+
+#[derive(Parser)]
+pub struct ImportCommand {
+    #[clap(
+        short,
+        long,
+        help = "File holding the key to import. Prompts for a secret or public key if omitted."
+    )]
+    pub input: Option<String>,
+    #[clap(long, value_enum, default_value = "auto", help = "Format of the imported key")]
+    pub format: KeyFormat,
+    #[clap(short, long, help = "Optional path to save the keys file")]
+    pub file: Option<String>,
+    #[clap(short, long, conflicts_with = "file", help = "Save the keys in the wallet under this label")]
+    pub label: Option<String>,
+    #[clap(long, help = "Store the private key unencrypted (not recommended)")]
+    pub plaintext: bool,
+}
+
+#[derive(Parser)]
+pub struct ExportCommand {
+    #[clap(short, long, help = "Key file path or wallet label to export. Defaults to the account file.")]
+    pub file: Option<String>,
+    #[clap(long, value_enum, default_value = "keygen", help = "Format to export to")]
+    pub format: KeyFormat,
+    #[clap(short, long, help = "Write the export to this file instead of stdout")]
+    pub output_file: Option<String>,
+    #[clap(long, help = "Store the private key unencrypted when exporting to the scw format")]
+    pub plaintext: bool,
+    #[clap(long, requires = "output_file", help = "Overwrite an existing --output-file")]
+    pub force: bool,
+}
+
+/// Key formats understood by `import` and `export`.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum KeyFormat {
+    /// Detect the format from the input
+    Auto,
+    /// This tool's JSON key file, plaintext or encrypted
+    Scw,
+    /// solana-keygen JSON array of the 64 secret key bytes
+    Keygen,
+    /// Base58 secret key string, as exported by Phantom
+    Base58,
+    /// Public key only, for watch-only accounts
+    Watch,
+}
+
+#[derive(Parser)]
+pub struct TokenCommand {
+    #[clap(
+        long,
+        global = true,
+        help = "Key file path or wallet label of the signer. Defaults to the account file."
+    )]
+    pub from: Option<String>,
+
+    #[clap(subcommand)]
+    pub operation: TokenOperations,
+}
+
+#[derive(Subcommand)]
+pub enum TokenOperations {
+    CreateMint(CreateMintCommand),
+    Mint(MintTokensCommand),
+    Transfer(TransferTokensCommand),
+    Burn(BurnTokensCommand),
+    Approve(ApproveCommand),
+    Revoke(RevokeCommand),
+    Close(CloseTokenAccountCommand),
+    Balance(TokenBalanceCommand),
+    Info(TokenInfoCommand),
+}
+
+#[derive(Parser)]
+pub struct CreateMintCommand {
+    #[clap(long, default_value_t = 9, help = "Number of decimals of the new mint")]
+    pub decimals: u8,
+    #[clap(long, help = "Mint, freeze and extension authority. Defaults to the signer.")]
+    pub authority: Option<String>,
+    #[clap(
+        long,
+        value_enum,
+        help = "Token program that owns the mint. Defaults to token, or token-2022 with extensions."
+    )]
+    pub program: Option<TokenProgram>,
+    #[clap(long, help = "Enable transfer fees of this many basis points")]
+    pub transfer_fee_basis_points: Option<u16>,
+    #[clap(
+        long,
+        requires = "transfer_fee_basis_points",
+        help = "Maximum transfer fee in tokens. Defaults to no maximum."
+    )]
+    pub max_transfer_fee: Option<String>,
+    #[clap(long, help = "Enable the metadata pointer extension, pointing at the mint itself")]
+    pub metadata_pointer: bool,
+    #[clap(long, help = "Enable the metadata pointer extension, pointing at this account")]
+    pub metadata_address: Option<String>,
+    #[clap(long, allow_negative_numbers = true, help = "Enable interest in basis points per year")]
+    pub interest_rate: Option<i16>,
+    #[clap(long, help = "Make tokens of this mint non-transferable")]
+    pub non_transferable: bool,
+}
+
+impl CreateMintCommand {
+    pub fn uses_extensions(&self) -> bool {
+        self.transfer_fee_basis_points.is_some() ||
+            self.metadata_pointer ||
+            self.metadata_address.is_some() ||
+            self.interest_rate.is_some() ||
+            self.non_transferable
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum TokenProgram {
+    Token,
+    #[value(name = "token-2022")]
+    Token2022,
+}
+
+#[derive(Parser)]
+pub struct MintTokensCommand {
+    #[clap(help = "The mint address")]
+    pub mint: String,
+    #[clap(help = "The amount of tokens to mint, e.g. 1.5")]
+    pub amount: String,
+    #[clap(long, help = "Owner of the receiving token account. Defaults to the signer.")]
+    pub to: Option<String>,
+}
+
+#[derive(Parser)]
+pub struct TransferTokensCommand {
+    #[clap(help = "The mint address")]
+    pub mint: String,
+    #[clap(help = "The public key of the recipient wallet")]
+    pub to: String,
+    #[clap(help = "The amount of tokens to send, e.g. 1.5, or ALL")]
+    pub amount: String,
+}
+
+#[derive(Parser)]
+pub struct BurnTokensCommand {
+    #[clap(help = "The mint address")]
+    pub mint: String,
+    #[clap(help = "The amount of tokens to burn, e.g. 1.5, or ALL")]
+    pub amount: String,
+}
+
+#[derive(Parser)]
+pub struct ApproveCommand {
+    #[clap(help = "The mint address")]
+    pub mint: String,
+    #[clap(help = "The public key allowed to spend the tokens")]
+    pub delegate: String,
+    #[clap(help = "The amount of tokens the delegate may spend, e.g. 1.5")]
+    pub amount: String,
+}
+
+#[derive(Parser)]
+pub struct RevokeCommand {
+    #[clap(help = "The mint address")]
+    pub mint: String,
+}
+
+#[derive(Parser)]
+pub struct CloseTokenAccountCommand {
+    #[clap(help = "The mint address")]
+    pub mint: String,
+    #[clap(long, help = "Wallet that receives any remaining tokens before closing")]
+    pub drain_to: Option<String>,
+}
+
+#[derive(Parser)]
+pub struct TokenInfoCommand {
+    #[clap(help = "Address of a mint or token account")]
+    pub address: String,
+}
+
+#[derive(Parser)]
+pub struct TokenBalanceCommand {
+    #[clap(help = "The mint address")]
+    pub mint: String,
+    #[clap(long, help = "Owner of the token account. Defaults to the signer.")]
+    pub owner: Option<String>,
+}
+
+#[derive(Parser)]
+pub struct HistoryCommand {
+    #[clap(help = "Address, key file or wallet label. Defaults to the account file.")]
+    pub wallet: Option<String>,
+    #[clap(long, help = "Only show transactions older than this signature")]
+    pub before: Option<String>,
+    #[clap(long, help = "Only show transactions newer than this signature")]
+    pub until: Option<String>,
+    #[clap(long, default_value_t = 20, help = "Maximum number of transactions to show")]
+    pub limit: usize,
+    #[clap(long, help = "Also write the history as CSV, one row per balance change")]
+    pub csv: Option<String>,
+    #[clap(long, help = "Also write the history as JSON")]
+    pub json: Option<String>,
+}
+
+#[derive(Parser)]
+pub struct NonceCommand {
+    #[clap(
+        long,
+        global = true,
+        help = "Key file path or wallet label of the payer and authority [default: account file]"
+    )]
+    pub from: Option<String>,
+
+    #[clap(subcommand)]
+    pub operation: NonceOperations,
+}
+
+#[derive(Subcommand)]
+pub enum NonceOperations {
+    Create(CreateNonceCommand),
+    Show(NonceAccountCommand),
+    Advance(NonceAccountCommand),
+    Withdraw(WithdrawNonceCommand),
+    Authorize(AuthorizeNonceCommand),
+}
+
+#[derive(Parser)]
+pub struct CreateNonceCommand {
+    #[clap(long, help = "Authority of the new nonce account. Defaults to the signer.")]
+    pub authority: Option<String>,
+    #[clap(long, help = "SOL to fund the account with. Defaults to the rent-exempt minimum.")]
+    pub amount: Option<String>,
+    #[clap(long, requires = "amount", help = "Read the amount as lamports instead of SOL")]
+    pub lamports: bool,
+}
+
+#[derive(Parser)]
+pub struct NonceAccountCommand {
+    #[clap(help = "The nonce account address")]
+    pub nonce: String,
+}
+
+#[derive(Parser)]
+pub struct WithdrawNonceCommand {
+    #[clap(help = "The nonce account address")]
+    pub nonce: String,
+    #[clap(help = "Recipient of the withdrawn SOL")]
+    pub to: String,
+    #[clap(help = "Amount of SOL to withdraw")]
+    pub amount: String,
+    #[clap(long, help = "Read the amount as lamports instead of SOL")]
+    pub lamports: bool,
+}
+
+#[derive(Parser)]
+pub struct AuthorizeNonceCommand {
+    #[clap(help = "The nonce account address")]
+    pub nonce: String,
+    #[clap(help = "Public key of the new nonce authority")]
+    pub new_authority: String,
+}
+
+#[derive(Parser)]
+pub struct BatchSendCommand {
+    #[clap(help = "CSV file of recipient,amount[,mint] rows; no mint means SOL")]
+    pub file: String,
+    #[clap(
+        long,
+        help = "Key file path or wallet label of the sender. Defaults to the account file."
+    )]
+    pub from: Option<String>,
+    #[clap(long, help = "Result ledger used to resume a run [default: <FILE>.ledger.json]")]
+    pub ledger: Option<String>,
+    #[clap(long, default_value_t = 4, help = "Number of transactions sent at once")]
+    pub concurrency: usize,
+    #[clap(long, help = "Validate the file and show the batches without sending")]
+    pub dry_run: bool,
+}
+
+#[derive(Parser)]
+pub struct VaultCommand {
+    #[clap(
+        long,
+        global = true,
+        help = "Key file path or wallet label of the signer. Defaults to the account file."
+    )]
+    pub from: Option<String>,
+    #[clap(
+        long,
+        global = true,
+        default_value = "AJ711G562a3nm2Dg6kt9YtGWVwpbprcuSi37CKPwhWqL",
+        help = "Address the vault program is deployed at"
+    )]
+    pub program_id: String,
+
+    #[clap(subcommand)]
+    pub operation: VaultOperations,
+}
+
+#[derive(Subcommand)]
+pub enum VaultOperations {
+    Create(CreateVaultCommand),
+    Deposit(VaultTransferCommand),
+    Withdraw(VaultTransferCommand),
+    Close(CloseVaultCommand),
+    Show(ShowVaultCommand),
+}
+
+#[derive(Parser)]
+pub struct CreateVaultCommand {
+    #[clap(help = "Name of the vault, at most 32 bytes")]
+    pub name: String,
+}
+
+#[derive(Parser)]
+pub struct VaultTransferCommand {
+    #[clap(help = "Name of the vault")]
+    pub name: String,
+    #[clap(help = "The amount of tokens, e.g. 1.5, or ALL")]
+    pub amount: String,
+    #[clap(long, help = "Mint of the tokens held by the vault")]
+    pub mint: String,
+}
+
+#[derive(Parser)]
+pub struct CloseVaultCommand {
+    #[clap(help = "Name of the vault")]
+    pub name: String,
+    #[clap(long, help = "Mint of the tokens held by the vault")]
+    pub mint: String,
+}
+
+#[derive(Parser)]
+pub struct ShowVaultCommand {
+    #[clap(help = "Name of the vault")]
+    pub name: String,
+    #[clap(long, help = "Depositor whose user account is shown. Defaults to the signer.")]
+    pub user: Option<String>,
+}
+
+#[derive(Parser)]
+pub struct NativeVaultCommand {
+    #[clap(
+        long,
+        global = true,
+        help = "Key file path or wallet label of the payer. Defaults to the account file."
+    )]
+    pub from: Option<String>,
+    #[clap(long, global = true, help = "Address the native vault program is deployed at")]
+    pub program_id: Option<String>,
+
+    #[clap(subcommand)]
+    pub operation: NativeVaultOperations,
+}
+
+#[derive(Subcommand)]
+pub enum NativeVaultOperations {
+    Initialize,
+    Deposit(NativeDepositCommand),
+    Withdraw(NativeWithdrawCommand),
+    Show(NativeVaultAccountCommand),
+}
+
+#[derive(Parser)]
+pub struct NativeDepositCommand {
+    #[clap(help = "The deposit account address")]
+    pub account: String,
+    #[clap(help = "Amount of SOL to deposit")]
+    pub amount: String,
+    #[clap(long, help = "Read the amount as lamports instead of SOL")]
+    pub lamports: bool,
+}
+
+#[derive(Parser)]
+pub struct NativeWithdrawCommand {
+    #[clap(help = "The deposit account address")]
+    pub account: String,
+    #[clap(long, help = "Recipient of the withdrawn SOL. Defaults to the payer.")]
+    pub to: Option<String>,
+}
+
+#[derive(Parser)]
+pub struct NativeVaultAccountCommand {
+    #[clap(help = "The deposit account address")]
+    pub account: String,
+}
+
+#[derive(Parser)]
+pub struct PayCommand {
+    #[clap(subcommand)]
+    pub operation: PayOperations,
+}
+
+#[derive(Subcommand)]
+pub enum PayOperations {
+    Request(PayRequestCommand),
+    Parse(PayParseCommand),
+    Verify(PayVerifyCommand),
+    Serve(PayServeCommand),
+}
+
+#[derive(Parser)]
+pub struct PayRequestCommand {
+    #[clap(long, help = "Wallet that receives the payment. Defaults to the account file.")]
+    pub recipient: Option<String>,
+    #[clap(long, help = "Amount of SOL, or of tokens with --spl-token, e.g. 0.1")]
+    pub amount: Option<String>,
+    #[clap(long, help = "Mint of the SPL token to be paid instead of SOL")]
+    pub spl_token: Option<String>,
+    #[clap(
+        long = "reference",
+        help = "Reference key to find the payment by; repeatable [default: a new random key]"
+    )]
+    pub references: Vec<String>,
+    #[clap(long, help = "Merchant or app the payment goes to, shown by the wallet")]
+    pub label: Option<String>,
+    #[clap(long, help = "Description of the payment, shown by the wallet")]
+    pub message: Option<String>,
+    #[clap(long, help = "Memo included in the payment transaction; it is public")]
+    pub memo: Option<String>,
+    #[clap(long, help = "Print the URL without a QR code")]
+    pub no_qr: bool,
+}
+
+#[derive(Parser)]
+pub struct PayVerifyCommand {
+    #[clap(long, help = "Reference key of the transfer request")]
+    pub reference: String,
+    #[clap(long, help = "Wallet that should have received the payment")]
+    pub recipient: String,
+    #[clap(long, help = "Amount of SOL, or of tokens with --spl-token, that should have arrived")]
+    pub amount: String,
+    #[clap(long, help = "Mint of the SPL token that should have been paid instead of SOL")]
+    pub spl_token: Option<String>,
+    #[clap(long, help = "Memo the payment transaction should carry")]
+    pub memo: Option<String>,
+}
+
+#[derive(Parser)]
+pub struct PayParseCommand {
+    #[clap(help = "A solana: transfer request URL")]
+    pub url: String,
+}
+
+#[derive(Parser)]
+pub struct PayServeCommand {
+    #[clap(long, default_value = "127.0.0.1:8080", help = "Address to listen on")]
+    pub bind: String,
+    #[clap(long, help = "URL wallets reach the server at [default: http://<bind>]")]
+    pub public_url: Option<String>,
+    #[clap(long, help = "Wallet that receives the payments. Defaults to the account file.")]
+    pub recipient: Option<String>,
+    #[clap(
+        long,
+        default_value = "0.1",
+        help = "Price of one item, in SOL or in --spl-token tokens"
+    )]
+    pub price: String,
+    #[clap(long, help = "Mint of the SPL token to be paid instead of SOL")]
+    pub spl_token: Option<String>,
+    #[clap(long, default_value = "shirt", help = "What is sold, used in order descriptions")]
+    pub item: String,
+    #[clap(long, default_value = "Solana Shirts", help = "Store name shown by the wallet")]
+    pub label: String,
+    #[clap(long, help = "Icon shown by the wallet [default: a built-in <public-url>/icon.svg]")]
+    pub icon: Option<String>,
+    #[clap(
+        long,
+        default_value = "T-shirt purchase",
+        help = "Memo included in payment transactions; it is public"
+    )]
+    pub memo: String,
+    #[clap(long, help = "Leave the memo out of payment transactions")]
+    pub no_memo: bool,
+    #[clap(
+        long,
+        help = "Key file path or wallet label of the fee payer, which signs every payment \
+                transaction first. Defaults to the account file."
+    )]
+    pub fee_payer: Option<String>,
+    #[clap(long, help = "SQLite file to keep orders in [default: kept in memory]")]
+    pub database: Option<String>,
+}
+
+#[derive(Parser)]
+pub struct CnftCommand {
+    #[clap(
+        long,
+        global = true,
+        help = "Key file path or wallet label of the signer. Defaults to the account file."
+    )]
+    pub from: Option<String>,
+    #[clap(
+        long,
+        global = true,
+        default_value = "merkleTree.json",
+        help = "File the tree address and configuration are kept in"
+    )]
+    pub tree_file: String,
+
+    #[clap(subcommand)]
+    pub operation: CnftOperations,
+}
+
+#[derive(Subcommand)]
+pub enum CnftOperations {
+    CreateTree(CreateTreeCommand),
+    Mint(CnftMintCommand),
+    Airdrop(CnftAirdropCommand),
+    Show,
+    Replay,
+    Proof(CnftProofCommand),
+    Verify(CnftVerifyCommand),
+}
+
+#[derive(Parser)]
+pub struct CreateTreeCommand {
+    #[clap(long, default_value_t = 14, help = "Depth of the tree; it holds 2^depth leaves")]
+    pub max_depth: u32,
+    #[clap(
+        long,
+        default_value_t = 64,
+        help = "Number of changes the tree accepts per slot against the same root"
+    )]
+    pub max_buffer_size: u32,
+    #[clap(
+        long,
+        default_value_t = 0,
+        help = "Levels of the tree kept on chain, shortening the proofs transfers need"
+    )]
+    pub canopy_depth: u32,
+    #[clap(long, help = "Let anyone mint into the tree, not just its creator")]
+    pub public: bool,
+    #[clap(long, help = "Create a new tree even if the tree file already names one")]
+    pub force: bool,
+}
+
+#[derive(Parser)]
+pub struct CnftMintCommand {
+    #[clap(help = "Wallet that receives the compressed NFT")]
+    pub owner: String,
+    #[clap(long, help = "Delegate of the new leaf [default: the owner]")]
+    pub delegate: Option<String>,
+    #[clap(flatten)]
+    pub metadata: NftMetadataArgs,
+}
+
+/// Metadata of the compressed NFTs `cnft mint` and `cnft airdrop` create.
+#[derive(Args, Debug)]
+pub struct NftMetadataArgs {
+    #[clap(long, help = "Name of the NFT, at most 32 bytes")]
+    pub name: String,
+    #[clap(long, help = "URI of the off-chain metadata JSON, at most 200 bytes")]
+    pub uri: String,
+    #[clap(long, default_value = "", help = "Symbol of the NFT, at most 10 bytes")]
+    pub symbol: String,
+    #[clap(
+        long,
+        default_value_t = 500,
+        help = "Royalty on secondary sales, in basis points (500 is 5%)"
+    )]
+    pub seller_fee_basis_points: u16,
+    #[clap(
+        long = "creator",
+        help = "Creator as ADDRESS:SHARE, shares summing to 100; repeatable [default: the signer]"
+    )]
+    pub creators: Vec<String>,
+    #[clap(long, help = "Collection the NFT claims, unverified")]
+    pub collection: Option<String>,
+    #[clap(long, help = "Make the metadata immutable")]
+    pub immutable: bool,
+}
+
+#[derive(Parser)]
+pub struct CnftAirdropCommand {
+    #[clap(help = "File of recipient addresses, one per line")]
+    pub file: String,
+    #[clap(flatten)]
+    pub metadata: NftMetadataArgs,
+    #[clap(long, help = "Progress file used to resume a run [default: <FILE>.airdrop.json]")]
+    pub state: Option<String>,
+    #[clap(long, default_value_t = 4, help = "Number of mints sent at once")]
+    pub concurrency: usize,
+    #[clap(long, help = "Validate the file and check the tree's capacity without minting")]
+    pub dry_run: bool,
+}
+
+#[derive(Parser)]
+pub struct CnftProofCommand {
+    #[clap(help = "Index of the leaf to prove")]
+    pub leaf_index: u32,
+}
+
+/// Checks a proof from elsewhere, such as an indexer's `getAssetProof`, against the tree account.
+/// Nodes are base58.
+#[derive(Parser)]
+pub struct CnftVerifyCommand {
+    #[clap(help = "Index of the leaf the proof is for")]
+    pub leaf_index: u32,
+    #[clap(long, help = "Root the proof was made against")]
+    pub root: String,
+    #[clap(
+        long = "proof",
+        help = "Proof node, from the leaf's sibling upwards; repeatable, canopy nodes optional"
+    )]
+    pub proof: Vec<String>,
+    #[clap(long, help = "Hash of the leaf [default: computed from --owner and the hashes]")]
+    pub leaf: Option<String>,
+    #[clap(long, conflicts_with = "leaf", help = "Owner of the leaf")]
+    pub owner: Option<String>,
+    #[clap(long, conflicts_with = "leaf", help = "Delegate of the leaf [default: the owner]")]
+    pub delegate: Option<String>,
+    #[clap(long, conflicts_with = "leaf", help = "Data hash of the leaf")]
+    pub data_hash: Option<String>,
+    #[clap(long, conflicts_with = "leaf", help = "Creator hash of the leaf")]
+    pub creator_hash: Option<String>,
+}
+
+/// A Bubblegum tree created by `cnft create-tree`. `publicKey` alone is the s8 script's
+/// `merkleTree.json`; the rest is read from the chain when missing.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TreeFile {
+    pub public_key: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_depth: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_buffer_size: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub canopy_depth: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub creator: Option<String>,
+    /// Transaction that created the tree.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+}
+
+/// Progress of a `cnft airdrop`: one entry per recipient, so a rerun only mints what is missing.
+#[derive(Serialize, Deserialize, Default)]
+pub struct AirdropState {
+    pub tree: String,
+    pub recipients: BTreeMap<String, AirdropEntry>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AirdropEntry {
+    pub status: MintStatus,
+    pub signature: Option<String>,
+    /// Blockhash or durable nonce value the transaction was signed with.
+    pub blockhash: Option<String>,
+    pub nonce: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub leaf_index: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub asset_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum MintStatus {
+    /// Submitted, but not known to have landed.
+    Pending,
+    Minted,
+    Failed,
+}
+
+/// Outcome of every payment of a `batch-send` file, so a rerun skips what was already paid.
+#[derive(Serialize, Deserialize, Default)]
+pub struct BatchLedger {
+    pub sender: String,
+    pub payments: BTreeMap<String, LedgerEntry>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LedgerEntry {
+    pub status: PaymentStatus,
+    pub signature: Option<String>,
+    /// Blockhash or durable nonce value the transaction was signed with.
+    pub blockhash: Option<String>,
+    pub nonce: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PaymentStatus {
+    /// Submitted, but not known to have landed.
+    Pending,
+    Paid,
+    Failed,
+}
+
+#[derive(ValueEnum, Clone, Debug)]
 pub enum Endpoint {
+    #[value(alias = "localhost")]
     Local,
+    #[value(alias = "devnet")]
     Dev,
+    #[value(alias = "testnet")]
     Test,
+    #[value(alias = "mainnet")]
+    MainnetBeta,
 }
@@ -0,0 +1,1128 @@
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{
+    instruction::Instruction,
+    pubkey::Pubkey,
+    signature::Keypair,
+    signer::Signer,
+    system_instruction,
+};
+use spl_associated_token_account::{
+    get_associated_token_address_with_program_id,
+    instruction::create_associated_token_account_idempotent,
+};
+use spl_token_2022::{
+    extension::{
+        interest_bearing_mint::{ self, InterestBearingConfig },
+        metadata_pointer::{ self, MetadataPointer },
+        non_transferable::NonTransferable,
+        transfer_fee::{
+            instruction as transfer_fee_instruction,
+            TransferFeeAmount,
+            TransferFeeConfig,
+        },
+        BaseStateWithExtensions,
+        ExtensionType,
+        StateWithExtensions,
+    },
+    instruction as token_instruction,
+    state::{ Account as TokenAccount, Mint },
+};
+use serde_json::{ json, Value };
+use std::{ error::Error, str::FromStr };
+
+use crate::{
+    amount::Amount,
+    keystore::{ read_keys, read_public_key },
+    output::emit,
+    state::*,
+    transaction::send_instructions,
+    wallet::Wallet,
+};
+
+pub fn token_command(
+    rpc_client: &RpcClient,
+    options: &TransactionArgs,
+    wallet: &Wallet,
+    cmd: &TokenCommand
+) -> Result<(), Box<dyn Error>> {
+    let signer_path = wallet.resolve(cmd.from.as_deref())?;
+    let signer = || read_keys(&signer_path);
+    match &cmd.operation {
+        TokenOperations::CreateMint(cmd) => create_mint(rpc_client, options, &signer()?, cmd),
+        TokenOperations::Mint(cmd) => mint_tokens(rpc_client, options, &signer()?, cmd),
+        TokenOperations::Transfer(cmd) => transfer_tokens(rpc_client, options, &signer()?, cmd),
+        TokenOperations::Burn(cmd) => burn_tokens(rpc_client, options, &signer()?, cmd),
+        TokenOperations::Approve(cmd) => approve_delegate(rpc_client, options, &signer()?, cmd),
+        TokenOperations::Revoke(cmd) => revoke_delegate(rpc_client, options, &signer()?, cmd),
+        TokenOperations::Close(cmd) => close_token_account(rpc_client, options, &signer()?, cmd),
+        TokenOperations::Balance(cmd) => token_balance(rpc_client, &signer_path, cmd),
+        TokenOperations::Info(cmd) => token_info(rpc_client, cmd),
+    }
+}
+
+impl TokenProgram {
+    pub fn id(&self) -> Pubkey {
+        match self {
+            TokenProgram::Token => spl_token::id(),
+            TokenProgram::Token2022 => spl_token_2022::id(),
+        }
+    }
+}
+
+/// Size of an associated token account of a mint with `mint_extensions`. Token-2022 accounts
+/// carry the extensions the mint requires, and associated ones are immutably owned.
+pub fn associated_account_len(
+    program_id: &Pubkey,
+    mint_extensions: &[ExtensionType]
+) -> Result<usize, Box<dyn Error>> {
+    let mut extensions = Vec::new();
+    if *program_id == spl_token_2022::id() {
+        extensions = ExtensionType::get_required_init_account_extensions(mint_extensions);
+        extensions.push(ExtensionType::ImmutableOwner);
+    }
+    Ok(ExtensionType::try_calculate_account_len::<TokenAccount>(&extensions)?)
+}
+
+pub fn program_name(program_id: &Pubkey) -> &'static str {
+    if *program_id == spl_token_2022::id() { "Token-2022" } else { "Token" }
+}
+
+pub fn is_token_program(program_id: &Pubkey) -> bool {
+    *program_id == spl_token::id() || *program_id == spl_token_2022::id()
+}
+
+/// Decimals, owning program and transfer fee of a mint, needed to build checked instructions.
+pub struct MintInfo {
+    pub address: Pubkey,
+    pub program_id: Pubkey,
+    pub decimals: u8,
+    pub transfer_fee: Option<TransferFeeConfig>,
+    pub non_transferable: bool,
+    /// Size of a new associated token account of the mint, which its rent is paid for.
+    pub account_len: usize,
+}
+
+impl MintInfo {
+    /// Fetches a Token or Token-2022 mint; instructions are routed to whichever program owns it.
+    pub fn fetch(rpc_client: &RpcClient, address: &Pubkey) -> Result<Self, Box<dyn Error>> {
+        let account = rpc_client.get_account(address)?;
+        if !is_token_program(&account.owner) {
+            return Err(format!("{} is not owned by a token program", address).into());
+        }
+        let mint = StateWithExtensions::<Mint>::unpack(&account.data)?;
+        Ok(Self {
+            address: *address,
+            program_id: account.owner,
+            decimals: mint.base.decimals,
+            transfer_fee: mint.get_extension::<TransferFeeConfig>().ok().copied(),
+            non_transferable: mint.get_extension::<NonTransferable>().is_ok(),
+            account_len: associated_account_len(&account.owner, &mint.get_extension_types()?)?,
+        })
+    }
+
+    /// Builds `transfer_checked`, or `transfer_checked_with_fee` with the fee due this epoch
+    /// when the mint charges transfer fees. Returns the instruction and the fee.
+    pub fn transfer(
+        &self,
+        rpc_client: &RpcClient,
+        source: &Pubkey,
+        destination: &Pubkey,
+        authority: &Pubkey,
+        amount: u64
+    ) -> Result<(Instruction, u64), Box<dyn Error>> {
+        // Only the transfer fee depends on the epoch.
+        let epoch = match self.transfer_fee {
+            Some(_) => rpc_client.get_epoch_info()?.epoch,
+            None => 0,
+        };
+        self.transfer_at_epoch(epoch, source, destination, authority, amount)
+    }
+
+    fn transfer_at_epoch(
+        &self,
+        epoch: u64,
+        source: &Pubkey,
+        destination: &Pubkey,
+        authority: &Pubkey,
+        amount: u64
+    ) -> Result<(Instruction, u64), Box<dyn Error>> {
+        if self.non_transferable {
+            return Err(format!("Tokens of mint {} are non-transferable", self.address).into());
+        }
+        match &self.transfer_fee {
+            Some(config) => {
+                let fee = config.calculate_epoch_fee(epoch, amount).ok_or("Transfer fee overflow")?;
+                let instruction = transfer_fee_instruction::transfer_checked_with_fee(
+                    &self.program_id,
+                    source,
+                    &self.address,
+                    destination,
+                    authority,
+                    &[],
+                    amount,
+                    self.decimals,
+                    fee
+                )?;
+                Ok((instruction, fee))
+            }
+            None => {
+                let instruction = token_instruction::transfer_checked(
+                    &self.program_id,
+                    source,
+                    &self.address,
+                    destination,
+                    authority,
+                    &[],
+                    amount,
+                    self.decimals
+                )?;
+                Ok((instruction, 0))
+            }
+        }
+    }
+
+    pub fn associated_account(&self, owner: &Pubkey) -> Pubkey {
+        get_associated_token_address_with_program_id(owner, &self.address, &self.program_id)
+    }
+
+    /// Creates `owner`'s associated token account if it does not exist yet.
+    pub fn create_associated_account(&self, payer: &Pubkey, owner: &Pubkey) -> Instruction {
+        create_associated_token_account_idempotent(payer, owner, &self.address, &self.program_id)
+    }
+
+    pub fn parse_amount(&self, amount: &str) -> Result<u64, Box<dyn Error>> {
+        parse_token_amount(amount, self.decimals)
+    }
+
+    /// Parses an amount to move out of `account`; `ALL` is its whole balance.
+    pub fn parse_amount_or_all(
+        &self,
+        rpc_client: &RpcClient,
+        amount: &str,
+        account: &Pubkey
+    ) -> Result<u64, Box<dyn Error>> {
+        match Amount::parse(amount, self.decimals, false)? {
+            Amount::Exact(amount) => Ok(amount),
+            Amount::All => {
+                let balance = unpack_token_account(&rpc_client.get_account(account)?.data)?.amount;
+                if balance == 0 {
+                    return Err(format!("{} holds no tokens", account).into());
+                }
+                Ok(balance)
+            }
+        }
+    }
+
+    pub fn format_amount(&self, amount: u64) -> String {
+        format_token_amount(amount, self.decimals)
+    }
+}
+
+/// Program owning a new mint. Extensions only exist in Token-2022, so they select it unless
+/// `--program token` was asked for explicitly.
+fn mint_program(cmd: &CreateMintCommand) -> Result<TokenProgram, Box<dyn Error>> {
+    match (cmd.program, cmd.uses_extensions()) {
+        (Some(TokenProgram::Token), true) => {
+            Err("Mint extensions need --program token-2022, not --program token".into())
+        }
+        (Some(program), _) => Ok(program),
+        (None, true) => Ok(TokenProgram::Token2022),
+        (None, false) => Ok(TokenProgram::Token),
+    }
+}
+
+fn create_mint(
+    rpc_client: &RpcClient,
+    options: &TransactionArgs,
+    payer: &Keypair,
+    cmd: &CreateMintCommand
+) -> Result<(), Box<dyn Error>> {
+    let mint = Keypair::new();
+    let authority = match &cmd.authority {
+        Some(authority) => Pubkey::from_str(authority)?,
+        None => payer.pubkey(),
+    };
+
+    let mut extensions = Vec::new();
+    let mut extension_instructions = Vec::new();
+    let program_id = mint_program(cmd)?.id();
+    if let Some(basis_points) = cmd.transfer_fee_basis_points {
+        let maximum_fee = match &cmd.max_transfer_fee {
+            Some(max) => parse_token_amount(max, cmd.decimals)?,
+            None => u64::MAX,
+        };
+        extensions.push(ExtensionType::TransferFeeConfig);
+        extension_instructions.push(
+            transfer_fee_instruction::initialize_transfer_fee_config(
+                &program_id,
+                &mint.pubkey(),
+                Some(&authority),
+                Some(&authority),
+                basis_points,
+                maximum_fee
+            )?
+        );
+    }
+    if cmd.metadata_pointer || cmd.metadata_address.is_some() {
+        let metadata_address = match &cmd.metadata_address {
+            Some(address) => Pubkey::from_str(address)?,
+            None => mint.pubkey(),
+        };
+        extensions.push(ExtensionType::MetadataPointer);
+        extension_instructions.push(
+            metadata_pointer::instruction::initialize(
+                &program_id,
+                &mint.pubkey(),
+                Some(authority),
+                Some(metadata_address)
+            )?
+        );
+    }
+    if let Some(rate) = cmd.interest_rate {
+        extensions.push(ExtensionType::InterestBearingConfig);
+        extension_instructions.push(
+            interest_bearing_mint::instruction::initialize(
+                &program_id,
+                &mint.pubkey(),
+                Some(authority),
+                rate
+            )?
+        );
+    }
+    if cmd.non_transferable {
+        extensions.push(ExtensionType::NonTransferable);
+        extension_instructions.push(
+            token_instruction::initialize_non_transferable_mint(&program_id, &mint.pubkey())?
+        );
+    }
+
+    let space = ExtensionType::try_calculate_account_len::<Mint>(&extensions)?;
+    let lamports = rpc_client.get_minimum_balance_for_rent_exemption(space)?;
+
+    let mut instructions = vec![
+        system_instruction::create_account(
+            &payer.pubkey(),
+            &mint.pubkey(),
+            lamports,
+            space as u64,
+            &program_id
+        ),
+    ];
+    // Extensions must be initialized before the mint itself.
+    instructions.extend(extension_instructions);
+    instructions.push(
+        token_instruction::initialize_mint2(
+            &program_id,
+            &mint.pubkey(),
+            &authority,
+            Some(&authority),
+            cmd.decimals
+        )?
+    );
+
+    let confirmation = send_instructions(rpc_client, options, &instructions, payer, &[&mint])?;
+    let signature = confirmation.signature;
+    let extension_names: Vec<String> = extensions
+        .iter()
+        .map(|extension| format!("{:?}", extension))
+        .collect();
+    emit(
+        json!({
+            "mint": mint.pubkey().to_string(),
+            "program": program_id.to_string(),
+            "decimals": cmd.decimals,
+            "authority": authority.to_string(),
+            "extensions": extension_names,
+            "signature": signature.to_string(),
+        }),
+        || {
+            println!("Mint created: {}", mint.pubkey());
+            println!("Program: {}", program_name(&program_id));
+            println!("Decimals: {}", cmd.decimals);
+            for extension in &extension_names {
+                println!("Extension: {}", extension);
+            }
+            println!("Signature: {}", signature);
+        }
+    );
+    Ok(())
+}
+
+fn mint_tokens(
+    rpc_client: &RpcClient,
+    options: &TransactionArgs,
+    authority: &Keypair,
+    cmd: &MintTokensCommand
+) -> Result<(), Box<dyn Error>> {
+    let mint = MintInfo::fetch(rpc_client, &Pubkey::from_str(&cmd.mint)?)?;
+    let owner = match &cmd.to {
+        Some(owner) => Pubkey::from_str(owner)?,
+        None => authority.pubkey(),
+    };
+    let amount = mint.parse_amount(&cmd.amount)?;
+    let destination = mint.associated_account(&owner);
+
+    let instructions = [
+        mint.create_associated_account(&authority.pubkey(), &owner),
+        token_instruction::mint_to_checked(
+            &mint.program_id,
+            &mint.address,
+            &destination,
+            &authority.pubkey(),
+            &[],
+            amount,
+            mint.decimals
+        )?,
+    ];
+
+    let confirmation = send_instructions(rpc_client, options, &instructions, authority, &[])?;
+    let signature = confirmation.signature;
+    emit(
+        json!({
+            "mint": mint.address.to_string(),
+            "account": destination.to_string(),
+            "amount": mint.format_amount(amount),
+            "base_units": amount,
+            "signature": signature.to_string(),
+        }),
+        || {
+            println!("Minted {} tokens to {}", mint.format_amount(amount), destination);
+            println!("Signature: {}", signature);
+        }
+    );
+    Ok(())
+}
+
+fn transfer_tokens(
+    rpc_client: &RpcClient,
+    options: &TransactionArgs,
+    owner: &Keypair,
+    cmd: &TransferTokensCommand
+) -> Result<(), Box<dyn Error>> {
+    let mint = MintInfo::fetch(rpc_client, &Pubkey::from_str(&cmd.mint)?)?;
+    let recipient = Pubkey::from_str(&cmd.to)?;
+    let source = mint.associated_account(&owner.pubkey());
+    let destination = mint.associated_account(&recipient);
+    let amount = mint.parse_amount_or_all(rpc_client, &cmd.amount, &source)?;
+
+    let (transfer, fee) = mint.transfer(
+        rpc_client,
+        &source,
+        &destination,
+        &owner.pubkey(),
+        amount
+    )?;
+    let instructions = [mint.create_associated_account(&owner.pubkey(), &recipient), transfer];
+
+    let signature = send_instructions(rpc_client, options, &instructions, owner, &[])?.signature;
+    emit(
+        json!({
+            "mint": mint.address.to_string(),
+            "from": source.to_string(),
+            "to": destination.to_string(),
+            "amount": mint.format_amount(amount),
+            "base_units": amount,
+            "fee": mint.format_amount(fee),
+            "signature": signature.to_string(),
+        }),
+        || {
+            println!("Transferred {} tokens to {}", mint.format_amount(amount), destination);
+            if mint.transfer_fee.is_some() {
+                println!("Transfer fee withheld: {}", mint.format_amount(fee));
+            }
+            println!("Signature: {}", signature);
+        }
+    );
+    Ok(())
+}
+
+fn burn_tokens(
+    rpc_client: &RpcClient,
+    options: &TransactionArgs,
+    owner: &Keypair,
+    cmd: &BurnTokensCommand
+) -> Result<(), Box<dyn Error>> {
+    let mint = MintInfo::fetch(rpc_client, &Pubkey::from_str(&cmd.mint)?)?;
+    let account = mint.associated_account(&owner.pubkey());
+    let amount = mint.parse_amount_or_all(rpc_client, &cmd.amount, &account)?;
+
+    let instruction = token_instruction::burn_checked(
+        &mint.program_id,
+        &account,
+        &mint.address,
+        &owner.pubkey(),
+        &[],
+        amount,
+        mint.decimals
+    )?;
+
+    let signature = send_instructions(rpc_client, options, &[instruction], owner, &[])?.signature;
+    emit(
+        json!({
+            "mint": mint.address.to_string(),
+            "account": account.to_string(),
+            "amount": mint.format_amount(amount),
+            "base_units": amount,
+            "signature": signature.to_string(),
+        }),
+        || {
+            println!("Burned {} tokens from {}", mint.format_amount(amount), account);
+            println!("Signature: {}", signature);
+        }
+    );
+    Ok(())
+}
+
+fn approve_delegate(
+    rpc_client: &RpcClient,
+    options: &TransactionArgs,
+    owner: &Keypair,
+    cmd: &ApproveCommand
+) -> Result<(), Box<dyn Error>> {
+    let mint = MintInfo::fetch(rpc_client, &Pubkey::from_str(&cmd.mint)?)?;
+    let delegate = Pubkey::from_str(&cmd.delegate)?;
+    let amount = mint.parse_amount(&cmd.amount)?;
+    let account = mint.associated_account(&owner.pubkey());
+
+    let instruction = token_instruction::approve_checked(
+        &mint.program_id,
+        &account,
+        &mint.address,
+        &delegate,
+        &owner.pubkey(),
+        &[],
+        amount,
+        mint.decimals
+    )?;
+
+    let signature = send_instructions(rpc_client, options, &[instruction], owner, &[])?.signature;
+    emit(
+        json!({
+            "mint": mint.address.to_string(),
+            "account": account.to_string(),
+            "delegate": delegate.to_string(),
+            "amount": mint.format_amount(amount),
+            "base_units": amount,
+            "signature": signature.to_string(),
+        }),
+        || {
+            println!(
+                "Approved {} to spend {} tokens from {}",
+                delegate,
+                mint.format_amount(amount),
+                account
+            );
+            println!("Signature: {}", signature);
+        }
+    );
+    Ok(())
+}
+
+fn revoke_delegate(
+    rpc_client: &RpcClient,
+    options: &TransactionArgs,
+    owner: &Keypair,
+    cmd: &RevokeCommand
+) -> Result<(), Box<dyn Error>> {
+    let mint = MintInfo::fetch(rpc_client, &Pubkey::from_str(&cmd.mint)?)?;
+    let account = mint.associated_account(&owner.pubkey());
+
+    let instruction = token_instruction::revoke(
+        &mint.program_id,
+        &account,
+        &owner.pubkey(),
+        &[]
+    )?;
+
+    let signature = send_instructions(rpc_client, options, &[instruction], owner, &[])?.signature;
+    emit(
+        json!({
+            "mint": mint.address.to_string(),
+            "account": account.to_string(),
+            "signature": signature.to_string(),
+        }),
+        || {
+            println!("Delegate revoked for {}", account);
+            println!("Signature: {}", signature);
+        }
+    );
+    Ok(())
+}
+
+fn close_token_account(
+    rpc_client: &RpcClient,
+    options: &TransactionArgs,
+    owner: &Keypair,
+    cmd: &CloseTokenAccountCommand
+) -> Result<(), Box<dyn Error>> {
+    let mint = MintInfo::fetch(rpc_client, &Pubkey::from_str(&cmd.mint)?)?;
+    let account = mint.associated_account(&owner.pubkey());
+    let balance = closable_balance(&mint, &account, &rpc_client.get_account(&account)?.data)?;
+
+    let mut instructions = Vec::new();
+    if balance > 0 {
+        let recipient = match &cmd.drain_to {
+            Some(recipient) => Pubkey::from_str(recipient)?,
+            None => {
+                return Err(
+                    format!(
+                        "{} still holds {} tokens; pass --drain-to to transfer them first",
+                        account,
+                        mint.format_amount(balance)
+                    ).into()
+                );
+            }
+        };
+        let destination = mint.associated_account(&recipient);
+        instructions.push(mint.create_associated_account(&owner.pubkey(), &recipient));
+        let (transfer, _) = mint.transfer(
+            rpc_client,
+            &account,
+            &destination,
+            &owner.pubkey(),
+            balance
+        )?;
+        instructions.push(transfer);
+    }
+    instructions.push(
+        token_instruction::close_account(
+            &mint.program_id,
+            &account,
+            &owner.pubkey(),
+            &owner.pubkey(),
+            &[]
+        )?
+    );
+
+    let signature = send_instructions(rpc_client, options, &instructions, owner, &[])?.signature;
+    emit(
+        json!({
+            "mint": mint.address.to_string(),
+            "account": account.to_string(),
+            "drained": mint.format_amount(balance),
+            "signature": signature.to_string(),
+        }),
+        || {
+            println!("Token account closed: {}", account);
+            println!("Signature: {}", signature);
+        }
+    );
+    Ok(())
+}
+
+/// Balance of a token account about to be closed. The program refuses to close an account
+/// holding withheld transfer fees, and non-transferable tokens can only be burned.
+fn closable_balance(
+    mint: &MintInfo,
+    account: &Pubkey,
+    data: &[u8]
+) -> Result<u64, Box<dyn Error>> {
+    let state = StateWithExtensions::<TokenAccount>::unpack(data)?;
+    if let Ok(fees) = state.get_extension::<TransferFeeAmount>() {
+        let withheld = u64::from(fees.withheld_amount);
+        if withheld > 0 {
+            return Err(
+                format!(
+                    "{} holds {} tokens of withheld transfer fees; they must be harvested to the \
+                     mint before it can be closed",
+                    account,
+                    mint.format_amount(withheld)
+                ).into()
+            );
+        }
+    }
+    let balance = state.base.amount;
+    if balance > 0 && mint.non_transferable {
+        return Err(
+            format!(
+                "{} holds {} non-transferable tokens; burn them before closing it",
+                account,
+                mint.format_amount(balance)
+            ).into()
+        );
+    }
+    Ok(balance)
+}
+
+fn token_balance(
+    rpc_client: &RpcClient,
+    signer_path: &str,
+    cmd: &TokenBalanceCommand
+) -> Result<(), Box<dyn Error>> {
+    let mint = MintInfo::fetch(rpc_client, &Pubkey::from_str(&cmd.mint)?)?;
+    let owner = match &cmd.owner {
+        Some(owner) => Pubkey::from_str(owner)?,
+        None => read_public_key(signer_path)?,
+    };
+    let account = mint.associated_account(&owner);
+    // Only a missing account means a zero balance; RPC failures are reported.
+    let response = rpc_client.get_account_with_commitment(&account, rpc_client.commitment())?;
+    let balance = match response.value {
+        Some(data) => unpack_token_account(&data.data)?.amount,
+        None => 0,
+    };
+    emit(
+        json!({
+            "mint": mint.address.to_string(),
+            "owner": owner.to_string(),
+            "account": account.to_string(),
+            "amount": mint.format_amount(balance),
+            "base_units": balance,
+        }),
+        || println!("Balance: {} ({})", mint.format_amount(balance), account)
+    );
+    Ok(())
+}
+
+pub fn unpack_token_account(data: &[u8]) -> Result<TokenAccount, Box<dyn Error>> {
+    Ok(StateWithExtensions::<TokenAccount>::unpack(data)?.base)
+}
+
+/// Prints a mint or token account, including the state of its Token-2022 extensions.
+fn token_info(rpc_client: &RpcClient, cmd: &TokenInfoCommand) -> Result<(), Box<dyn Error>> {
+    let address = Pubkey::from_str(&cmd.address)?;
+    let account = rpc_client.get_account(&address)?;
+    if !is_token_program(&account.owner) {
+        return Err(format!("{} is not owned by a token program", address).into());
+    }
+    if let Ok(mint) = StateWithExtensions::<Mint>::unpack(&account.data) {
+        let decimals = mint.base.decimals;
+        // Only the transfer fee depends on the epoch.
+        let epoch = match mint.get_extension::<TransferFeeConfig>() {
+            Ok(_) => rpc_client.get_epoch_info()?.epoch,
+            Err(_) => 0,
+        };
+        let extensions = mint_extensions(&mint, epoch)?;
+        let supply = format_token_amount(mint.base.supply, decimals);
+        let mint_authority = format_authority(mint.base.mint_authority.into());
+        let freeze_authority = format_authority(mint.base.freeze_authority.into());
+        emit(
+            json!({
+                "type": "mint",
+                "address": address.to_string(),
+                "program": account.owner.to_string(),
+                "supply": supply,
+                "decimals": decimals,
+                "mint_authority": mint_authority,
+                "freeze_authority": freeze_authority,
+                "extensions": extensions.iter().map(|(value, _)| value).collect::<Vec<_>>(),
+            }),
+            || {
+                println!("Program: {}", program_name(&account.owner));
+                println!("Mint: {}", address);
+                println!("Supply: {}", supply);
+                println!("Decimals: {}", decimals);
+                println!("Mint authority: {}", mint_authority);
+                println!("Freeze authority: {}", freeze_authority);
+                for (_, text) in &extensions {
+                    println!("{}", text);
+                }
+            }
+        );
+        return Ok(());
+    }
+
+    let token_account = StateWithExtensions::<TokenAccount>::unpack(&account.data)?;
+    let mint = MintInfo::fetch(rpc_client, &token_account.base.mint)?;
+    let extensions = account_extensions(&token_account, &mint)?;
+    let base = &token_account.base;
+    let delegate = Option::<Pubkey>::from(base.delegate);
+    emit(
+        json!({
+            "type": "token_account",
+            "address": address.to_string(),
+            "program": account.owner.to_string(),
+            "mint": base.mint.to_string(),
+            "owner": base.owner.to_string(),
+            "amount": mint.format_amount(base.amount),
+            "base_units": base.amount,
+            "state": format!("{:?}", base.state),
+            "delegate": delegate.map(|delegate| delegate.to_string()),
+            "delegated_amount": mint.format_amount(base.delegated_amount),
+            "extensions": extensions.iter().map(|(value, _)| value).collect::<Vec<_>>(),
+        }),
+        || {
+            println!("Program: {}", program_name(&account.owner));
+            println!("Token account: {}", address);
+            println!("Mint: {}", base.mint);
+            println!("Owner: {}", base.owner);
+            println!("Balance: {}", mint.format_amount(base.amount));
+            println!("State: {:?}", base.state);
+            if let Some(delegate) = delegate {
+                println!(
+                    "Delegate: {} ({} tokens)",
+                    delegate,
+                    mint.format_amount(base.delegated_amount)
+                );
+            }
+            for (_, text) in &extensions {
+                println!("{}", text);
+            }
+        }
+    );
+    Ok(())
+}
+
+/// Each extension of a mint as a JSON object and the equivalent line of text.
+fn mint_extensions(
+    mint: &StateWithExtensions<Mint>,
+    epoch: u64
+) -> Result<Vec<(Value, String)>, Box<dyn Error>> {
+    let decimals = mint.base.decimals;
+    let mut extensions = Vec::new();
+    for extension in mint.get_extension_types()? {
+        let name = format!("{:?}", extension);
+        extensions.push(match extension {
+            ExtensionType::TransferFeeConfig => {
+                let config = mint.get_extension::<TransferFeeConfig>()?;
+                let fee = config.get_epoch_fee(epoch);
+                let basis_points = u16::from(fee.transfer_fee_basis_points);
+                let maximum = format_token_amount(fee.maximum_fee.into(), decimals);
+                let withheld = format_token_amount(config.withheld_amount.into(), decimals);
+                let text = format!(
+                    "Transfer fee: {} bps, max {}, withheld {}",
+                    basis_points,
+                    maximum,
+                    withheld
+                );
+                let value = json!({
+                    "type": name,
+                    "basis_points": basis_points,
+                    "maximum_fee": maximum,
+                    "withheld": withheld,
+                });
+                (value, text)
+            }
+            ExtensionType::MetadataPointer => {
+                let pointer = mint.get_extension::<MetadataPointer>()?;
+                let metadata = format_authority(pointer.metadata_address.into());
+                let authority = format_authority(pointer.authority.into());
+                let text = format!("Metadata pointer: {} (authority {})", metadata, authority);
+                let value = json!({
+                    "type": name,
+                    "metadata_address": metadata,
+                    "authority": authority,
+                });
+                (value, text)
+            }
+            ExtensionType::InterestBearingConfig => {
+                let config = mint.get_extension::<InterestBearingConfig>()?;
+                let rate = i16::from(config.current_rate);
+                let average = i16::from(config.pre_update_average_rate);
+                let authority = format_authority(config.rate_authority.into());
+                let text = format!(
+                    "Interest rate: {} bps (average before last update {} bps, authority {})",
+                    rate,
+                    average,
+                    authority
+                );
+                let value = json!({
+                    "type": name,
+                    "rate_basis_points": rate,
+                    "pre_update_average_rate": average,
+                    "authority": authority,
+                });
+                (value, text)
+            }
+            ExtensionType::NonTransferable => {
+                (json!({ "type": name }), "Non-transferable".to_string())
+            }
+            _ => (json!({ "type": name }), format!("Extension: {}", name)),
+        });
+    }
+    Ok(extensions)
+}
+
+/// Each extension of a token account as a JSON object and the equivalent line of text.
+fn account_extensions(
+    token_account: &StateWithExtensions<TokenAccount>,
+    mint: &MintInfo
+) -> Result<Vec<(Value, String)>, Box<dyn Error>> {
+    let mut extensions = Vec::new();
+    for extension in token_account.get_extension_types()? {
+        let name = format!("{:?}", extension);
+        extensions.push(match extension {
+            ExtensionType::TransferFeeAmount => {
+                let withheld = token_account.get_extension::<TransferFeeAmount>()?.withheld_amount;
+                let withheld = mint.format_amount(withheld.into());
+                let text = format!("Withheld transfer fees: {}", withheld);
+                (json!({ "type": name, "withheld": withheld }), text)
+            }
+            ExtensionType::NonTransferableAccount => {
+                (json!({ "type": name }), "Non-transferable".to_string())
+            }
+            ExtensionType::ImmutableOwner => {
+                (json!({ "type": name }), "Immutable owner".to_string())
+            }
+            _ => (json!({ "type": name }), format!("Extension: {}", name)),
+        });
+    }
+    Ok(extensions)
+}
+
+pub fn format_authority(authority: Option<Pubkey>) -> String {
+    authority.map_or_else(|| "none".to_string(), |authority| authority.to_string())
+}
+
+/// Converts a decimal string such as "1.5" into base units, rejecting excess precision.
+pub fn parse_token_amount(amount: &str, decimals: u8) -> Result<u64, Box<dyn Error>> {
+    let amount = amount.trim();
+    let (whole, fraction) = amount.split_once('.').unwrap_or((amount, ""));
+    let is_digits = |s: &str| s.chars().all(|c| c.is_ascii_digit());
+    if (whole.is_empty() && fraction.is_empty()) || !is_digits(whole) || !is_digits(fraction) {
+        return Err(format!("Invalid amount '{}'", amount).into());
+    }
+    if fraction.len() > (decimals as usize) {
+        return Err(
+            format!("Amount '{}' has more than {} decimal places", amount, decimals).into()
+        );
+    }
+
+    let scale = 10u64.checked_pow(decimals as u32).ok_or("Too many decimals")?;
+    let whole: u64 = if whole.is_empty() { 0 } else { whole.parse()? };
+    let fraction: u64 = if fraction.is_empty() {
+        0
+    } else {
+        format!("{:0<width$}", fraction, width = decimals as usize).parse()?
+    };
+    whole
+        .checked_mul(scale)
+        .and_then(|base| base.checked_add(fraction))
+        .ok_or_else(|| format!("Amount '{}' is too large", amount).into())
+}
+
+/// Formats base units with `decimals` places, trimming trailing zeros.
+pub fn format_token_amount(amount: u64, decimals: u8) -> String {
+    if decimals == 0 {
+        return amount.to_string();
+    }
+    let scale = 10u128.pow(decimals as u32);
+    let whole = (amount as u128) / scale;
+    let fraction = (amount as u128) % scale;
+    if fraction == 0 {
+        return whole.to_string();
+    }
+    let fraction = format!("{:0>width$}", fraction, width = decimals as usize);
+    format!("{}.{}", whole, fraction.trim_end_matches('0'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::program_pack::Pack;
+    use spl_token_2022::{
+        extension::{
+            immutable_owner::ImmutableOwner,
+            non_transferable::NonTransferableAccount,
+            transfer_fee::{ instruction::TransferFeeInstruction, TransferFee },
+            BaseStateWithExtensionsMut,
+            StateWithExtensionsMut,
+        },
+        instruction::TokenInstruction,
+        state::AccountState,
+    };
+
+    /// 1% of each transfer, at most 10 tokens of 2 decimals, with 2.5 tokens withheld.
+    fn fee_config() -> TransferFeeConfig {
+        let fee = TransferFee {
+            epoch: 0.into(),
+            maximum_fee: 1_000.into(),
+            transfer_fee_basis_points: 100.into(),
+        };
+        TransferFeeConfig {
+            withheld_amount: 250.into(),
+            older_transfer_fee: fee,
+            newer_transfer_fee: fee,
+            ..Default::default()
+        }
+    }
+
+    fn mint_info(transfer_fee: Option<TransferFeeConfig>, non_transferable: bool) -> MintInfo {
+        MintInfo {
+            address: Pubkey::new_unique(),
+            program_id: spl_token_2022::id(),
+            decimals: 2,
+            transfer_fee,
+            non_transferable,
+            account_len: 0,
+        }
+    }
+
+    fn account_data(amount: u64, withheld: Option<u64>, non_transferable: bool) -> Vec<u8> {
+        let mut extensions = vec![ExtensionType::ImmutableOwner];
+        extensions.extend(withheld.map(|_| ExtensionType::TransferFeeAmount));
+        extensions.extend(non_transferable.then_some(ExtensionType::NonTransferableAccount));
+        let len = ExtensionType::try_calculate_account_len::<TokenAccount>(&extensions).unwrap();
+        let mut data = vec![0; len];
+        let mut state = StateWithExtensionsMut::<TokenAccount>::unpack_uninitialized(&mut data)
+            .unwrap();
+        state.init_extension::<ImmutableOwner>(true).unwrap();
+        if let Some(withheld) = withheld {
+            state.init_extension::<TransferFeeAmount>(true).unwrap().withheld_amount =
+                withheld.into();
+        }
+        if non_transferable {
+            state.init_extension::<NonTransferableAccount>(true).unwrap();
+        }
+        state.base = TokenAccount {
+            amount,
+            state: AccountState::Initialized,
+            ..Default::default()
+        };
+        state.pack_base();
+        state.init_account_type().unwrap();
+        data
+    }
+
+    #[test]
+    fn associated_accounts_are_sized_for_the_mint_extensions() {
+        let token = spl_token::id();
+        let token_2022 = spl_token_2022::id();
+        let fee = [ExtensionType::TransferFeeConfig];
+        assert_eq!(associated_account_len(&token, &[]).unwrap(), TokenAccount::LEN);
+        assert_eq!(associated_account_len(&token, &fee).unwrap(), TokenAccount::LEN);
+        // Account type byte, then the immutable owner's type and length header.
+        assert_eq!(associated_account_len(&token_2022, &[]).unwrap(), TokenAccount::LEN + 5);
+        // Plus the withheld amount with its own header.
+        assert_eq!(associated_account_len(&token_2022, &fee).unwrap(), TokenAccount::LEN + 17);
+        let non_transferable = [ExtensionType::NonTransferable];
+        assert_eq!(
+            associated_account_len(&token_2022, &non_transferable).unwrap(),
+            TokenAccount::LEN + 9
+        );
+    }
+
+    #[test]
+    fn fee_charging_mints_transfer_with_the_expected_fee() {
+        let (source, destination, owner) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+
+        let plain = mint_info(None, false);
+        let (instruction, fee) = plain
+            .transfer_at_epoch(0, &source, &destination, &owner, 5_000)
+            .unwrap();
+        assert_eq!(fee, 0);
+        assert!(
+            matches!(
+                TokenInstruction::unpack(&instruction.data).unwrap(),
+                TokenInstruction::TransferChecked { amount: 5_000, decimals: 2 }
+            )
+        );
+
+        let charged = mint_info(Some(fee_config()), false);
+        for (amount, expected) in [(5_000, 50), (500_000, 1_000)] {
+            let (instruction, fee) = charged
+                .transfer_at_epoch(7, &source, &destination, &owner, amount)
+                .unwrap();
+            assert_eq!(fee, expected);
+            assert!(
+                matches!(
+                    TokenInstruction::unpack(&instruction.data).unwrap(),
+                    TokenInstruction::TransferFeeExtension
+                )
+            );
+            assert_eq!(
+                TransferFeeInstruction::unpack(&instruction.data[1..]).unwrap(),
+                TransferFeeInstruction::TransferCheckedWithFee {
+                    amount,
+                    decimals: 2,
+                    fee: expected,
+                }
+            );
+        }
+
+        let bound = mint_info(None, true);
+        assert!(bound.transfer_at_epoch(0, &source, &destination, &owner, 1).is_err());
+    }
+
+    #[test]
+    fn withheld_fees_and_non_transferable_tokens_stop_a_close() {
+        let account = Pubkey::new_unique();
+        let charged = mint_info(Some(fee_config()), false);
+        let bound = mint_info(None, true);
+
+        let data = account_data(300, Some(0), false);
+        assert_eq!(closable_balance(&charged, &account, &data).unwrap(), 300);
+        let data = account_data(0, Some(25), false);
+        let error = closable_balance(&charged, &account, &data).unwrap_err();
+        assert!(error.to_string().contains("0.25 tokens of withheld transfer fees"));
+
+        assert_eq!(closable_balance(&bound, &account, &account_data(0, None, true)).unwrap(), 0);
+        let data = account_data(100, None, true);
+        let error = closable_balance(&bound, &account, &data).unwrap_err();
+        assert!(error.to_string().contains("1 non-transferable tokens"));
+    }
+
+    #[test]
+    fn token_info_describes_extensions() {
+        let extensions = [ExtensionType::TransferFeeConfig, ExtensionType::NonTransferable];
+        let len = ExtensionType::try_calculate_account_len::<Mint>(&extensions).unwrap();
+        let mut data = vec![0; len];
+        let mut state = StateWithExtensionsMut::<Mint>::unpack_uninitialized(&mut data).unwrap();
+        *state.init_extension::<TransferFeeConfig>(true).unwrap() = fee_config();
+        state.init_extension::<NonTransferable>(true).unwrap();
+        state.base = Mint { decimals: 2, is_initialized: true, ..Default::default() };
+        state.pack_base();
+        state.init_account_type().unwrap();
+
+        let mint = StateWithExtensions::<Mint>::unpack(&data).unwrap();
+        let described = mint_extensions(&mint, 3).unwrap();
+        assert_eq!(
+            described[0].0,
+            json!({
+                "type": "TransferFeeConfig",
+                "basis_points": 100,
+                "maximum_fee": "10",
+                "withheld": "2.5",
+            })
+        );
+        assert_eq!(described[0].1, "Transfer fee: 100 bps, max 10, withheld 2.5");
+        assert_eq!(described[1].1, "Non-transferable");
+
+        let data = account_data(0, Some(25), false);
+        let account = StateWithExtensions::<TokenAccount>::unpack(&data).unwrap();
+        let charged = mint_info(Some(fee_config()), false);
+        let described = account_extensions(&account, &charged).unwrap();
+        let texts: Vec<&str> = described.iter().map(|(_, text)| text.as_str()).collect();
+        assert_eq!(texts, ["Immutable owner", "Withheld transfer fees: 0.25"]);
+        assert_eq!(described[1].0, json!({ "type": "TransferFeeAmount", "withheld": "0.25" }));
+    }
+
+    #[test]
+    fn amounts_are_parsed_exactly() {
+        assert_eq!(parse_token_amount("1.5", 9).unwrap(), 1_500_000_000);
+        assert_eq!(parse_token_amount("0.000000001", 9).unwrap(), 1);
+        assert_eq!(parse_token_amount(".3", 1).unwrap(), 3);
+        assert_eq!(parse_token_amount("42", 0).unwrap(), 42);
+        assert!(parse_token_amount("0.0000000001", 9).is_err());
+        assert!(parse_token_amount("1e3", 9).is_err());
+        assert!(parse_token_amount("-1", 9).is_err());
+        assert!(parse_token_amount("18446744073709551616", 0).is_err());
+    }
+
+    #[test]
+    fn amounts_are_formatted_without_trailing_zeros() {
+        assert_eq!(format_token_amount(1_500_000_000, 9), "1.5");
+        assert_eq!(format_token_amount(1, 9), "0.000000001");
+        assert_eq!(format_token_amount(7, 0), "7");
+    }
+
+    #[test]
+    fn extensions_select_token_2022_but_reject_an_explicit_token_program() {
+        use clap::Parser;
+        let program = |args: &[&str]| {
+            let cmd = CreateMintCommand::parse_from([&["create-mint"], args].concat());
+            mint_program(&cmd).map(|program| program.id())
+        };
+        assert_eq!(program(&[]).unwrap(), spl_token::id());
+        assert_eq!(program(&["--non-transferable"]).unwrap(), spl_token_2022::id());
+        assert_eq!(
+            program(&["--program", "token-2022", "--interest-rate", "5"]).unwrap(),
+            spl_token_2022::id()
+        );
+        assert!(program(&["--program", "token", "--metadata-pointer"]).is_err());
+    }
+}
@@ -0,0 +1,337 @@
+use solana_client::{ nonce_utils, rpc_client::RpcClient };
+use solana_sdk::{
+    compute_budget::ComputeBudgetInstruction,
+    hash::Hash,
+    instruction::Instruction,
+    message::Message,
+    pubkey::Pubkey,
+    signature::Keypair,
+    signer::Signer,
+    system_instruction,
+    transaction::{ uses_durable_nonce, Transaction },
+};
+use std::{ error::Error, str::FromStr, sync::OnceLock };
+
+use crate::{
+    confirm::{ confirm_transaction, Confirmation },
+    keystore::{ read_keys, read_public_key },
+    nonce::nonce_value,
+    offline::missing_signers,
+    state::TransactionArgs,
+    wallet::Wallet,
+};
+
+/// `getRecentPrioritizationFees` accepts at most this many accounts.
+const MAX_FEE_ACCOUNTS: usize = 128;
+/// Compute units each instruction may use when no limit is set, and the most a transaction may.
+const DEFAULT_INSTRUCTION_COMPUTE_UNITS: u64 = 200_000;
+const MAX_COMPUTE_UNITS: u64 = 1_400_000;
+
+/// The signer named by `--nonce-authority`.
+#[derive(Debug)]
+pub enum NonceAuthority {
+    /// A key file, unlocked when the first nonce transaction is built.
+    KeyFile(String, Box<OnceLock<Keypair>>),
+    /// A public key whose holder adds the signature later with `sign`.
+    Pubkey(Pubkey),
+}
+
+impl NonceAuthority {
+    pub fn pubkey(&self) -> Result<Pubkey, Box<dyn Error>> {
+        match self {
+            NonceAuthority::KeyFile(path, keypair) =>
+                match keypair.get() {
+                    Some(keypair) => Ok(keypair.pubkey()),
+                    None => read_public_key(path),
+                }
+            NonceAuthority::Pubkey(pubkey) => Ok(*pubkey),
+        }
+    }
+
+    /// Unlocks the key file on first use; a bare public key has no keypair.
+    pub fn keypair(&self) -> Result<Option<&Keypair>, Box<dyn Error>> {
+        match self {
+            NonceAuthority::KeyFile(path, keypair) => {
+                if keypair.get().is_none() {
+                    let _ = keypair.set(read_keys(path)?);
+                }
+                Ok(keypair.get())
+            }
+            NonceAuthority::Pubkey(_) => Ok(None),
+        }
+    }
+}
+
+impl TransactionArgs {
+    /// The command-line defaults, for tests to override.
+    #[cfg(test)]
+    pub fn for_tests() -> Self {
+        TransactionArgs {
+            priority_fee: None,
+            compute_unit_limit: None,
+            auto_fee: false,
+            fee_percentile: 75,
+            nonce: None,
+            nonce_authority: None,
+            confirm_timeout: 90,
+            nonce_authority_signer: None,
+            commitment: Default::default(),
+            websocket_url: None,
+        }
+    }
+
+    /// Resolves `--nonce-authority` to a key file or wallet label, or failing that to a
+    /// public key. Nothing is unlocked until a nonce transaction is built.
+    pub fn resolve_nonce_authority(&mut self, wallet: &Wallet) -> Result<(), Box<dyn Error>> {
+        if let Some(authority) = &self.nonce_authority {
+            self.nonce_authority_signer = Some(match wallet.resolve(Some(authority)) {
+                Ok(path) => NonceAuthority::KeyFile(path, Box::default()),
+                Err(e) => NonceAuthority::Pubkey(Pubkey::from_str(authority).map_err(|_| e)?),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Signs `instructions` with `payer` and any extra `signers`, then submits and confirms them.
+/// Compute budget instructions requested in `options` are prepended.
+pub fn send_instructions(
+    rpc_client: &RpcClient,
+    options: &TransactionArgs,
+    instructions: &[Instruction],
+    payer: &Keypair,
+    signers: &[&Keypair]
+) -> Result<Confirmation, Box<dyn Error>> {
+    let mut all_signers: Vec<&Keypair> = vec![payer];
+    all_signers.extend(signers);
+    let transaction = build_transaction(
+        rpc_client,
+        options,
+        instructions,
+        &payer.pubkey(),
+        &all_signers,
+        None
+    )?;
+
+    submit_transaction(rpc_client, options, &transaction)
+}
+
+/// Builds a transaction paid by `fee_payer` and signs it with `signers`. Signatures of
+/// required signers that are not in `signers` are left empty for `sign` to fill in.
+///
+/// With `--nonce`, the nonce is advanced first and its durable value replaces the recent
+/// blockhash. `blockhash` overrides the value fetched from the cluster, for offline signing.
+pub fn build_transaction(
+    rpc_client: &RpcClient,
+    options: &TransactionArgs,
+    instructions: &[Instruction],
+    fee_payer: &Pubkey,
+    signers: &[&Keypair],
+    blockhash: Option<Hash>
+) -> Result<Transaction, Box<dyn Error>> {
+    let price = compute_unit_price(rpc_client, options, instructions)?;
+    build_transaction_at_price(
+        rpc_client,
+        options,
+        instructions,
+        fee_payer,
+        signers,
+        blockhash,
+        price
+    )
+}
+
+/// `build_transaction` with a compute unit price already resolved by `compute_unit_price`, so
+/// that several transactions pay the same priority fee.
+pub fn build_transaction_at_price(
+    rpc_client: &RpcClient,
+    options: &TransactionArgs,
+    instructions: &[Instruction],
+    fee_payer: &Pubkey,
+    signers: &[&Keypair],
+    blockhash: Option<Hash>,
+    price: u64
+) -> Result<Transaction, Box<dyn Error>> {
+    let mut all_instructions = Vec::new();
+    let mut all_signers = signers.to_vec();
+    let blockhash = match &options.nonce {
+        Some(nonce) => {
+            let nonce = Pubkey::from_str(nonce)?;
+            let authority = match &options.nonce_authority_signer {
+                Some(authority) => {
+                    if let Some(keypair) = authority.keypair()? {
+                        all_signers.push(keypair);
+                    }
+                    authority.pubkey()?
+                }
+                None => *fee_payer,
+            };
+            all_instructions.push(system_instruction::advance_nonce_account(&nonce, &authority));
+            match blockhash {
+                Some(blockhash) => blockhash,
+                None => nonce_blockhash(rpc_client, &nonce)?,
+            }
+        }
+        None =>
+            match blockhash {
+                Some(blockhash) => blockhash,
+                None => rpc_client.get_latest_blockhash()?,
+            }
+    };
+    all_instructions.extend(budget_instructions_at_price(options, price));
+    all_instructions.extend_from_slice(instructions);
+
+    let mut unique_signers: Vec<&Keypair> = Vec::with_capacity(all_signers.len());
+    for signer in all_signers {
+        if !unique_signers.iter().any(|unique| unique.pubkey() == signer.pubkey()) {
+            unique_signers.push(signer);
+        }
+    }
+
+    let mut transaction = Transaction::new_unsigned(
+        Message::new(&all_instructions, Some(fee_payer))
+    );
+    transaction.try_partial_sign(&unique_signers, blockhash)?;
+    Ok(transaction)
+}
+
+/// Sends a signed transaction and waits for it to reach the profile's commitment. Durable
+/// nonce transactions are not checked for blockhash expiry, since their nonce never expires.
+pub fn submit_transaction(
+    rpc_client: &RpcClient,
+    options: &TransactionArgs,
+    transaction: &Transaction
+) -> Result<Confirmation, Box<dyn Error>> {
+    let missing = missing_signers(transaction);
+    if !missing.is_empty() {
+        let missing: Vec<String> = missing.iter().map(Pubkey::to_string).collect();
+        return Err(
+            format!(
+                "Transaction is missing signatures from: {}; sign it with --sign-only and `sign`",
+                missing.join(", ")
+            ).into()
+        );
+    }
+    let signature = rpc_client.send_transaction(transaction)?;
+    let blockhash = match uses_durable_nonce(transaction) {
+        Some(_) => None,
+        None => Some(&transaction.message.recent_blockhash),
+    };
+    confirm_transaction(rpc_client, options, &signature, blockhash)
+}
+
+/// Current durable value stored in a nonce account.
+pub fn nonce_blockhash(rpc_client: &RpcClient, nonce: &Pubkey) -> Result<Hash, Box<dyn Error>> {
+    nonce_value(&nonce_utils::get_account(rpc_client, nonce)?)
+}
+
+/// Compute unit limit and price instructions for `--compute-unit-limit`, `--priority-fee` and
+/// `--auto-fee`. Empty when none of them is set.
+pub fn compute_budget_instructions(
+    rpc_client: &RpcClient,
+    options: &TransactionArgs,
+    instructions: &[Instruction]
+) -> Result<Vec<Instruction>, Box<dyn Error>> {
+    let price = compute_unit_price(rpc_client, options, instructions)?;
+    Ok(budget_instructions_at_price(options, price))
+}
+
+fn budget_instructions_at_price(options: &TransactionArgs, price: u64) -> Vec<Instruction> {
+    let mut budget = Vec::new();
+    if let Some(limit) = options.compute_unit_limit {
+        budget.push(ComputeBudgetInstruction::set_compute_unit_limit(limit));
+    }
+    if price > 0 {
+        budget.push(ComputeBudgetInstruction::set_compute_unit_price(price));
+    }
+    budget
+}
+
+/// Price per compute unit, in micro-lamports, from `--priority-fee` or `--auto-fee`.
+pub fn compute_unit_price(
+    rpc_client: &RpcClient,
+    options: &TransactionArgs,
+    instructions: &[Instruction]
+) -> Result<u64, Box<dyn Error>> {
+    if options.auto_fee {
+        auto_priority_fee(rpc_client, instructions, options.fee_percentile)
+    } else {
+        Ok(options.priority_fee.unwrap_or(0))
+    }
+}
+
+/// Most a transaction of `instructions` instructions pays in priority fees at `price`, in
+/// lamports: the price times its compute unit limit.
+pub fn max_priority_fee(options: &TransactionArgs, price: u64, instructions: usize) -> u64 {
+    let limit = match options.compute_unit_limit {
+        Some(limit) => u64::from(limit),
+        None => ((instructions as u64) * DEFAULT_INSTRUCTION_COMPUTE_UNITS).min(MAX_COMPUTE_UNITS),
+    };
+    (u128::from(price) * u128::from(limit)).div_ceil(1_000_000) as u64
+}
+
+/// Priority fee paid by the given percentile of recent slots that wrote to the same accounts.
+fn auto_priority_fee(
+    rpc_client: &RpcClient,
+    instructions: &[Instruction],
+    percentile: u8
+) -> Result<u64, Box<dyn Error>> {
+    let mut accounts: Vec<Pubkey> = Vec::new();
+    for meta in instructions.iter().flat_map(|instruction| &instruction.accounts) {
+        if meta.is_writable && !accounts.contains(&meta.pubkey) {
+            accounts.push(meta.pubkey);
+        }
+    }
+    accounts.truncate(MAX_FEE_ACCOUNTS);
+
+    let fees: Vec<u64> = rpc_client
+        .get_recent_prioritization_fees(&accounts)?
+        .iter()
+        .map(|fee| fee.prioritization_fee)
+        .collect();
+    let slots = fees.len();
+    let fee = fee_percentile(fees, percentile);
+    eprintln!(
+        "Priority fee: {} micro-lamports per compute unit (p{} of {} recent slots)",
+        fee,
+        percentile,
+        slots
+    );
+    Ok(fee)
+}
+
+/// Nearest-rank percentile of `fees`, or 0 when there are none.
+pub fn fee_percentile(mut fees: Vec<u64>, percentile: u8) -> u64 {
+    if fees.is_empty() {
+        return 0;
+    }
+    fees.sort_unstable();
+    let rank = ((percentile as usize) * fees.len()).div_ceil(100);
+    fees[rank.saturating_sub(1)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_nearest_rank_percentile() {
+        let fees = vec![50, 0, 10, 40, 20, 30, 0, 0, 0, 100];
+        assert_eq!(fee_percentile(fees.clone(), 0), 0);
+        assert_eq!(fee_percentile(fees.clone(), 50), 10);
+        assert_eq!(fee_percentile(fees.clone(), 75), 40);
+        assert_eq!(fee_percentile(fees, 100), 100);
+        assert_eq!(fee_percentile(Vec::new(), 90), 0);
+    }
+
+    #[test]
+    fn priority_fees_are_the_price_times_the_unit_limit() {
+        let mut options = TransactionArgs::for_tests();
+        assert_eq!(max_priority_fee(&options, 0, 3), 0);
+        // 2 instructions may use 400,000 units; at 10,000 micro-lamports that is 4,000 lamports.
+        assert_eq!(max_priority_fee(&options, 10_000, 2), 4_000);
+        assert_eq!(max_priority_fee(&options, 10_000, 20), 14_000);
+        options.compute_unit_limit = Some(1_000);
+        assert_eq!(max_priority_fee(&options, 1_500, 2), 2);
+    }
+}
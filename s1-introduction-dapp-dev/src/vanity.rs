@@ -0,0 +1,181 @@
+use rand::{ rngs::OsRng, RngCore };
+use solana_sdk::signature::{ keypair_from_seed, Keypair, Signer };
+use std::{
+    error::Error,
+    io::{ self, Write },
+    sync::{ atomic::{ AtomicBool, AtomicU64, Ordering }, Arc, Mutex },
+    thread,
+    time::{ Duration, Instant },
+};
+
+const BASE58_ALPHABET: &str = "123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+// A 32-byte public key encodes to at most 44 base58 characters.
+const MAX_PATTERN_LEN: usize = 44;
+const BATCH: u64 = 1_000;
+
+pub struct VanityPattern {
+    pub starts_with: String,
+    pub ends_with: String,
+    pub ignore_case: bool,
+}
+
+impl VanityPattern {
+    pub fn is_empty(&self) -> bool {
+        self.starts_with.is_empty() && self.ends_with.is_empty()
+    }
+
+    /// Rejects patterns that no base58 public key can ever match.
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if self.starts_with.len() + self.ends_with.len() > MAX_PATTERN_LEN {
+            return Err(
+                format!(
+                    "Vanity pattern is longer than a public key ({} characters)",
+                    MAX_PATTERN_LEN
+                ).into()
+            );
+        }
+        for c in self.starts_with.chars().chain(self.ends_with.chars()) {
+            if self.char_choices(c) == 0 {
+                return Err(
+                    format!(
+                        "'{}' is not in the base58 alphabet (0, O, I and l are never used)",
+                        c
+                    ).into()
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Expected number of keys to try before one matches.
+    pub fn expected_attempts(&self) -> f64 {
+        self.starts_with
+            .chars()
+            .chain(self.ends_with.chars())
+            .map(|c| 58.0 / (self.char_choices(c) as f64))
+            .product()
+    }
+
+    pub fn matches(&self, address: &str) -> bool {
+        if self.ignore_case {
+            let address = address.to_lowercase();
+            address.starts_with(&self.starts_with.to_lowercase()) &&
+                address.ends_with(&self.ends_with.to_lowercase())
+        } else {
+            address.starts_with(&self.starts_with) && address.ends_with(&self.ends_with)
+        }
+    }
+
+    fn char_choices(&self, c: char) -> usize {
+        if !self.ignore_case {
+            return BASE58_ALPHABET.contains(c) as usize;
+        }
+        let lower = c.to_ascii_lowercase();
+        let upper = c.to_ascii_uppercase();
+        if lower == upper {
+            BASE58_ALPHABET.contains(c) as usize
+        } else {
+            (BASE58_ALPHABET.contains(lower) as usize) + (BASE58_ALPHABET.contains(upper) as usize)
+        }
+    }
+}
+
+/// Searches for a keypair matching `pattern` on `threads` cores, reporting progress on stderr.
+/// Returns `None` once `max_attempts` keys have been tried without a match.
+pub fn grind(
+    pattern: &VanityPattern,
+    threads: usize,
+    max_attempts: Option<u64>
+) -> Result<Option<Keypair>, Box<dyn Error>> {
+    pattern.validate()?;
+    let expected = pattern.expected_attempts();
+    eprintln!("Searching with {} threads, ~{:.0} attempts expected", threads, expected);
+
+    let attempts = Arc::new(AtomicU64::new(0));
+    let done = Arc::new(AtomicBool::new(false));
+    let found: Arc<Mutex<Option<Keypair>>> = Arc::new(Mutex::new(None));
+    let start = Instant::now();
+
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            let (attempts, done, found) = (attempts.clone(), done.clone(), found.clone());
+            scope.spawn(move || {
+                let mut seed = [0u8; 32];
+                while !done.load(Ordering::Relaxed) {
+                    for _ in 0..BATCH {
+                        OsRng.fill_bytes(&mut seed);
+                        let keypair = keypair_from_seed(&seed).expect("32-byte seed");
+                        if pattern.matches(&keypair.pubkey().to_string()) {
+                            done.store(true, Ordering::Relaxed);
+                            found.lock().unwrap().get_or_insert(keypair);
+                            break;
+                        }
+                    }
+                    let total = attempts.fetch_add(BATCH, Ordering::Relaxed) + BATCH;
+                    if max_attempts.is_some_and(|max| total >= max) {
+                        done.store(true, Ordering::Relaxed);
+                    }
+                }
+            });
+        }
+
+        while !done.load(Ordering::Relaxed) {
+            thread::sleep(Duration::from_millis(500));
+            report_progress(attempts.load(Ordering::Relaxed), expected, start.elapsed());
+        }
+    });
+    eprintln!();
+
+    let keypair = found.lock().unwrap().take();
+    eprintln!(
+        "{} after {} attempts in {:.1}s",
+        if keypair.is_some() { "Found" } else { "Gave up" },
+        attempts.load(Ordering::Relaxed),
+        start.elapsed().as_secs_f64()
+    );
+    Ok(keypair)
+}
+
+fn report_progress(attempts: u64, expected: f64, elapsed: Duration) {
+    let rate = (attempts as f64) / elapsed.as_secs_f64().max(f64::EPSILON);
+    let remaining = (expected - (attempts as f64)).max(0.0);
+    let eta = if rate > 0.0 { remaining / rate } else { f64::INFINITY };
+    eprint!("\r{} attempts, {:.0} keys/s, ETA {:.0}s      ", attempts, rate, eta);
+    let _ = io::stderr().flush();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pattern(starts_with: &str, ends_with: &str, ignore_case: bool) -> VanityPattern {
+        VanityPattern {
+            starts_with: starts_with.to_string(),
+            ends_with: ends_with.to_string(),
+            ignore_case,
+        }
+    }
+
+    #[test]
+    fn impossible_characters_are_rejected() {
+        assert!(pattern("0x", "", false).validate().is_err());
+        assert!(pattern("", "Ol", false).validate().is_err());
+        assert!(pattern("Io", "", true).validate().is_ok());
+        assert!(pattern(&"a".repeat(45), "", false).validate().is_err());
+    }
+
+    #[test]
+    fn ignore_case_widens_matches() {
+        assert!(pattern("abc", "", true).matches("ABCdef"));
+        assert!(!pattern("abc", "", false).matches("ABCdef"));
+        assert_eq!(pattern("a", "", false).expected_attempts(), 58.0);
+        assert_eq!(pattern("a", "", true).expected_attempts(), 29.0);
+    }
+
+    #[test]
+    fn grinds_a_short_prefix() {
+        let pattern = pattern("A", "", false);
+        let keypair = grind(&pattern, 2, None).unwrap().unwrap();
+        assert!(keypair.pubkey().to_string().starts_with('A'));
+    }
+}
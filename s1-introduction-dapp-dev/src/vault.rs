@@ -0,0 +1,540 @@
+use borsh::{ BorshDeserialize, BorshSerialize };
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{
+    hash::hash,
+    instruction::{ AccountMeta, Instruction },
+    pubkey::Pubkey,
+    signature::Keypair,
+    signer::Signer,
+    system_program,
+};
+use serde_json::{ json, Value };
+use std::{ error::Error, str::FromStr };
+
+use crate::{
+    amount::Amount,
+    history::format_timestamp,
+    keystore::{ read_keys, read_public_key },
+    output::emit,
+    state::*,
+    token::MintInfo,
+    transaction::send_instructions,
+    wallet::Wallet,
+};
+
+/// Seeds are at most 32 bytes, so a longer name cannot address a vault.
+const MAX_NAME_LENGTH: usize = 32;
+
+/// `Vault` account of the s3 vault program. Amounts are in base units of the vault's mint.
+#[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq)]
+pub struct Vault {
+    pub bump: u8,
+    pub authority: Pubkey,
+    pub name: String,
+    pub ts: i64,
+    pub net_deposits: u128,
+    pub net_withdraws: u128,
+}
+
+/// `User` account of the s3 vault program: what one depositor put in and took out of a vault.
+#[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq)]
+pub struct VaultUser {
+    pub bump: u8,
+    pub authority: Pubkey,
+    pub ts: i64,
+    pub vault: Pubkey,
+    pub net_deposit: u64,
+    pub net_withdraw: u64,
+}
+
+impl Vault {
+    pub const PREFIX_SEED: &'static [u8] = b"vault";
+
+    /// The address `create_vault` initializes, which `deposit` checks.
+    pub fn address(program_id: &Pubkey, name: &str) -> Pubkey {
+        Pubkey::find_program_address(&[Self::PREFIX_SEED, name.as_bytes()], program_id).0
+    }
+
+    /// The address `withdraw` and `close` check the vault against: its seeds also include the
+    /// authority, with the bump `create_vault` stored. `None` when they give no valid address.
+    pub fn checked_address(&self, program_id: &Pubkey) -> Option<Pubkey> {
+        let bump = [self.bump];
+        let seeds = [Self::PREFIX_SEED, self.authority.as_ref(), self.name.as_bytes(), &bump];
+        Pubkey::create_program_address(&seeds, program_id).ok()
+    }
+
+    pub fn held(&self) -> u128 {
+        self.net_deposits.saturating_sub(self.net_withdraws)
+    }
+}
+
+impl VaultUser {
+    pub const PREFIX_SEED: &'static [u8] = b"user";
+
+    pub fn address(program_id: &Pubkey, vault: &Pubkey, authority: &Pubkey) -> Pubkey {
+        let seeds = [Self::PREFIX_SEED, vault.as_ref(), authority.as_ref()];
+        Pubkey::find_program_address(&seeds, program_id).0
+    }
+
+    pub fn held(&self) -> u64 {
+        self.net_deposit.saturating_sub(self.net_withdraw)
+    }
+}
+
+/// Anchor prefixes instruction data with the first 8 bytes of `sha256("global:<name>")`.
+pub fn instruction_discriminator(name: &str) -> [u8; 8] {
+    discriminator(&format!("global:{}", name))
+}
+
+/// Anchor prefixes account data with the first 8 bytes of `sha256("account:<Name>")`.
+pub fn account_discriminator(name: &str) -> [u8; 8] {
+    discriminator(&format!("account:{}", name))
+}
+
+fn discriminator(preimage: &str) -> [u8; 8] {
+    let mut discriminator = [0; 8];
+    discriminator.copy_from_slice(&hash(preimage.as_bytes()).to_bytes()[..8]);
+    discriminator
+}
+
+/// Decodes an Anchor account after checking its discriminator. Accounts are allocated with
+/// more space than their fields need, so trailing bytes are ignored.
+pub fn decode_anchor_account<T: BorshDeserialize>(
+    name: &str,
+    data: &[u8]
+) -> Result<T, Box<dyn Error>> {
+    if data.len() < 8 || data[..8] != account_discriminator(name) {
+        return Err(format!("Account data is not an Anchor {} account", name).into());
+    }
+    Ok(T::deserialize(&mut &data[8..])?)
+}
+
+pub fn anchor_instruction(
+    program_id: &Pubkey,
+    name: &str,
+    args: &impl BorshSerialize,
+    accounts: Vec<AccountMeta>
+) -> Result<Instruction, Box<dyn Error>> {
+    let mut data = instruction_discriminator(name).to_vec();
+    args.serialize(&mut data)?;
+    Ok(Instruction::new_with_bytes(*program_id, &data, accounts))
+}
+
+pub fn create_vault_instruction(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    name: &str
+) -> Result<Instruction, Box<dyn Error>> {
+    let accounts = vec![
+        AccountMeta::new(Vault::address(program_id, name), false),
+        AccountMeta::new(*authority, true),
+        AccountMeta::new_readonly(system_program::id(), false)
+    ];
+    anchor_instruction(program_id, "create_vault", &name.to_string(), accounts)
+}
+
+/// Builds `deposit` or `withdraw`, which take the same accounts and a `u64` amount.
+pub fn vault_transfer_instruction(
+    program_id: &Pubkey,
+    instruction: &str,
+    vault: &Pubkey,
+    authority: &Pubkey,
+    user_token_account: &Pubkey,
+    vault_token_account: &Pubkey,
+    amount: u64
+) -> Result<Instruction, Box<dyn Error>> {
+    let accounts = vec![
+        AccountMeta::new(*vault, false),
+        AccountMeta::new(VaultUser::address(program_id, vault, authority), false),
+        AccountMeta::new(*authority, true),
+        AccountMeta::new(*user_token_account, false),
+        AccountMeta::new(*vault_token_account, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(system_program::id(), false)
+    ];
+    anchor_instruction(program_id, instruction, &amount, accounts)
+}
+
+pub fn close_vault_instruction(
+    program_id: &Pubkey,
+    vault: &Pubkey,
+    authority: &Pubkey,
+    vault_token_account: &Pubkey
+) -> Result<Instruction, Box<dyn Error>> {
+    let accounts = vec![
+        AccountMeta::new(*vault, false),
+        AccountMeta::new(*authority, true),
+        AccountMeta::new(*vault_token_account, false),
+        AccountMeta::new_readonly(spl_token::id(), false)
+    ];
+    anchor_instruction(program_id, "close", &(), accounts)
+}
+
+pub fn vault_command(
+    rpc_client: &RpcClient,
+    options: &TransactionArgs,
+    wallet: &Wallet,
+    cmd: &VaultCommand
+) -> Result<(), Box<dyn Error>> {
+    let program_id = Pubkey::from_str(&cmd.program_id)?;
+    let signer_path = wallet.resolve(cmd.from.as_deref())?;
+    let signer = || read_keys(&signer_path);
+    match &cmd.operation {
+        VaultOperations::Create(cmd) => {
+            create_vault(rpc_client, options, &program_id, &signer()?, cmd)
+        }
+        VaultOperations::Deposit(cmd) => {
+            deposit(rpc_client, options, &program_id, &signer()?, cmd)
+        }
+        VaultOperations::Withdraw(cmd) => {
+            withdraw(rpc_client, options, &program_id, &signer()?, cmd)
+        }
+        VaultOperations::Close(cmd) => {
+            close_vault(rpc_client, options, &program_id, &signer()?, cmd)
+        }
+        VaultOperations::Show(cmd) => show_vault(rpc_client, &program_id, &signer_path, cmd),
+    }
+}
+
+/// Fetches and decodes an account of the vault program, or `None` if it does not exist.
+fn fetch_account<T: BorshDeserialize>(
+    rpc_client: &RpcClient,
+    program_id: &Pubkey,
+    address: &Pubkey,
+    name: &str
+) -> Result<Option<T>, Box<dyn Error>> {
+    let Some(account) = rpc_client
+        .get_account_with_commitment(address, rpc_client.commitment())?
+        .value else {
+        return Ok(None);
+    };
+    if account.owner != *program_id {
+        return Err(format!("{} is not owned by the vault program {}", address, program_id).into());
+    }
+    decode_anchor_account(name, &account.data).map(Some)
+}
+
+fn fetch_vault(
+    rpc_client: &RpcClient,
+    program_id: &Pubkey,
+    name: &str
+) -> Result<(Pubkey, Vault), Box<dyn Error>> {
+    let address = Vault::address(program_id, name);
+    let vault = fetch_account(rpc_client, program_id, &address, "Vault")?.ok_or_else(|| {
+        format!("No vault named '{}' exists; it would be at {}", name, address)
+    })?;
+    Ok((address, vault))
+}
+
+/// The vault as `withdraw` and `close` accept it. The deployed program checks it at
+/// `Vault::checked_address`, which is not where `create_vault` puts it, so these fail fast
+/// instead of sending a transaction that is rejected with `ConstraintSeeds`.
+fn fetch_checked_vault(
+    rpc_client: &RpcClient,
+    program_id: &Pubkey,
+    name: &str
+) -> Result<(Pubkey, Vault), Box<dyn Error>> {
+    let (address, vault) = fetch_vault(rpc_client, program_id, name)?;
+    if vault.checked_address(program_id) != Some(address) {
+        return Err(
+            format!(
+                "Vault '{}' is at {}, the PDA of [\"vault\", name], but the vault program checks \
+                 withdraw and close against [\"vault\", authority, name]; no vault passes that \
+                 check until the program's seeds are fixed and it is redeployed",
+                name,
+                address
+            ).into()
+        );
+    }
+    Ok((address, vault))
+}
+
+/// The vault program moves tokens with the original Token program only.
+fn fetch_mint(rpc_client: &RpcClient, mint: &str) -> Result<MintInfo, Box<dyn Error>> {
+    let mint = MintInfo::fetch(rpc_client, &Pubkey::from_str(mint)?)?;
+    if mint.program_id != spl_token::id() {
+        return Err("The vault program does not support Token-2022 mints".into());
+    }
+    Ok(mint)
+}
+
+fn create_vault(
+    rpc_client: &RpcClient,
+    options: &TransactionArgs,
+    program_id: &Pubkey,
+    authority: &Keypair,
+    cmd: &CreateVaultCommand
+) -> Result<(), Box<dyn Error>> {
+    if cmd.name.is_empty() || cmd.name.len() > MAX_NAME_LENGTH {
+        return Err(format!("A vault name must be 1 to {} bytes long", MAX_NAME_LENGTH).into());
+    }
+    let address = Vault::address(program_id, &cmd.name);
+    if rpc_client.get_account_with_commitment(&address, rpc_client.commitment())?.value.is_some() {
+        return Err(format!("Vault '{}' already exists at {}", cmd.name, address).into());
+    }
+
+    let instruction = create_vault_instruction(program_id, &authority.pubkey(), &cmd.name)?;
+    let confirmation = send_instructions(rpc_client, options, &[instruction], authority, &[])?;
+    let signature = confirmation.signature;
+    emit(
+        json!({
+            "vault": address.to_string(),
+            "name": cmd.name,
+            "authority": authority.pubkey().to_string(),
+            "signature": signature.to_string(),
+        }),
+        || {
+            println!("Vault '{}' created: {}", cmd.name, address);
+            println!("Signature: {}", signature);
+        }
+    );
+    Ok(())
+}
+
+fn deposit(
+    rpc_client: &RpcClient,
+    options: &TransactionArgs,
+    program_id: &Pubkey,
+    authority: &Keypair,
+    cmd: &VaultTransferCommand
+) -> Result<(), Box<dyn Error>> {
+    let (vault, _) = fetch_vault(rpc_client, program_id, &cmd.name)?;
+    let mint = fetch_mint(rpc_client, &cmd.mint)?;
+    let user_token_account = mint.associated_account(&authority.pubkey());
+    let vault_token_account = mint.associated_account(&vault);
+    let amount = mint.parse_amount_or_all(rpc_client, &cmd.amount, &user_token_account)?;
+
+    let instructions = [
+        mint.create_associated_account(&authority.pubkey(), &vault),
+        vault_transfer_instruction(
+            program_id,
+            "deposit",
+            &vault,
+            &authority.pubkey(),
+            &user_token_account,
+            &vault_token_account,
+            amount
+        )?,
+    ];
+    let confirmation = send_instructions(rpc_client, options, &instructions, authority, &[])?;
+    let signature = confirmation.signature;
+    emit(transfer_json(&vault, &mint, amount, &signature.to_string()), || {
+        println!("Deposited {} tokens into vault '{}'", mint.format_amount(amount), cmd.name);
+        println!("Signature: {}", signature);
+    });
+    Ok(())
+}
+
+fn withdraw(
+    rpc_client: &RpcClient,
+    options: &TransactionArgs,
+    program_id: &Pubkey,
+    authority: &Keypair,
+    cmd: &VaultTransferCommand
+) -> Result<(), Box<dyn Error>> {
+    let (vault, _) = fetch_checked_vault(rpc_client, program_id, &cmd.name)?;
+    let mint = fetch_mint(rpc_client, &cmd.mint)?;
+    let user_address = VaultUser::address(program_id, &vault, &authority.pubkey());
+    let user: VaultUser = fetch_account(rpc_client, program_id, &user_address, "User")?.ok_or_else(
+        || format!("{} has no deposits in vault '{}'", authority.pubkey(), cmd.name)
+    )?;
+    let amount = match Amount::parse(&cmd.amount, mint.decimals, false)? {
+        Amount::Exact(amount) => amount,
+        Amount::All => user.held(),
+    };
+    if amount > user.held() {
+        return Err(
+            format!(
+                "Cannot withdraw {} tokens; only {} are deposited",
+                mint.format_amount(amount),
+                mint.format_amount(user.held())
+            ).into()
+        );
+    }
+
+    let user_token_account = mint.associated_account(&authority.pubkey());
+    let instructions = [
+        mint.create_associated_account(&authority.pubkey(), &authority.pubkey()),
+        vault_transfer_instruction(
+            program_id,
+            "withdraw",
+            &vault,
+            &authority.pubkey(),
+            &user_token_account,
+            &mint.associated_account(&vault),
+            amount
+        )?,
+    ];
+    let confirmation = send_instructions(rpc_client, options, &instructions, authority, &[])?;
+    let signature = confirmation.signature;
+    emit(transfer_json(&vault, &mint, amount, &signature.to_string()), || {
+        println!("Withdrew {} tokens from vault '{}'", mint.format_amount(amount), cmd.name);
+        println!("Signature: {}", signature);
+    });
+    Ok(())
+}
+
+fn transfer_json(vault: &Pubkey, mint: &MintInfo, amount: u64, signature: &str) -> Value {
+    json!({
+        "vault": vault.to_string(),
+        "mint": mint.address.to_string(),
+        "amount": mint.format_amount(amount),
+        "base_units": amount,
+        "signature": signature,
+    })
+}
+
+fn close_vault(
+    rpc_client: &RpcClient,
+    options: &TransactionArgs,
+    program_id: &Pubkey,
+    authority: &Keypair,
+    cmd: &CloseVaultCommand
+) -> Result<(), Box<dyn Error>> {
+    let (address, vault) = fetch_checked_vault(rpc_client, program_id, &cmd.name)?;
+    if vault.authority != authority.pubkey() {
+        return Err(format!("Only the vault authority {} can close it", vault.authority).into());
+    }
+    if vault.held() != 0 {
+        return Err(format!("Vault '{}' still holds {} base units", cmd.name, vault.held()).into());
+    }
+    let mint = fetch_mint(rpc_client, &cmd.mint)?;
+
+    let instruction = close_vault_instruction(
+        program_id,
+        &address,
+        &authority.pubkey(),
+        &mint.associated_account(&address)
+    )?;
+    let confirmation = send_instructions(rpc_client, options, &[instruction], authority, &[])?;
+    let signature = confirmation.signature;
+    emit(json!({ "vault": address.to_string(), "signature": signature.to_string() }), || {
+        println!("Vault '{}' closed; its rent was returned to {}", cmd.name, vault.authority);
+        println!("Signature: {}", signature);
+    });
+    Ok(())
+}
+
+fn show_vault(
+    rpc_client: &RpcClient,
+    program_id: &Pubkey,
+    signer_path: &str,
+    cmd: &ShowVaultCommand
+) -> Result<(), Box<dyn Error>> {
+    let (address, vault) = fetch_vault(rpc_client, program_id, &cmd.name)?;
+    let owner = match &cmd.user {
+        Some(user) => Pubkey::from_str(user)?,
+        None => read_public_key(signer_path)?,
+    };
+    let user_address = VaultUser::address(program_id, &address, &owner);
+    let user: Option<VaultUser> = fetch_account(rpc_client, program_id, &user_address, "User")?;
+
+    emit(
+        json!({
+            "vault": {
+                "address": address.to_string(),
+                "name": vault.name,
+                "authority": vault.authority.to_string(),
+                "bump": vault.bump,
+                "created": vault.ts,
+                "net_deposits": vault.net_deposits.to_string(),
+                "net_withdraws": vault.net_withdraws.to_string(),
+                "held": vault.held().to_string(),
+            },
+            "user": user.as_ref().map(|user| json!({
+                "address": user_address.to_string(),
+                "authority": user.authority.to_string(),
+                "bump": user.bump,
+                "last_activity": user.ts,
+                "net_deposit": user.net_deposit,
+                "net_withdraw": user.net_withdraw,
+                "held": user.held(),
+            })),
+        }),
+        || {
+            println!("Vault '{}': {}", vault.name, address);
+            println!("Authority: {}", vault.authority);
+            println!("Created: {} UTC", format_timestamp(vault.ts));
+            println!("Net deposits: {}", vault.net_deposits);
+            println!("Net withdraws: {}", vault.net_withdraws);
+            println!("Held: {} (base units of the vault's mint)", vault.held());
+            match &user {
+                Some(user) => {
+                    println!("User account of {}: {}", owner, user_address);
+                    println!("    Net deposit: {}", user.net_deposit);
+                    println!("    Net withdraw: {}", user.net_withdraw);
+                    println!("    Last activity: {} UTC", format_timestamp(user.ts));
+                }
+                None => println!("{} has no user account in this vault", owner),
+            }
+        }
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn discriminators_match_the_idl() {
+        let create_vault = [29, 237, 247, 208, 193, 82, 54, 135];
+        assert_eq!(instruction_discriminator("create_vault"), create_vault);
+        assert_eq!(account_discriminator("Vault"), [211, 8, 232, 43, 2, 152, 117, 119]);
+        assert_eq!(account_discriminator("User"), [159, 117, 95, 227, 239, 151, 58, 236]);
+
+        let program_id = Pubkey::new_unique();
+        let accounts = [Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique()];
+        let [vault, authority, token_account] = &accounts;
+        let deposit = vault_transfer_instruction(
+            &program_id,
+            "deposit",
+            vault,
+            authority,
+            token_account,
+            token_account,
+            1_000_000
+        ).unwrap();
+        assert_eq!(deposit.data[..8], [242, 35, 198, 137, 82, 225, 242, 182]);
+        assert_eq!(deposit.data[8..], 1_000_000u64.to_le_bytes());
+        assert!(deposit.accounts[2].is_signer);
+    }
+
+    #[test]
+    fn decodes_vault_accounts() {
+        let vault = Vault {
+            bump: 254,
+            authority: Pubkey::new_unique(),
+            name: "test_vault".to_string(),
+            ts: 1_700_000_000,
+            net_deposits: 1_000_000,
+            net_withdraws: 400_000,
+        };
+        let mut data = account_discriminator("Vault").to_vec();
+        vault.serialize(&mut data).unwrap();
+        data.resize(data.len() + 32, 0);
+
+        assert_eq!(decode_anchor_account::<Vault>("Vault", &data).unwrap(), vault);
+        assert_eq!(vault.held(), 600_000);
+        assert!(decode_anchor_account::<VaultUser>("User", &data).is_err());
+    }
+    #[test]
+    fn withdraw_and_close_check_another_address_than_create() {
+        let program_id = Pubkey::new_unique();
+        let name = "test_vault";
+        let (address, bump) = Pubkey::find_program_address(
+            &[Vault::PREFIX_SEED, name.as_bytes()],
+            &program_id
+        );
+        assert_eq!(Vault::address(&program_id, name), address);
+        let vault = Vault {
+            bump,
+            authority: Pubkey::new_unique(),
+            name: name.to_string(),
+            ts: 0,
+            net_deposits: 0,
+            net_withdraws: 0,
+        };
+        assert_ne!(vault.checked_address(&program_id), Some(address));
+    }
+}
@@ -0,0 +1,189 @@
+use std::{ error::Error, fs, path::{ Path, PathBuf } };
+
+use crate::state::WalletConfig;
+
+pub const DEFAULT_ACCOUNT_FILE: &str = "account.json";
+
+const CONFIG_FILE: &str = ".scw-wallet.json";
+const KEY_EXTENSION: &str = "json";
+
+/// A directory of labelled key files, `<dir>/<label>.json`, plus a default signer.
+pub struct Wallet {
+    dir: PathBuf,
+    account_file: Option<String>,
+    profile_keypair: Option<String>,
+}
+
+impl Wallet {
+    pub fn new(dir: &str, account_file: Option<&str>) -> Self {
+        Self {
+            dir: PathBuf::from(dir),
+            account_file: account_file.map(str::to_string),
+            profile_keypair: None,
+        }
+    }
+
+    /// Signer of the selected configuration profile, a path or label. Used when no
+    /// `--account-file` is given, before the wallet's default label.
+    pub fn with_profile_keypair(mut self, keypair: Option<String>) -> Self {
+        self.profile_keypair = keypair;
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn key_path(&self, label: &str) -> String {
+        self.dir.join(format!("{}.{}", label, KEY_EXTENSION)).to_string_lossy().into_owned()
+    }
+
+    pub fn contains(&self, label: &str) -> bool {
+        validate_label(label).is_ok() && Path::new(&self.key_path(label)).is_file()
+    }
+
+    /// Labels of every key file in the directory, sorted.
+    pub fn labels(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        if !self.dir.is_dir() {
+            return Ok(Vec::new());
+        }
+        let mut labels = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            let is_key_file =
+                path.is_file() && path.extension().is_some_and(|ext| ext == KEY_EXTENSION);
+            let label = path.file_stem().map(|s| s.to_string_lossy().into_owned());
+            if let (true, Some(label)) = (is_key_file, label) {
+                if validate_label(&label).is_ok() {
+                    labels.push(label);
+                }
+            }
+        }
+        labels.sort();
+        Ok(labels)
+    }
+
+    pub fn default_label(&self) -> Result<Option<String>, Box<dyn Error>> {
+        Ok(self.load_config()?.default)
+    }
+
+    pub fn set_default(&self, label: &str) -> Result<(), Box<dyn Error>> {
+        if !self.contains(label) {
+            return Err(format!("No account labelled '{}' in {}", label, self.dir.display()).into());
+        }
+        let mut config = self.load_config()?;
+        config.default = Some(label.to_string());
+        fs::write(self.dir.join(CONFIG_FILE), serde_json::to_string_pretty(&config)?)?;
+        Ok(())
+    }
+
+    /// Resolves a key file path or wallet label to a path. Without one, falls back to
+    /// `--account-file`, the profile's keypair, the wallet's default label, then `account.json`.
+    pub fn resolve(&self, spec: Option<&str>) -> Result<String, Box<dyn Error>> {
+        match spec {
+            Some(spec) if Path::new(spec).is_file() => Ok(spec.to_string()),
+            Some(spec) if self.contains(spec) => Ok(self.key_path(spec)),
+            Some(spec) => Err(format!("No key file or wallet label named '{}'", spec).into()),
+            None => {
+                if let Some(account_file) = &self.account_file {
+                    return self.resolve(Some(account_file));
+                }
+                if let Some(keypair) = &self.profile_keypair {
+                    return self.resolve(Some(keypair));
+                }
+                match self.default_label()? {
+                    Some(label) => Ok(self.key_path(&label)),
+                    None => Ok(DEFAULT_ACCOUNT_FILE.to_string()),
+                }
+            }
+        }
+    }
+
+    /// Where `generate`/`recover` write new keys: a new label, an explicit path, or the account file.
+    /// A wallet label given as `--account-file` names its key file, as in `resolve`.
+    pub fn output_path(
+        &self,
+        file: Option<&str>,
+        label: Option<&str>
+    ) -> Result<String, Box<dyn Error>> {
+        if let Some(label) = label {
+            validate_label(label)?;
+            if self.contains(label) {
+                return Err(format!("Wallet label '{}' already exists", label).into());
+            }
+            fs::create_dir_all(&self.dir)?;
+            return Ok(self.key_path(label));
+        }
+        if let Some(file) = file {
+            return Ok(file.to_string());
+        }
+        match self.account_file.as_deref() {
+            Some(spec) if !Path::new(spec).is_file() && self.contains(spec) => {
+                Ok(self.key_path(spec))
+            }
+            Some(spec) => Ok(spec.to_string()),
+            None => Ok(DEFAULT_ACCOUNT_FILE.to_string()),
+        }
+    }
+
+    fn load_config(&self) -> Result<WalletConfig, Box<dyn Error>> {
+        match fs::read_to_string(self.dir.join(CONFIG_FILE)) {
+            Ok(data) => Ok(serde_json::from_str(&data)?),
+            Err(_) => Ok(WalletConfig::default()),
+        }
+    }
+}
+
+pub fn validate_label(label: &str) -> Result<(), Box<dyn Error>> {
+    let valid =
+        !label.is_empty() &&
+        label.len() <= 64 &&
+        label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        return Err(
+            format!(
+                "Invalid label '{}': use up to 64 letters, digits, '-' or '_'",
+                label
+            ).into()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn resolves_the_signer_in_order_of_precedence() {
+        let dir = env::temp_dir().join(format!("scw-wallet-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let dir = dir.to_string_lossy().into_owned();
+        for label in ["alice", "bob", "carol"] {
+            fs::write(Path::new(&dir).join(format!("{}.json", label)), "{}").unwrap();
+        }
+        let path = |label: &str| Wallet::new(&dir, None).key_path(label);
+
+        let wallet = Wallet::new(&dir, None);
+        assert_eq!(wallet.resolve(None).unwrap(), DEFAULT_ACCOUNT_FILE);
+        wallet.set_default("carol").unwrap();
+        assert_eq!(wallet.resolve(None).unwrap(), path("carol"));
+        let wallet = wallet.with_profile_keypair(Some("bob".to_string()));
+        assert_eq!(wallet.resolve(None).unwrap(), path("bob"));
+        let wallet = Wallet::new(&dir, Some("alice")).with_profile_keypair(Some("bob".to_string()));
+        assert_eq!(wallet.resolve(None).unwrap(), path("alice"));
+        assert_eq!(wallet.resolve(Some("carol")).unwrap(), path("carol"));
+        assert_eq!(Wallet::new(&dir, Some(&path("bob"))).resolve(None).unwrap(), path("bob"));
+        assert!(Wallet::new(&dir, Some("dave")).resolve(None).is_err());
+
+        let output = |account_file: &str| {
+            Wallet::new(&dir, Some(account_file)).output_path(None, None).unwrap()
+        };
+        assert_eq!(output("alice"), path("alice"));
+        assert_eq!(output("out.json"), "out.json");
+        assert_eq!(wallet.output_path(Some("out.json"), None).unwrap(), "out.json");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}